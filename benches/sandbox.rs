@@ -2,13 +2,56 @@
 extern crate criterion;
 
 use criterion::{Benchmark, Criterion};
-use std::time::Duration;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
 use serde_json::Result;
 
 use factorio_blueprint_processor::belt_routing;
+use factorio_blueprint_processor::belt_routing::CostModel;
 use factorio_blueprint_processor::blueprint::*;
 
+/// Keeps track of how much is allocated, so that the benchmarks can report memory use as well.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK_ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    let result = System.alloc(layout);
+    if !result.is_null() {
+      let now = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+      PEAK_ALLOCATED.fetch_max(now, Ordering::Relaxed);
+    }
+    result
+  }
+
+  unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+    System.dealloc(pointer, layout);
+    ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+  }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Runs `run` once, printing how long it took and the most memory it had allocated at once.
+fn report_memory<T>(name: &str, run: impl FnOnce() -> T) -> T {
+  let before = ALLOCATED.load(Ordering::Relaxed);
+  PEAK_ALLOCATED.store(before, Ordering::Relaxed);
+  let start = Instant::now();
+  let result = run();
+  println!(
+    "{}: {:?}, peak {:.1} MiB allocated",
+    name,
+    start.elapsed(),
+    (PEAK_ALLOCATED.load(Ordering::Relaxed) - before) as f64 / (1 << 20) as f64
+  );
+  result
+}
+
 fn bench_belt_routing(criterion: &mut Criterion) {
   criterion.bench(
     "belt_routing_1",
@@ -19,5 +62,20 @@ fn bench_belt_routing(criterion: &mut Criterion) {
   );
 }
 
-criterion_group!(benches, bench_belt_routing);
+fn bench_large_map(criterion: &mut Criterion) {
+  let (map, endpoints) = belt_routing::outpost_scenario(200, 16);
+  let costs = CostModel::default();
+  report_memory("outpost 200×200, 16 belts", || {
+    belt_routing::find_routes(&map, &endpoints, 2, &costs)
+  });
+  criterion.bench(
+    "belt_routing_large",
+    Benchmark::new("outpost_200", move |bencher| {
+      bencher.iter(|| belt_routing::find_routes(&map, &endpoints, 2, &costs))
+    })
+    .sample_size(2),
+  );
+}
+
+criterion_group!(benches, bench_belt_routing, bench_large_map);
 criterion_main!(benches);
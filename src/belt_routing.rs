@@ -7,6 +7,9 @@ use rand::{ChaChaRng, Rng, SeedableRng};
 use arrayvec::ArrayVec;
 use smallvec::SmallVec;
 use std::cmp::{max, min};
+use std::collections::{HashSet, VecDeque};
+
+use serde_derive::{Deserialize, Serialize};
 
 use super::blueprint::*;
 use super::simplified::*;
@@ -22,6 +25,9 @@ impl RoutingMap {
       grid: Grid::new(bounds),
     }
   }
+  pub fn bounds(&self) -> Rectangle {
+    self.grid.bounds()
+  }
 }
 /*
 fn clone_with_room<T: Clone>(source: &[T]) -> Vec<T> {
@@ -47,16 +53,137 @@ impl RoutingMap {
   pub fn obstructed(&self, coordinates: [i32; 2]) -> bool {
     self.grid.get(coordinates).cloned().unwrap_or(true)
   }
+  pub fn set_obstructed(&mut self, coordinates: [i32; 2], obstructed: bool) {
+    if let Some(tile) = self.grid.get_mut(coordinates) {
+      *tile = obstructed;
+    }
+  }
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum RouteDestination {
   Conveyor(DirectedEdge),
   Assembler(Assembler),
+  Chest(Chest),
+  Loader(Loader),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RouteSource {
+  Conveyor(DirectedEdge),
+  Assembler(Assembler),
+  Chest(Chest),
+}
+
+/// Where a route starts, everywhere it has to reach, whether it carries items backwards (from the
+/// destinations to the source), and how it picks the order to connect the destinations in.
+pub type RouteEndpoint = (RouteSource, Vec<RouteDestination>, bool, RouteStrategy);
+
+/// How a route with several destinations decides which one to connect next. Each destination
+/// after the first branches off the route so far, so the order decides the shape of the tree.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteStrategy {
+  /// whichever destination is cheapest to reach from the route so far
+  #[default]
+  Nearest,
+  /// the order that a minimum spanning tree over the source and destinations, grown from the
+  /// source, adds them in; better when destinations are spread out in clusters
+  SpanningTree,
+  /// try both of the above and keep whichever route costs less
+  Cheapest,
+}
+
+/// Tiles apart, counting only the gap between the two rectangles.
+fn rectangle_distance(first: Rectangle, second: Rectangle) -> i32 {
+  let gap = |axis: usize| {
+    max(
+      0,
+      max(
+        second.bounds[axis][0] - first.bounds[axis][1],
+        first.bounds[axis][0] - second.bounds[axis][1],
+      ),
+    )
+  };
+  gap(0) + gap(1)
+}
+
+impl RouteDestination {
+  /// Roughly where the destination is, for ordering destinations.
+  fn area(&self) -> Rectangle {
+    match self {
+      RouteDestination::Conveyor(edge) => Rectangle::singleton(edge.after_coordinates()),
+      RouteDestination::Assembler(assembler) => assembler.shape(),
+      RouteDestination::Chest(chest) => Rectangle::singleton(chest.position),
+      RouteDestination::Loader(loader) => loader.shape(),
+    }
+  }
+}
+
+/// The order that Prim's algorithm adds the destinations to a spanning tree grown from `source`.
+fn spanning_tree_order(source: &RouteSource, destinations: &[RouteDestination]) -> Vec<usize> {
+  let source_area = match source {
+    RouteSource::Conveyor(edge) => Rectangle::singleton(edge.after_coordinates()),
+    RouteSource::Assembler(assembler) => assembler.shape(),
+    RouteSource::Chest(chest) => Rectangle::singleton(chest.position),
+  };
+  let areas: Vec<Rectangle> = destinations.iter().map(RouteDestination::area).collect();
+  let mut distances: Vec<i32> = areas
+    .iter()
+    .map(|&area| rectangle_distance(source_area, area))
+    .collect();
+  let mut result = Vec::new();
+  while result.len() < destinations.len() {
+    let next = (0..destinations.len())
+      .filter(|index| !result.contains(index))
+      .min_by_key(|&index| distances[index])
+      .unwrap();
+    result.push(next);
+    for index in 0..destinations.len() {
+      distances[index] = min(
+        distances[index],
+        rectangle_distance(areas[next], areas[index]),
+      );
+    }
+  }
+  result
+}
+
+/// What the objects would cost to build under `costs`, ignoring congestion.
+pub fn objects_cost(objects: &[Object], costs: &CostModel) -> usize {
+  objects
+    .iter()
+    .map(|object| match object {
+      Object::Belt(_) => costs.belt,
+      Object::UndergroundBelt(underground_belt) => {
+        costs.underground_belt[underground_belt.level() as usize - 1]
+      }
+      Object::Splitter(_) => costs.splitter,
+      Object::Inserter(inserter) => costs.inserter[inserter.length() as usize - 1],
+      Object::Chest(_) => costs.chest,
+      Object::Assembler(_) | Object::Loader(_) => 0,
+    })
+    .sum()
+}
+
+impl RouteSource {
+  fn outputs(&self) -> Vec<RouteOutput> {
+    match self {
+      RouteSource::Conveyor(edge) => vec![RouteOutput::Conveyor(*edge)],
+      RouteSource::Assembler(assembler) => assembler
+        .shape()
+        .tiles()
+        .map(RouteOutput::InsertFrom)
+        .collect(),
+      RouteSource::Chest(chest) => vec![RouteOutput::InsertFrom(chest.position)],
+    }
+  }
 }
 
 impl RouteOutput {
-  pub fn satisfies(&self, destination: &RouteDestination) -> bool {
+  /// Whether reaching this output reaches `destination`, in a route that's searching
+  /// `backwards` or not.
+  pub fn satisfies(&self, destination: &RouteDestination, backwards: bool) -> bool {
     match (self, destination) {
       (RouteOutput::Conveyor(output), RouteDestination::Conveyor(destination)) => {
         output == destination
@@ -64,27 +191,163 @@ impl RouteOutput {
       (RouteOutput::InsertInto(output), RouteDestination::Assembler(destination)) => {
         destination.shape().contains(*output)
       }
+      (RouteOutput::InsertInto(output), RouteDestination::Chest(destination)) => {
+        destination.position == *output
+      }
+      // a loading loader is reached by conveying into it, which only a forward route can do;
+      // an unloading loader is where a backwards route's items come from, so it's reached by
+      // conveying out of it
+      (RouteOutput::Conveyor(output), RouteDestination::Loader(destination)) => {
+        if backwards {
+          destination.output().map(|edge| edge.reversed()) == Some(*output)
+        } else {
+          destination.input() == Some(*output)
+        }
+      }
       _ => false,
     }
   }
 }
 
+/// The A* edge weights of each kind of object the router can place.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CostModel {
+  pub belt: usize,
+  /// indexed by level - 1; longer undergrounds need faster (more expensive) levels
+  pub underground_belt: [usize; 3],
+  pub splitter: usize,
+  /// indexed by length - 1; pretty expensive because they have an actual power cost
+  pub inserter: [usize; 2],
+  pub chest: usize,
+  /// the kind of chest inserters may drop into for other inserters to pick up from; with
+  /// `None`, inserters only drop straight into a destination, so there are no chains
+  pub chain_chest: Option<ChestType>,
+}
+
+impl Default for CostModel {
+  fn default() -> Self {
+    CostModel {
+      belt: 1,
+      underground_belt: [7, 20, 70],
+      splitter: 4,
+      inserter: [5, 9],
+      chest: 4,
+      chain_chest: None,
+    }
+  }
+}
+
+impl CostModel {
+  /// Checks that nothing costs less than the tiles it carries items across. The search
+  /// estimates the cost left as one per tile, and goes wrong if anything beats that.
+  pub fn check(&self) -> Result<(), String> {
+    let too_cheap = |what: &str, cost: usize, tiles: usize| {
+      if cost < tiles {
+        Err(format!(
+          "{} cost {}, less than the {} tiles they cross",
+          what, cost, tiles
+        ))
+      } else {
+        Ok(())
+      }
+    };
+    too_cheap("belts", self.belt, 1)?;
+    too_cheap("splitters", self.splitter, 1)?;
+    for length in 2..=10 {
+      let level = UndergroundBelt::min_level(length);
+      too_cheap(
+        &format!("level {} underground belts", level),
+        self.underground_belt[level as usize - 1],
+        length as usize,
+      )?;
+    }
+    for length in 1..=2 {
+      too_cheap(
+        &format!("length {} inserters", length),
+        self.inserter[length - 1],
+        length * 2,
+      )?;
+    }
+    Ok(())
+  }
+}
+
 pub struct RouteSearchParameters<'a> {
   pub map: &'a RoutingMap,
   //pub previous_version: Option <& 'a Route>,
   pub search_map: &'a mut RouteSearchMap,
+  pub costs: &'a CostModel,
   pub overlap_penalty: usize,
   pub max_cost: usize,
-  pub source: DirectedEdge,
+  pub source: RouteSource,
   pub destinations: &'a [RouteDestination],
   pub backwards: bool,
+  pub strategy: RouteStrategy,
+}
+/// Outputs waiting to be explored, in buckets by score. Scores never go below the bucket being
+/// explored, so only the buckets from there up to the highest score waiting are kept, and the
+/// memory used follows the range of live scores rather than `max_cost`.
+#[derive(Default)]
+struct FrontierQueue {
+  /// `buckets[index]` holds the outputs with score `first + index`
+  buckets: VecDeque<Vec<RouteOutput>>,
+  first: usize,
+  waiting: usize,
+  /// outputs from the buckets already explored, whose route heads still need clearing
+  explored: Vec<RouteOutput>,
+}
+
+impl FrontierQueue {
+  fn push(&mut self, score: usize, output: RouteOutput) {
+    let index = score - self.first;
+    if index >= self.buckets.len() {
+      self.buckets.resize_with(index + 1, Vec::new);
+    }
+    self.buckets[index].push(output);
+    self.waiting += 1;
+  }
+
+  fn get(&self, score: usize, index: usize) -> Option<&RouteOutput> {
+    self.buckets.get(score.checked_sub(self.first)?)?.get(index)
+  }
+
+  /// Forgets the buckets below `score`, keeping their allocations for higher scores.
+  fn skip_to(&mut self, score: usize) {
+    while self.first < score {
+      if let Some(mut bucket) = self.buckets.pop_front() {
+        self.waiting -= bucket.len();
+        self.explored.append(&mut bucket);
+        self.buckets.push_back(bucket);
+      }
+      self.first += 1;
+    }
+  }
+
+  fn is_empty(&self) -> bool {
+    self.waiting == 0
+  }
+
+  /// Empties the queue, ready to start again from score 0, returning everything that was ever
+  /// queued since the last time.
+  fn drain(&mut self) -> impl Iterator<Item = RouteOutput> + '_ {
+    self.first = 0;
+    self.waiting = 0;
+    self
+      .explored
+      .drain(..)
+      .chain(self.buckets.iter_mut().flat_map(|bucket| bucket.drain(..)))
+  }
 }
+
 pub struct RouteSearch<'a> {
   parameters: RouteSearchParameters<'a>,
   committed_objects: Vec<Object>,
-  frontiers: Vec<Vec<RouteOutput>>,
+  frontiers: FrontierQueue,
   current_frontier: usize,
   destinations_satisfied: Vec<bool>,
+  /// for `RouteStrategy::SpanningTree`, the order to connect the destinations in
+  destination_order: Option<Vec<usize>>,
   failed: bool,
 }
 
@@ -118,7 +381,7 @@ struct RouteSearchMapTile {
   obstructed: bool,
   solid_objects_here: u8,
   accumulated_solid_presence_cost: u16,
-  heuristic: u8,
+  heuristic: u16,
   this_search_objects: Vec<Object>,
   penalized_objects: Vec<(Object, usize)>,
   underground: [RouteSearchMapUnderground; 2],
@@ -163,6 +426,58 @@ impl Default for MaybeRouteHead {
 }
 
 impl RouteSearchMap {
+  /// A fresh search map for routing `endpoints` on `map`. Conveyors may only leave the map
+  /// where one of the endpoints wants them to.
+  pub fn new(map: &RoutingMap, endpoints: &[RouteEndpoint]) -> RouteSearchMap {
+    let mut search_map = RouteSearchMap {
+      grid: Grid::new(map.grid.bounds().outset(1)),
+    };
+    search_map.mark_exits(map, endpoints);
+    search_map
+  }
+
+  /// Forbids conveyors from leaving the map except where one of the endpoints wants them to.
+  fn mark_exits(&mut self, map: &RoutingMap, endpoints: &[RouteEndpoint]) {
+    for (coordinates, tile) in self.grid.tiles_mut() {
+      for direction in 0..4 {
+        let output = DirectedEdge::from_before(coordinates, direction as u8 * 2);
+        if !map.out_of_bounds(output.after_coordinates()) {
+          continue;
+        }
+        let wanted = endpoints.iter().any(|(_, destinations, _, _)| {
+          destinations.contains(&RouteDestination::Conveyor(output))
+        });
+        tile.edges[direction].best_route_conveying_here = if wanted {
+          MaybeRouteHead::Available
+        } else {
+          MaybeRouteHead::Forbidden
+        };
+      }
+    }
+  }
+
+  pub fn bounds(&self) -> Rectangle {
+    self.grid.bounds()
+  }
+
+  /// The congestion history of a tile: how much the negotiated routing has learned to avoid
+  /// putting things there, summed over solid objects, conveyor edges and undergrounds.
+  pub fn accumulated_cost(&self, coordinates: Coordinates) -> Option<usize> {
+    self.get_tile(coordinates).map(|tile| {
+      tile.accumulated_solid_presence_cost as usize
+        + tile
+          .edges
+          .iter()
+          .map(|edge| edge.accumulated_conveyor_locking_cost as usize)
+          .sum::<usize>()
+        + tile
+          .underground
+          .iter()
+          .map(|underground| underground.accumulated_belt_cost as usize)
+          .sum::<usize>()
+    })
+  }
+
   fn get_tile(&self, coordinates: Coordinates) -> Option<&RouteSearchMapTile> {
     self.grid.get(coordinates)
   }
@@ -223,7 +538,7 @@ impl RouteSearchMap {
           .filter(|object| object.overlaps_solid_tile(coordinates))
           .count();
       result += objects_here as usize * overlap_penalty;
-      for (penalized, penalty) in & tile.penalized_objects {
+      for (penalized, penalty) in &tile.penalized_objects {
         if penalized == &object.clone().into_object() {
           result += penalty;
         }
@@ -292,11 +607,131 @@ impl RouteSearchMap {
   }
 }
 
+impl RouteSearchMap {
+  /// Puts an object of the route being searched on the map, raising the accumulated costs
+  /// wherever something else is already in the way.
+  fn occupy_object(&mut self, object: &Object, overlap_penalty: usize) {
+    for coordinates in object.solid_tiles() {
+      let tile = self.get_tile_mut(coordinates).unwrap();
+      increase_accumulated_cost(
+        &mut tile.accumulated_solid_presence_cost,
+        tile.solid_objects_here,
+        overlap_penalty,
+      );
+      tile.solid_objects_here += 1;
+      for object in &tile.this_search_objects {
+        tile.penalized_objects.push((object.clone(), 15));
+      }
+      tile.this_search_objects.push(object.clone());
+    }
+    for edge in object.conveyor_outputs() {
+      let edge = self.get_edge_mut(edge).unwrap();
+      increase_accumulated_cost(
+        &mut edge.accumulated_conveyor_locking_cost,
+        edge.conveyors_locking_material,
+        overlap_penalty,
+      );
+      // do not increment tile.conveyors_locking_material yet because a route doesn't limit its OWN conveyors
+    }
+    for edge in object.conveyor_inputs() {
+      let edge = self.get_edge_mut(edge).unwrap();
+      increase_accumulated_cost(
+        &mut edge.accumulated_conveyor_locking_cost,
+        edge.conveyors_locking_material,
+        overlap_penalty,
+      );
+      // do not increment tile.conveyors_locking_material yet because a route doesn't limit its OWN conveyors
+    }
+    if let Object::UndergroundBelt(underground_belt) = object.clone() {
+      let index = underground_belt.horizontal() as usize;
+      for coordinates in underground_belt.underground_tiles() {
+        let tile = self.get_tile_mut(coordinates).unwrap();
+        increase_accumulated_cost(
+          &mut tile.underground[index].accumulated_belt_cost,
+          tile.underground[index].belts_here,
+          overlap_penalty,
+        );
+        tile.underground[index].belts_here += 1;
+      }
+    }
+  }
+
+  /// Once a route's search is over, its conveyors compete with everyone else's.
+  fn settle_object(&mut self, object: &Object) {
+    for coordinates in object.solid_tiles() {
+      let tile = self.get_tile_mut(coordinates).unwrap();
+      tile.this_search_objects.remove(
+        tile
+          .this_search_objects
+          .iter()
+          .position(|existing| existing == object)
+          .unwrap(),
+      );
+    }
+
+    for edge in object.conveyor_outputs() {
+      let edge = self.get_edge_mut(edge).unwrap();
+      edge.conveyors_locking_material += 1;
+    }
+    for edge in object.conveyor_inputs() {
+      let edge = self.get_edge_mut(edge).unwrap();
+      edge.conveyors_locking_material += 1;
+    }
+  }
+
+  /// Whether any of `objects` would share a tile, a conveyor edge or an underground stretch with
+  /// what's already on the map.
+  fn collides(&self, objects: &[Object]) -> bool {
+    objects.iter().any(|object| {
+      object
+        .solid_tiles()
+        .into_iter()
+        .any(|coordinates| self.get_tile(coordinates).unwrap().solid_objects_here > 0)
+        || object
+          .conveyor_outputs()
+          .into_iter()
+          .chain(object.conveyor_inputs())
+          .any(|edge| self.get_edge(edge).unwrap().conveyors_locking_material > 0)
+        || match object {
+          Object::UndergroundBelt(underground_belt) => {
+            let index = underground_belt.horizontal() as usize;
+            underground_belt.underground_tiles().any(|coordinates| {
+              self.get_tile(coordinates).unwrap().underground[index].belts_here > 0
+            })
+          }
+          _ => false,
+        }
+    })
+  }
+
+  /// Whether any two routes on the map share a tile or an underground stretch.
+  fn overlapping(&self) -> bool {
+    self.grid.tiles().any(|(_, tile)| {
+      tile.solid_objects_here > 1
+        || tile
+          .underground
+          .iter()
+          .any(|underground| underground.belts_here > 1)
+    })
+  }
+
+  /// Puts a whole route found elsewhere on the map, as if it had just been searched here.
+  fn place_route(&mut self, objects: &[Object], overlap_penalty: usize) {
+    for object in objects {
+      self.occupy_object(object, overlap_penalty);
+    }
+    for object in objects {
+      self.settle_object(object);
+    }
+  }
+}
+
 fn increase_accumulated_cost(cost: &mut u16, competitors: u8, penalty: usize) {
   if competitors > 0 {
     // to use this, we already paid *cost + competitors*penalty
     // let's increase exponentially from that, but not be ridiculous
-    *cost += min(50, (competitors as u16 * penalty as u16 + *cost) >> 1) + 1;
+    let increase = min(50, (competitors as usize * penalty + *cost as usize) >> 1) + 1;
+    *cost = cost.saturating_add(increase as u16);
     //eprintln!(" {:?} ", cost) ;
   }
 }
@@ -347,63 +782,16 @@ impl<'a> RouteSearch<'a> {
         iterator = head.previous.clone();
       }
     }
-    
+
     new_committed_objects.reverse();
 
     for object in &new_committed_objects {
-        for coordinates in object.solid_tiles() {
-          let tile = self
-            .parameters
-            .search_map
-            .get_tile_mut(coordinates)
-            .unwrap();
-          increase_accumulated_cost(
-            &mut tile.accumulated_solid_presence_cost,
-            tile.solid_objects_here,
-            self.parameters.overlap_penalty,
-          );
-          tile.solid_objects_here += 1;
-          for object in &tile.this_search_objects {
-            tile.penalized_objects.push((object.clone(), 15));
-          }
-          tile.this_search_objects.push(object.clone());
-        }
-        for edge in object.conveyor_outputs() {
-          let edge = self.parameters.search_map.get_edge_mut(edge).unwrap();
-          increase_accumulated_cost(
-            &mut edge.accumulated_conveyor_locking_cost,
-            edge.conveyors_locking_material,
-            self.parameters.overlap_penalty,
-          );
-          // do not increment tile.conveyors_locking_material yet because a route doesn't limit its OWN conveyors
-        }
-        for edge in object.conveyor_inputs() {
-          let edge = self.parameters.search_map.get_edge_mut(edge).unwrap();
-          increase_accumulated_cost(
-            &mut edge.accumulated_conveyor_locking_cost,
-            edge.conveyors_locking_material,
-            self.parameters.overlap_penalty,
-          );
-          // do not increment tile.conveyors_locking_material yet because a route doesn't limit its OWN conveyors
-        }
-        if let Object::UndergroundBelt(underground_belt) = object.clone() {
-          let index = underground_belt.horizontal() as usize;
-          for coordinates in underground_belt.underground_tiles() {
-            let tile = self
-              .parameters
-              .search_map
-              .get_tile_mut(coordinates)
-              .unwrap();
-            increase_accumulated_cost(
-              &mut tile.underground[index].accumulated_belt_cost,
-              tile.underground[index].belts_here,
-              self.parameters.overlap_penalty,
-            );
-            tile.underground[index].belts_here += 1;
-          }
-        }
+      self
+        .parameters
+        .search_map
+        .occupy_object(object, self.parameters.overlap_penalty);
     }
-    
+
     self.committed_objects.extend(new_committed_objects);
 
     if self.finished() {
@@ -417,29 +805,7 @@ impl<'a> RouteSearch<'a> {
     self.clear_frontiers();
 
     for object in &self.committed_objects {
-      for coordinates in object.solid_tiles() {
-        let tile = self
-          .parameters
-          .search_map
-          .get_tile_mut(coordinates)
-          .unwrap();
-        tile.this_search_objects.remove(
-          tile
-            .this_search_objects
-            .iter()
-            .position(|existing| existing == object)
-            .unwrap(),
-        );
-      }
-
-      for edge in object.conveyor_outputs() {
-        let edge = self.parameters.search_map.get_edge_mut(edge).unwrap();
-        edge.conveyors_locking_material += 1;
-      }
-      for edge in object.conveyor_inputs() {
-        let edge = self.parameters.search_map.get_edge_mut(edge).unwrap();
-        edge.conveyors_locking_material += 1;
-      }
+      self.parameters.search_map.settle_object(object);
     }
 
     let solved = self.destinations_satisfied.iter().filter(|a| **a).count();
@@ -502,7 +868,7 @@ impl<'a> RouteSearch<'a> {
       Some(tile) => tile.heuristic as usize,
     };
     match output {
-      RouteOutput::InsertInto(_) => {
+      RouteOutput::InsertInto(_) if self.parameters.costs.chain_chest.is_none() => {
         if heuristic == 0 {
           0
         } else {
@@ -547,7 +913,7 @@ impl<'a> RouteSearch<'a> {
     };
     if better {
       *entry = route;
-      self.frontiers[score].push(output.clone());
+      self.frontiers.push(score, output.clone());
     }
   }
 
@@ -617,14 +983,12 @@ impl<'a> RouteSearch<'a> {
   }
 
   fn clear_frontiers(&mut self) {
-    for frontier in &mut self.frontiers {
-      for output in frontier.drain(..) {
-        *self
-          .parameters
-          .search_map
-          .get_route_head_mut(&output)
-          .unwrap() = MaybeRouteHead::Available;
-      }
+    for output in self.frontiers.drain() {
+      *self
+        .parameters
+        .search_map
+        .get_route_head_mut(&output)
+        .unwrap() = MaybeRouteHead::Available;
     }
   }
 
@@ -637,20 +1001,29 @@ impl<'a> RouteSearch<'a> {
     for (_coordinates, tile) in self.parameters.search_map.grid.tiles_mut() {
       //tile.best_route_inserting_here = MaybeRouteHead::Available;
       //tile.best_route_insertable_from_here = MaybeRouteHead::Available;
-      tile.heuristic = u8::max_value();
+      tile.heuristic = u16::MAX;
       //for edge in &mut tile.edges {
       //  edge.best_route_conveying_here = MaybeRouteHead::Available;
       //}
     }
 
+    // with a fixed order, head for the next destination in it (though reaching a different one
+    // on the way still counts)
+    let target = self.destination_order.as_ref().and_then(|order| {
+      order
+        .iter()
+        .cloned()
+        .find(|&index| !self.destinations_satisfied[index])
+    });
     let mut next_heuristic_frontier = Vec::new();
-    for (destination, satisfied) in self
+    for (index, (destination, satisfied)) in self
       .parameters
       .destinations
       .iter()
       .zip(&self.destinations_satisfied)
+      .enumerate()
     {
-      if *satisfied {
+      if *satisfied || target.is_some_and(|target| target != index) {
         continue;
       }
       match destination {
@@ -672,6 +1045,18 @@ impl<'a> RouteSearch<'a> {
             }
           }
         }
+        RouteDestination::Chest(destination) => {
+          next_heuristic_frontier.push(destination.position);
+          for &direction in &[0, 2, 4, 6] {
+            next_heuristic_frontier.push(further_coordinates(destination.position, direction, 2));
+          }
+        }
+        RouteDestination::Loader(destination) => {
+          for edge in destination.input().into_iter().chain(destination.output()) {
+            next_heuristic_frontier.push(edge.before_coordinates());
+            next_heuristic_frontier.push(edge.after_coordinates());
+          }
+        }
       }
     }
 
@@ -682,8 +1067,8 @@ impl<'a> RouteSearch<'a> {
       }
       for coordinates in frontier {
         if let Some(tile) = self.parameters.search_map.get_tile_mut(coordinates) {
-          if tile.heuristic > which_frontier as u8 {
-            tile.heuristic = which_frontier as u8;
+          if (tile.heuristic as usize) > which_frontier {
+            tile.heuristic = which_frontier as u16;
             for &direction in &[0, 2, 4, 6] {
               next_heuristic_frontier.push(next_coordinates(coordinates, direction));
             }
@@ -693,10 +1078,9 @@ impl<'a> RouteSearch<'a> {
     }
     //eprintln!(" {:?} ", self.parameters.search_map.grid.tiles().map(|t|t.1.heuristic).collect::<Vec<_>>());
 
-    self.insert_route(
-      &RouteOutput::Conveyor(self.parameters.source.clone()),
-      MaybeRouteHead::Source,
-    );
+    for output in self.parameters.source.outputs() {
+      self.insert_route(&output, MaybeRouteHead::Source);
+    }
     for object in &self.committed_objects.clone() {
       for output in self.object_outputs(object) {
         self.insert_route(&output, MaybeRouteHead::Source);
@@ -709,6 +1093,9 @@ impl<'a> RouteSearch<'a> {
             MaybeRouteHead::Source,
           );
         }
+        if let Object::Belt(belt) = object {
+          self.add_merging_splitters(belt);
+        }
       } else {
         match object {
           Object::Belt(belt) => {
@@ -751,8 +1138,10 @@ impl<'a> RouteSearch<'a> {
                 }
               }
             }
-            if self.parameters.source.after_coordinates() == belt.position() {
-              input_directions.push(self.parameters.source.direction())
+            if let RouteSource::Conveyor(source) = self.parameters.source {
+              if source.after_coordinates() == belt.position() {
+                input_directions.push(source.direction())
+              }
             }
             if self
               .parameters
@@ -836,7 +1225,7 @@ impl<'a> RouteSearch<'a> {
                   RouteOutput::Conveyor(source),
                   &MaybeRouteHead::Source,
                   splitter.clone(),
-                  4,
+                  self.parameters.costs.splitter,
                   replace,
                 );
               }
@@ -848,13 +1237,101 @@ impl<'a> RouteSearch<'a> {
     }
   }
 
+  /// The directions that items come onto `belt` from, along the conveyors of this route or from
+  /// outside the map.
+  fn feeding_directions(&self, belt: &Belt) -> SmallVec<[u8; 3]> {
+    let mut result = SmallVec::new();
+    for &input in belt.conveyor_inputs().as_ref() {
+      let from_outside = self
+        .parameters
+        .destinations
+        .contains(&RouteDestination::Conveyor(input.reversed()));
+      if from_outside
+        || self
+          .parameters
+          .search_map
+          .get_tile(input.before_coordinates())
+          .unwrap()
+          .this_search_objects
+          .iter()
+          .any(|other| other.conveyor_outputs().as_ref().contains(&input))
+      {
+        result.push(input.direction());
+      }
+    }
+    result
+  }
+
+  /// Lets a backwards route merge another branch into `belt` through a splitter, the mirror
+  /// image of the splitters that forward routes branch with. (Side-loading needs nothing
+  /// special: the sides of a committed belt are already places to continue from.)
+  fn add_merging_splitters(&mut self, belt: &Belt) {
+    let direction = belt.direction();
+    let feeding = self.feeding_directions(belt);
+    let mut splitters = Vec::new();
+    if feeding.iter().all(|&from| from == direction) {
+      let replace = vec![Object::Belt(belt.clone())];
+      splitters.push((
+        Splitter::from_right(belt.position(), direction),
+        replace.clone(),
+      ));
+      splitters.push((Splitter::from_left(belt.position(), direction), replace));
+    } else if let [from] = feeding[..] {
+      // the belt turns onto this one from the side; if it had just turned off a belt going our
+      // way, a splitter across both tiles can take the place of the jog
+      let input = DirectedEdge::from_after(belt.position(), from);
+      let feeders: SmallVec<[Belt; 2]> = self
+        .parameters
+        .search_map
+        .get_tile(input.before_coordinates())
+        .unwrap()
+        .this_search_objects
+        .iter()
+        .filter_map(|other| match other {
+          Object::Belt(feeder) if feeder.output() == input => Some(feeder.clone()),
+          _ => None,
+        })
+        .collect();
+      if !feeders.is_empty()
+        && feeders.iter().all(|feeder| {
+          self
+            .feeding_directions(feeder)
+            .iter()
+            .all(|&from| from == direction)
+        })
+      {
+        let replace = std::iter::once(belt.clone())
+          .chain(feeders)
+          .map(Object::Belt)
+          .collect();
+        if from == (direction + 2) % 8 {
+          splitters.push((Splitter::from_right(belt.position(), direction), replace));
+        } else if from == (direction + 6) % 8 {
+          splitters.push((Splitter::from_left(belt.position(), direction), replace));
+        } else {
+          unreachable!()
+        }
+      }
+    }
+
+    for (splitter, replace) in splitters {
+      self.add_object(
+        RouteOutput::Conveyor(belt.output().reversed()),
+        &MaybeRouteHead::Source,
+        splitter,
+        self.parameters.costs.splitter,
+        replace,
+      );
+    }
+  }
+
   fn search_step(&mut self) {
     //while self.frontiers[self.current_frontier].len() > 0 {
     //eprintln!(" cost: {:?} ", self.current_frontier) ;
     //let frontier = std::mem::replace(&mut self.frontiers[self.current_frontier], Vec::new());
     //for output in frontier {
     for index in 0.. {
-      let output = match self.frontiers[self.current_frontier].get(index) {
+      let output = match self.frontiers.get(self.current_frontier, index) {
         Some(output) => output.clone(),
         None => break,
       };
@@ -862,7 +1339,10 @@ impl<'a> RouteSearch<'a> {
       let mut satisfied_any = false;
       for index in 0..self.parameters.destinations.len() {
         if !self.destinations_satisfied[index]
-          && output.satisfies(&self.parameters.destinations[index])
+          && output.satisfies(
+            &self.parameters.destinations[index],
+            self.parameters.backwards,
+          )
         {
           self.destinations_satisfied[index] = true;
           satisfied_any = true;
@@ -892,12 +1372,24 @@ impl<'a> RouteSearch<'a> {
           let coordinates = conveyor_output.after_coordinates();
           if self.parameters.backwards {
             let belt = Belt::new(coordinates, (conveyor_output.direction() + 4) % 8);
-            self.add_object(output.clone(), &head, belt, 1, Vec::new());
+            self.add_object(
+              output.clone(),
+              &head,
+              belt,
+              self.parameters.costs.belt,
+              Vec::new(),
+            );
           } else {
             for direction in &[0, 2, 6] {
               let direction = (direction + conveyor_output.direction()) % 8;
               let belt = Belt::new(coordinates, direction);
-              self.add_object(output.clone(), &head, belt, 1, Vec::new());
+              self.add_object(
+                output.clone(),
+                &head,
+                belt,
+                self.parameters.costs.belt,
+                Vec::new(),
+              );
             }
           }
           for distance in 2..=10 {
@@ -907,12 +1399,7 @@ impl<'a> RouteSearch<'a> {
               UndergroundBelt::from_input(conveyor_output, distance as u8)
             };
 
-            let cost = match underground.level() {
-              1 => 7,
-              2 => 20,
-              3 => 70,
-              _ => unreachable!(),
-            };
+            let cost = self.parameters.costs.underground_belt[underground.level() as usize - 1];
             self.add_object(output.clone(), &head, underground.clone(), cost, Vec::new());
           }
         }
@@ -929,18 +1416,34 @@ impl<'a> RouteSearch<'a> {
                 length as u8,
               );
               //eprintln!(" {:?} ", inserter);
-              let cost = 1 + length as usize * 4; //pretty expensive because they have an actual power cost;
+              let cost = self.parameters.costs.inserter[length as usize - 1];
               self.add_object(output.clone(), &head, inserter, cost, Vec::new());
             }
           }
         }
-        RouteOutput::InsertInto(_output) => (), //eprintln!(" {:?} ", output), //(),//no way to extend from inserters at this time
+        RouteOutput::InsertInto(insert_coordinates) => {
+          // put a chest where the inserter drops, so that another inserter can take from it;
+          // if we reached a destination this way, we already returned above
+          let chest_type = match self.parameters.costs.chain_chest {
+            Some(chest_type) => chest_type,
+            None => continue,
+          };
+          let chest = Chest::new(insert_coordinates, chest_type);
+          self.add_object(
+            output.clone(),
+            &head,
+            chest,
+            self.parameters.costs.chest,
+            Vec::new(),
+          );
+        }
       }
     }
     //}
 
     self.current_frontier += 1;
-    if self.current_frontier >= self.parameters.max_cost {
+    self.frontiers.skip_to(self.current_frontier);
+    if self.current_frontier >= self.parameters.max_cost || self.frontiers.is_empty() {
       self.failed = true;
       self.finish();
     }
@@ -948,13 +1451,20 @@ impl<'a> RouteSearch<'a> {
 
   fn new(parameters: RouteSearchParameters) -> RouteSearch {
     let mut search = RouteSearch {
-      frontiers: (0..parameters.max_cost).map(|_| Vec::new()).collect(),
+      frontiers: FrontierQueue::default(),
       //routes: Default::default(),
       current_frontier: Default::default(),
       failed: false,
       committed_objects: Vec::new(),
       //self_so_far: Route::new (RouteOutput::Conveyor (parameters.source.clone())),
       destinations_satisfied: (0..parameters.destinations.len()).map(|_| false).collect(),
+      destination_order: match parameters.strategy {
+        RouteStrategy::SpanningTree => Some(spanning_tree_order(
+          &parameters.source,
+          parameters.destinations,
+        )),
+        _ => None,
+      },
       parameters,
     };
     search.start_next_destination();
@@ -963,6 +1473,9 @@ impl<'a> RouteSearch<'a> {
 }
 
 pub fn find_route(parameters: RouteSearchParameters) -> (Vec<Object>, bool) {
+  if parameters.strategy == RouteStrategy::Cheapest {
+    return find_cheapest_route(parameters);
+  }
   let mut search = RouteSearch::new(parameters);
 
   while !(search.finished() || search.failed) {
@@ -972,41 +1485,96 @@ pub fn find_route(parameters: RouteSearchParameters) -> (Vec<Object>, bool) {
   (search.committed_objects, !search.failed)
 }
 
+/// Searches with each of the other strategies on copies of the search map, then puts the better
+/// route (one that reaches everything, and then the cheaper one) on the real map.
+fn find_cheapest_route(parameters: RouteSearchParameters) -> (Vec<Object>, bool) {
+  let RouteSearchParameters {
+    map,
+    search_map,
+    costs,
+    overlap_penalty,
+    max_cost,
+    source,
+    destinations,
+    backwards,
+    strategy: _,
+  } = parameters;
+  let candidates = [RouteStrategy::Nearest, RouteStrategy::SpanningTree]
+    .iter()
+    .map(|&strategy| {
+      find_route(RouteSearchParameters {
+        map,
+        search_map: &mut search_map.clone(),
+        costs,
+        overlap_penalty,
+        max_cost,
+        source: source.clone(),
+        destinations,
+        backwards,
+        strategy,
+      })
+    });
+  let best = candidates
+    .min_by_key(|(objects, succeeded)| (!succeeded, objects_cost(objects, costs)))
+    .unwrap();
+  search_map.place_route(&best.0, overlap_penalty);
+  best
+}
+
 pub fn find_routes(
   map: &RoutingMap,
-  endpoints: &[(DirectedEdge, Vec<RouteDestination>, bool)],
+  endpoints: &[RouteEndpoint],
   iterations: usize,
+  costs: &CostModel,
+) -> Vec<Vec<Object>> {
+  let mut search_map = RouteSearchMap::new(map, endpoints);
+  find_routes_in(map, &mut search_map, endpoints, iterations, costs)
+}
+
+/// Like `find_routes`, but leaves the congestion history in `search_map` for inspection.
+pub fn find_routes_in(
+  map: &RoutingMap,
+  search_map: &mut RouteSearchMap,
+  endpoints: &[RouteEndpoint],
+  iterations: usize,
+  costs: &CostModel,
 ) -> Vec<Vec<Object>> {
   let mut current_routes: Vec<(Vec<Object>, bool)> =
     endpoints.iter().map(|_| (Vec::new(), false)).collect();
-  let iterations = endpoints.len() * iterations;
+  let all: Vec<usize> = (0..endpoints.len()).collect();
+  negotiate_routes(
+    map,
+    search_map,
+    endpoints,
+    &all,
+    &mut current_routes,
+    iterations,
+    costs,
+  )
+}
+
+/// Reroutes the endpoints in `rerouting` in turn, `iterations` times each, while the others stay
+/// where `current_routes` has them. Returns all of the routes after each step.
+fn negotiate_routes(
+  map: &RoutingMap,
+  search_map: &mut RouteSearchMap,
+  endpoints: &[RouteEndpoint],
+  rerouting: &[usize],
+  current_routes: &mut [(Vec<Object>, bool)],
+  iterations: usize,
+  costs: &CostModel,
+) -> Vec<Vec<Object>> {
+  let iterations = rerouting.len() * iterations;
   let max_cost = 6000;
-  let mut search_map = RouteSearchMap {
-    grid: Grid::new(map.grid.bounds().outset(1)),
-  };
-  for (coordinates, tile) in search_map.grid.tiles_mut() {
-    for direction in 0..4 {
-      let output = DirectedEdge::from_before(coordinates, direction as u8 * 2);
-      if map.out_of_bounds(output.after_coordinates())
-        && endpoints.iter().all(|(_, destinations, _)| {
-          destinations
-            .iter()
-            .all(|destination| *destination != RouteDestination::Conveyor(output))
-        })
-      {
-        tile.edges[direction].best_route_conveying_here = MaybeRouteHead::Forbidden;
-      }
-    }
-  }
   let mut result = Vec::new();
 
   for iteration in 0..iterations {
-    let which = iteration % endpoints.len();
+    let which = rerouting[iteration % rerouting.len()];
 
-    let penalty = if iteration >= iterations - endpoints.len() {
+    let penalty = if iteration >= iterations - rerouting.len() {
       max_cost
     } else {
-      1 << min(5, iteration / endpoints.len())
+      1 << min(5, iteration / rerouting.len())
     };
 
     //let other_routes: Vec<&Route> = current_routes.iter().enumerate().filter_map (
@@ -1022,14 +1590,16 @@ pub fn find_routes(
       map,
       //previous_version: Some(&current_routes [which].0),
       //other_routes: & other_routes,
+      costs,
       overlap_penalty: penalty,
       max_cost,
       //max_route_objects: 64,
       //conflict_history: & conflict_history,
-      search_map: &mut search_map,
-      source: endpoints[which].0,
+      search_map,
+      source: endpoints[which].0.clone(),
       destinations: &endpoints[which].1,
       backwards: endpoints[which].2,
+      strategy: endpoints[which].3,
     });
 
     //conflict_history.extend_from_slice (& new_route.0.conflicts);
@@ -1049,6 +1619,295 @@ pub fn find_routes(
   //current_routes.into_iter().filter_map (| (route, success) | if true || success {Some (route)} else {None}).collect()
 }
 
+/// A change to a map that has already been routed, for `IncrementalRouting::reroute`.
+#[derive(Clone, Debug, Default)]
+pub struct MapEdit {
+  /// tiles that became obstructed or stopped being obstructed
+  pub changed_tiles: Vec<Coordinates>,
+  /// endpoints that were replaced by something else
+  pub changed_endpoints: Vec<usize>,
+}
+
+/// Routes that can be patched up after small changes to the map, such as moving an assembler,
+/// rather than found again from scratch. Only the routes that the change could matter to are
+/// ripped up, and the congestion history of the first routing guides the new ones.
+#[derive(Clone, Debug)]
+pub struct IncrementalRouting {
+  pub search_map: RouteSearchMap,
+  /// the route for each endpoint, and whether it reached all of its destinations
+  pub routes: Vec<(Vec<Object>, bool)>,
+  pub iterations: usize,
+  pub costs: CostModel,
+}
+
+impl IncrementalRouting {
+  /// Routes every endpoint, like `find_routes`.
+  pub fn new(
+    map: &RoutingMap,
+    endpoints: &[RouteEndpoint],
+    iterations: usize,
+    costs: &CostModel,
+  ) -> IncrementalRouting {
+    let mut result = IncrementalRouting {
+      search_map: RouteSearchMap::new(map, endpoints),
+      routes: endpoints.iter().map(|_| (Vec::new(), false)).collect(),
+      iterations,
+      costs: costs.clone(),
+    };
+    let all: Vec<usize> = (0..endpoints.len()).collect();
+    negotiate_routes(
+      map,
+      &mut result.search_map,
+      endpoints,
+      &all,
+      &mut result.routes,
+      iterations,
+      &result.costs,
+    );
+    result
+  }
+
+  /// Every object of every route.
+  pub fn objects(&self) -> Vec<Object> {
+    self
+      .routes
+      .iter()
+      .flat_map(|route| route.0.iter().cloned())
+      .collect()
+  }
+
+  /// Catches up with `edit`, which `map` and `endpoints` already show. Endpoints past the end of
+  /// the old list count as changed, and routes past the end of the new one are dropped.
+  /// Reroutes the changed endpoints, those that didn't reach all of their destinations, and
+  /// those with something on or reaching into a changed tile, and returns which those were.
+  pub fn reroute(
+    &mut self,
+    map: &RoutingMap,
+    endpoints: &[RouteEndpoint],
+    edit: &MapEdit,
+  ) -> Vec<usize> {
+    for (removed, _) in self.routes.drain(min(endpoints.len(), self.routes.len())..) {
+      for object in &removed {
+        self.search_map.remove_object(object, false);
+      }
+    }
+    self.routes.resize(endpoints.len(), (Vec::new(), false));
+    self.search_map.mark_exits(map, endpoints);
+
+    // an edited tile matters to every object that it's on or that reaches into it, like the
+    // tiles a long inserter takes from and drops onto
+    let changed_tiles: HashSet<Coordinates> = edit.changed_tiles.iter().cloned().collect();
+    let touches_edit = |object: &Object| {
+      object
+        .interaction_bounding_box()
+        .tiles()
+        .any(|coordinates| changed_tiles.contains(&coordinates))
+    };
+    let rerouting: Vec<usize> = (0..endpoints.len())
+      .filter(|which| {
+        let (route, succeeded) = &self.routes[*which];
+        edit.changed_endpoints.contains(which) || !succeeded || route.iter().any(touches_edit)
+      })
+      .collect();
+
+    for &which in &rerouting {
+      for object in &std::mem::take(&mut self.routes[which].0) {
+        self.search_map.remove_object(object, false);
+      }
+    }
+    negotiate_routes(
+      map,
+      &mut self.search_map,
+      endpoints,
+      &rerouting,
+      &mut self.routes,
+      self.iterations,
+      &self.costs,
+    );
+    rerouting
+  }
+}
+
+/// Settings for `find_routes_parallel`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ParallelRouting {
+  /// how many searches to run at once
+  pub threads: usize,
+  /// shuffles the order that each round's routes are added to the congestion costs in
+  pub seed: u64,
+}
+
+/// Negotiated routing in the style of PathFinder FPGA routers: each round reroutes every
+/// endpoint at once, each against the same snapshot of the map, and only then puts the new
+/// routes on the map and raises the congestion costs where they collide. Routes found at the
+/// same time can't avoid each other, so when one runs into a route merged before it, it is
+/// searched again against the map as it is by then; and a last sequential pass (like the last
+/// pass of `find_routes`) cleans up.
+///
+/// The result only depends on the seed: each search sees the same snapshot whichever thread
+/// runs it, and routes are merged in an order that the seed decides.
+pub fn find_routes_parallel(
+  map: &RoutingMap,
+  endpoints: &[RouteEndpoint],
+  iterations: usize,
+  costs: &CostModel,
+  parallel: ParallelRouting,
+) -> Vec<Vec<Object>> {
+  let mut search_map = RouteSearchMap::new(map, endpoints);
+  find_routes_parallel_in(map, &mut search_map, endpoints, iterations, costs, parallel)
+}
+
+/// Like `find_routes_parallel`, but leaves the congestion history in `search_map`.
+pub fn find_routes_parallel_in(
+  map: &RoutingMap,
+  search_map: &mut RouteSearchMap,
+  endpoints: &[RouteEndpoint],
+  iterations: usize,
+  costs: &CostModel,
+  parallel: ParallelRouting,
+) -> Vec<Vec<Object>> {
+  let max_cost = 6000;
+  let rounds = iterations.saturating_sub(1);
+  let mut current_routes: Vec<(Vec<Object>, bool)> =
+    endpoints.iter().map(|_| (Vec::new(), false)).collect();
+  let mut rng = StdRng::seed_from_u64(parallel.seed);
+  let mut result = Vec::new();
+  let flatten = |routes: &[(Vec<Object>, bool)]| -> Vec<Object> {
+    routes
+      .iter()
+      .flat_map(|route| route.0.iter().cloned())
+      .collect()
+  };
+
+  // unlike `find_routes`, where each search sees the routes before it, routes found at the same
+  // time only learn about each other from the costs, so keep raising the penalty, and keep
+  // going (for a while) until nothing overlaps
+  for round in 0..rounds * 2 {
+    if round >= rounds && !search_map.overlapping() {
+      break;
+    }
+    let penalty = min(max_cost, 1 << min(12, round));
+    eprintln!(" routing all endpoints: {:?} ", (round, penalty));
+    let new_routes = search_simultaneously(
+      map,
+      search_map,
+      &current_routes,
+      endpoints,
+      costs,
+      penalty,
+      max_cost,
+      parallel.threads,
+    );
+    let mut order: Vec<usize> = (0..endpoints.len()).collect();
+    order.shuffle(&mut rng);
+    for route in &current_routes {
+      for object in &route.0 {
+        search_map.remove_object(object, false);
+      }
+    }
+    // routes that run into ones merged before them in this round get searched again, here and
+    // now, so that the endpoints can't keep swapping places with each other
+    for &which in &order {
+      if search_map.collides(&new_routes[which].0) {
+        current_routes[which] = find_route(RouteSearchParameters {
+          map,
+          costs,
+          overlap_penalty: penalty,
+          max_cost,
+          search_map,
+          source: endpoints[which].0.clone(),
+          destinations: &endpoints[which].1,
+          backwards: endpoints[which].2,
+          strategy: endpoints[which].3,
+        });
+      } else {
+        search_map.place_route(&new_routes[which].0, penalty);
+        current_routes[which] = new_routes[which].clone();
+      }
+    }
+    result.push(flatten(&current_routes));
+  }
+
+  for which in 0..endpoints.len() {
+    eprintln!(" routing: {:?} ", (which, max_cost));
+    let previous = std::mem::take(&mut current_routes[which]);
+    for object in &previous.0 {
+      search_map.remove_object(object, false);
+    }
+    let new_route = find_route(RouteSearchParameters {
+      map,
+      costs,
+      overlap_penalty: max_cost,
+      max_cost,
+      search_map,
+      source: endpoints[which].0.clone(),
+      destinations: &endpoints[which].1,
+      backwards: endpoints[which].2,
+      strategy: endpoints[which].3,
+    });
+    // the search is greedy, one destination at a time, so starting over can paint itself into
+    // a corner that the last round's route didn't
+    current_routes[which] = if new_route.1 || !previous.1 {
+      new_route
+    } else {
+      for object in &new_route.0 {
+        search_map.remove_object(object, false);
+      }
+      let keep = if search_map.collides(&previous.0) {
+        new_route
+      } else {
+        previous
+      };
+      search_map.place_route(&keep.0, max_cost);
+      keep
+    };
+    result.push(flatten(&current_routes));
+  }
+  result
+}
+
+/// Reroutes every endpoint against its own copy of `snapshot`, splitting the endpoints between
+/// `threads` threads in contiguous runs.
+#[allow(clippy::too_many_arguments)]
+fn search_simultaneously(
+  map: &RoutingMap,
+  snapshot: &RouteSearchMap,
+  current_routes: &[(Vec<Object>, bool)],
+  endpoints: &[RouteEndpoint],
+  costs: &CostModel,
+  overlap_penalty: usize,
+  max_cost: usize,
+  threads: usize,
+) -> Vec<(Vec<Object>, bool)> {
+  let mut result = vec![(Vec::new(), false); endpoints.len()];
+  let run_length = max(1, endpoints.len().div_ceil(threads.max(1)));
+  std::thread::scope(|scope| {
+    for (run, routes) in result.chunks_mut(run_length).enumerate() {
+      scope.spawn(move || {
+        for (offset, route) in routes.iter_mut().enumerate() {
+          let which = run * run_length + offset;
+          let mut search_map = snapshot.clone();
+          for object in &current_routes[which].0 {
+            search_map.remove_object(object, false);
+          }
+          *route = find_route(RouteSearchParameters {
+            map,
+            costs,
+            overlap_penalty,
+            max_cost,
+            search_map: &mut search_map,
+            source: endpoints[which].0.clone(),
+            destinations: &endpoints[which].1,
+            backwards: endpoints[which].2,
+            strategy: endpoints[which].3,
+          });
+        }
+      });
+    }
+  });
+  result
+}
+
 pub fn upgrade_from(objects: &mut [Object], source: DirectedEdge, level: u8) {
   for index in 0..objects.len() {
     if objects[index]
@@ -1092,6 +1951,17 @@ pub fn gigabase_map() -> RoutingMap {
 
   map
 }
+/// A blueprint of a chunk routed on `gigabase_map`, snapping to the city-block grid.
+pub fn gigassembly_blueprint(entities: Vec<Entity>) -> Blueprint {
+  let mut result = Blueprint::simple(
+    "Gigassembly chunk".to_string(),
+    entities,
+    GameVersion::default(),
+  );
+  result.align_to_grid(gigabase_map().bounds().edges(), [0, 0]);
+  result
+}
+
 pub fn gigabase_electric_poles() -> Vec<Entity> {
   (&[
     [-0.5, -0.5],
@@ -1145,7 +2015,7 @@ pub fn gigassembly_chunk(
     .iter()
     .map(|&(vertical, is_output, which_assemblers)| {
       (
-        DirectedEdge::from_before([16, vertical], 6),
+        RouteSource::Conveyor(DirectedEdge::from_before([16, vertical], 6)),
         std::iter::once(RouteDestination::Conveyor(DirectedEdge::from_before(
           [-16, vertical],
           6,
@@ -1158,13 +2028,19 @@ pub fn gigassembly_chunk(
         )
         .collect(),
         is_output,
+        RouteStrategy::Nearest,
       )
     })
     .collect();
 
-  let mut routes = find_routes(&map, &converted_endpoints, iterations)
-    .pop()
-    .unwrap();
+  let mut routes = find_routes(
+    &map,
+    &converted_endpoints,
+    iterations,
+    &CostModel::default(),
+  )
+  .pop()
+  .unwrap();
 
   for &(vertical, is_output, _) in endpoints {
     if is_output {
@@ -1222,10 +2098,17 @@ pub fn lots_of_belts() -> Vec<Entity> {
   let endpoints: Vec<_> = sources
     .into_iter()
     .zip(destinations)
-    .map(|(s, d)| (s, vec![RouteDestination::Conveyor(d)], false))
+    .map(|(s, d)| {
+      (
+        RouteSource::Conveyor(s),
+        vec![RouteDestination::Conveyor(d)],
+        false,
+        RouteStrategy::Nearest,
+      )
+    })
     .collect();
 
-  let routes = find_routes(&map, &endpoints, 32);
+  let routes = find_routes(&map, &endpoints, 32, &CostModel::default());
 
   let mut result = Vec::new();
   for route in routes {
@@ -1236,6 +2119,47 @@ pub fn lots_of_belts() -> Vec<Entity> {
   result
 }
 
+/// A whole outpost's worth of map, for seeing how the search scales: `size` × `size` tiles with
+/// buildings scattered over them, and `belts` conveyors crossing from west to east, each leaving
+/// at the mirror image of the row it came in on, so that they all have to cross each other.
+pub fn outpost_scenario(size: i32, belts: i32) -> (RoutingMap, Vec<RouteEndpoint>) {
+  let mut map = RoutingMap::new(Rectangle::new([[0, size - 1], [0, size - 1]]));
+  for x in (8..size - 8).step_by(14) {
+    for y in (8..size - 8).step_by(14) {
+      let shift = (x * 3 + y * 5) % 7;
+      for tile in Rectangle::new([[x + shift, x + shift + 4], [y, y + 4]]).tiles() {
+        map.set_obstructed(tile, true);
+      }
+    }
+  }
+  let row = |index: i32| (index + 1) * size / (belts + 1);
+  let endpoints = (0..belts)
+    .map(|index| {
+      (
+        RouteSource::Conveyor(DirectedEdge::from_before([-1, row(index)], 2)),
+        vec![RouteDestination::Conveyor(DirectedEdge::from_before(
+          [size - 1, row(belts - 1 - index)],
+          2,
+        ))],
+        false,
+        RouteStrategy::Nearest,
+      )
+    })
+    .collect();
+  (map, endpoints)
+}
+
+pub fn outpost_belts() -> Vec<Entity> {
+  let (map, endpoints) = outpost_scenario(200, 16);
+  let routes = find_routes(&map, &endpoints, 4, &CostModel::default());
+  routes
+    .last()
+    .unwrap()
+    .iter()
+    .flat_map(|object| object.render())
+    .collect()
+}
+
 pub fn assemblers_thingy() -> Vec<Blueprint> {
   /*fn add_assembler(map: &mut RoutingMap, assemblers: &mut Vec<Assembler>, assembler: Assembler) {
     for tile in assembler.shape().tiles() {
@@ -1338,10 +2262,10 @@ pub fn assemblers_thingy() -> Vec<Blueprint> {
     for assembler in &assemblers {
       entities.extend(Object::Assembler(assembler.clone()).render())
     }
-    Blueprint::simple(format!("Iteration {}", index), entities)
+    Blueprint::simple(format!("Iteration {}", index), entities, GameVersion::default())
   })
   .collect()*/
-  vec![Blueprint::simple(format!("Gigassembly chunk"), entities)]
+  vec![gigassembly_blueprint(entities)]
 }
 
 pub fn advanced_circuits_chunk() -> Vec<Blueprint> {
@@ -1489,7 +2413,7 @@ pub fn advanced_circuits_chunk() -> Vec<Blueprint> {
     &endpoints,
     128,
   );
-  vec![Blueprint::simple(format!("Gigassembly chunk"), entities)]
+  vec![gigassembly_blueprint(entities)]
 }
 
 pub fn route_blueprint_thingy() -> Vec<Blueprint> {
@@ -1497,3 +2421,254 @@ pub fn route_blueprint_thingy() -> Vec<Blueprint> {
 
   advanced_circuits_chunk()
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::prototypes::footprint_tiles;
+
+  /// A map covering `bounds`, with every tile of `obstacles` obstructed.
+  fn obstructed_map(bounds: [[i32; 2]; 2], obstacles: &[Rectangle]) -> RoutingMap {
+    let mut map = RoutingMap::new(Rectangle::new(bounds));
+    for tile in obstacles.iter().flat_map(|obstacle| obstacle.tiles()) {
+      map.set_obstructed(tile, true);
+    }
+    map
+  }
+
+  /// Finds the route for `endpoint` on `map` with one search, returning the objects placed and
+  /// whether it reached all of its destinations.
+  fn route(map: &RoutingMap, endpoint: RouteEndpoint, costs: &CostModel) -> (Vec<Object>, bool) {
+    let (source, destinations, backwards, strategy) = endpoint.clone();
+    let mut search_map = RouteSearchMap::new(map, &[endpoint]);
+    find_route(RouteSearchParameters {
+      map,
+      search_map: &mut search_map,
+      costs,
+      overlap_penalty: 6000,
+      max_cost: 6000,
+      source,
+      destinations: &destinations,
+      backwards,
+      strategy,
+    })
+  }
+
+  /// The belt leaving the east edge of the map, which the merges have to end up on.
+  fn exit() -> DirectedEdge {
+    DirectedEdge::from_before([11, 0], 2)
+  }
+
+  /// Routes the output of six assemblers backwards onto one belt leaving the map, like
+  /// `problems/merging.toml`.
+  fn merge(costs: &CostModel) -> (Vec<Assembler>, Vec<Object>, bool) {
+    let assemblers: Vec<Assembler> = [[-8, -4], [-4, -4], [0, -4], [-8, 4], [-4, 4], [0, 4]]
+      .iter()
+      .map(|&center| Assembler { center })
+      .collect();
+    let shapes: Vec<Rectangle> = assemblers
+      .iter()
+      .map(|assembler| assembler.shape())
+      .collect();
+    let map = obstructed_map([[-12, 11], [-8, 7]], &shapes);
+    let destinations = assemblers
+      .iter()
+      .cloned()
+      .map(RouteDestination::Assembler)
+      .collect();
+    let source = RouteSource::Conveyor(exit().reversed());
+    let (objects, succeeded) = route(
+      &map,
+      (source, destinations, true, RouteStrategy::Nearest),
+      costs,
+    );
+    (assemblers, objects, succeeded)
+  }
+
+  /// Checks that every assembler has an inserter taking from it onto a conveyor that leads
+  /// to the exit, and that nothing in the blueprint overlaps.
+  fn assert_merged(assemblers: &[Assembler], objects: &[Object]) {
+    let reaches_exit = |start: &Object| {
+      let mut stack = vec![start];
+      let mut seen = Vec::new();
+      while let Some(object) = stack.pop() {
+        if seen.contains(&object) {
+          continue;
+        }
+        seen.push(object);
+        for &output in object.conveyor_outputs().iter() {
+          if output == exit() {
+            return true;
+          }
+          stack.extend(
+            objects
+              .iter()
+              .filter(|other| other.conveyor_inputs().contains(&output)),
+          );
+        }
+      }
+      false
+    };
+    for assembler in assemblers {
+      let satisfied = objects.iter().any(|object| match object {
+        Object::Inserter(inserter) if assembler.shape().contains(inserter.input()) => objects
+          .iter()
+          .filter(|other| other.solid_tiles().contains(&inserter.output()))
+          .any(&reaches_exit),
+        _ => false,
+      });
+      assert!(
+        satisfied,
+        "nothing carries the output of the assembler at {:?} away",
+        assembler.center
+      );
+    }
+
+    let mut covered = HashSet::new();
+    let entities = assemblers
+      .iter()
+      .cloned()
+      .map(Object::Assembler)
+      .chain(objects.iter().cloned())
+      .flat_map(|object| object.render());
+    for entity in entities {
+      for tile in footprint_tiles(&entity) {
+        assert!(
+          covered.insert(tile),
+          "{} at {:?} overlaps something else",
+          entity.name,
+          entity.position
+        );
+      }
+    }
+  }
+
+  /// Belts that another conveyor joins from the side, on top of the one feeding them from
+  /// behind.
+  fn side_loads(objects: &[Object]) -> usize {
+    objects
+      .iter()
+      .filter_map(|object| match object {
+        Object::Belt(belt) => Some(belt),
+        _ => None,
+      })
+      .filter(|belt| {
+        let fed_from = |direction: u8| {
+          objects.iter().any(|other| {
+            other
+              .conveyor_outputs()
+              .contains(&DirectedEdge::from_after(belt.position(), direction))
+          })
+        };
+        let direction = belt.direction();
+        fed_from(direction) && (fed_from((direction + 2) % 8) || fed_from((direction + 6) % 8))
+      })
+      .count()
+  }
+
+  #[test]
+  fn merge_by_side_loading() {
+    let (assemblers, objects, succeeded) = merge(&CostModel::default());
+    assert!(succeeded);
+    assert_merged(&assemblers, &objects);
+    assert!(side_loads(&objects) > 0);
+    assert!(!objects
+      .iter()
+      .any(|object| matches!(object, Object::Splitter(_))));
+  }
+
+  #[test]
+  fn merge_through_splitters() {
+    let costs = CostModel {
+      splitter: 0,
+      ..CostModel::default()
+    };
+    let (assemblers, objects, succeeded) = merge(&costs);
+    assert!(succeeded);
+    assert_merged(&assemblers, &objects);
+    assert!(objects
+      .iter()
+      .any(|object| matches!(object, Object::Splitter(_))));
+  }
+
+  /// Routes between the loader and the west edge of a small map, in whichever direction the
+  /// route goes, returning whether it got there.
+  fn route_to_loader(loader: Loader, backwards: bool) -> bool {
+    let map = obstructed_map([[0, 11], [0, 11]], &[loader.shape()]);
+    // backwards routes search from where their items leave, against the flow of the belt
+    let source = RouteSource::Conveyor(DirectedEdge::from_before([-1, 5], 2));
+    let destinations = vec![RouteDestination::Loader(loader)];
+    let endpoint = (source, destinations, backwards, RouteStrategy::Nearest);
+    route(&map, endpoint, &CostModel::default()).1
+  }
+
+  #[test]
+  fn loaders_only_work_one_way() {
+    let loading = Loader::loading(DirectedEdge::from_before([7, 5], 2));
+    let unloading = Loader::unloading(DirectedEdge::from_after([8, 5], 6));
+    assert!(route_to_loader(loading.clone(), false));
+    assert!(!route_to_loader(loading, true));
+    assert!(route_to_loader(unloading.clone(), true));
+    assert!(!route_to_loader(unloading, false));
+  }
+
+  /// Two wooden chests `distance` tiles apart on an otherwise empty map, and the route from the
+  /// first to the second.
+  fn chests_apart(distance: i32) -> (RoutingMap, RouteEndpoint) {
+    let source = Chest::new([0, 5], ChestType::Wooden);
+    let destination = Chest::new([distance, 5], ChestType::Wooden);
+    let map = obstructed_map(
+      [[-4, 11], [0, 11]],
+      &[
+        Rectangle::singleton(source.position),
+        Rectangle::singleton(destination.position),
+      ],
+    );
+    let endpoint = (
+      RouteSource::Chest(source),
+      vec![RouteDestination::Chest(destination)],
+      false,
+      RouteStrategy::Nearest,
+    );
+    (map, endpoint)
+  }
+
+  #[test]
+  fn inserters_only_chain_through_the_chosen_chest() {
+    // too far apart for one inserter
+    let (map, endpoint) = chests_apart(6);
+    assert!(!route(&map, endpoint.clone(), &CostModel::default()).1);
+    let costs = CostModel {
+      chain_chest: Some(ChestType::Iron),
+      ..CostModel::default()
+    };
+    let (objects, succeeded) = route(&map, endpoint, &costs);
+    assert!(succeeded);
+    let chests: Vec<ChestType> = objects
+      .iter()
+      .filter_map(|object| match object {
+        Object::Chest(chest) => Some(chest.chest_type),
+        _ => None,
+      })
+      .collect();
+    assert_eq!(chests, vec![ChestType::Iron]);
+  }
+
+  #[test]
+  fn reroute_reaches_as_far_as_long_inserters() {
+    let (map, endpoint) = chests_apart(4);
+    let endpoints = vec![endpoint];
+    let mut routing = IncrementalRouting::new(&map, &endpoints, 1, &CostModel::default());
+    // one long inserter, two tiles from each chest, is all it takes
+    assert_eq!(routing.routes[0].0.len(), 1);
+    let rerouted = routing.reroute(
+      &map,
+      &endpoints,
+      &MapEdit {
+        changed_tiles: vec![[4, 5]],
+        changed_endpoints: Vec::new(),
+      },
+    );
+    assert_eq!(rerouted, vec![0]);
+  }
+}
@@ -0,0 +1,775 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process;
+
+use factorio_blueprint_processor::belt_routing::{self, RouteStrategy};
+use factorio_blueprint_processor::blueprint::*;
+use factorio_blueprint_processor::combinators::{BlueprintSimulation, Circuit, Signals};
+use factorio_blueprint_processor::diff::{diff_blueprints, DiffAlignment};
+use factorio_blueprint_processor::pixel_art::{self, Dither, LampColorSource, PixelArtOptions};
+use factorio_blueprint_processor::preview::{render_blueprint, PreviewOptions};
+use factorio_blueprint_processor::rails::{self, StationKind, StationOptions};
+use factorio_blueprint_processor::routing_problem::{load_problem, solve_problem};
+use factorio_blueprint_processor::stamp::{ConflictPolicy, Rotation};
+use factorio_blueprint_processor::statistics::BlueprintStatistics;
+use factorio_blueprint_processor::upgrade::UpgradePlan;
+
+const USAGE: &str = "usage: fbp <command> [arguments] [-o <output file>]
+
+commands:
+  decode [<blueprint string file>]    print the blueprint as pretty JSON
+  encode [<JSON file>]                turn JSON back into a blueprint string
+  route <problem file | scenario> [--threads <count>] [--seed <number>]
+        [--strategy nearest|spanning-tree|cheapest]
+                                      solve a TOML or JSON routing problem, or run one of the
+                                        built-in scenarios (lots-of-belts, assemblers,
+                                        advanced-circuits, outpost). --threads routes every
+                                        endpoint at once; the seed, not the thread count,
+                                        decides the result. --strategy picks the order that
+                                        every endpoint connects its destinations in
+  pixel-art <image file> [--size <width>x<height>] [--dither none|ordered|floyd-steinberg]
+            [--palette icons|concrete|lamps|lamp-colors]
+                                      render an image as a grid of chests and undergrounds,
+                                        concrete tiles, or lamps lit by constant combinators
+                                        (lamp-colors sets each lamp's color directly instead)
+  upgrade <from>=<to>... [--belts <tier>-<tier>] [--planner <upgrade planner file>]
+          [--undergrounds check|ignore] [<blueprint string file>]
+                                      replace entity names throughout a blueprint or book;
+                                        belt tiers go from 1 (yellow) to 3 (blue). Refuses to
+                                        leave underground belts too far apart unless told to
+                                        ignore them
+  upgrade-planner <from>=<to>... [--belts <tier>-<tier>] [--label <label>]
+                                      make an upgrade planner string for the same replacements
+  stats [<blueprint string file>]     count the entities, materials and raw resources in each
+                                        blueprint, and in the whole book
+  preview [<blueprint string file>] -o <PNG file> [--tile-size <pixels>]
+                                      draw the blueprint as a picture; each blueprint in a
+                                        book gets its own numbered file
+  diff <before file> <after file> [--offset auto|<x>,<y>] [--png <PNG file>]
+                                      list the entities that were added, removed or changed
+                                        between two blueprints, with a map of where they are;
+                                        --offset auto lines them up first
+  stamp <base file> <blueprint file> [--offset <x>,<y>] [--rotate 0|90|180|270]
+        [--conflicts error|overwrite|skip]
+                                      copy the second blueprint into the first
+  tile <chunk file> --grid <columns>x<rows> [--spacing <x>,<y>] [--recipes <recipe>,...]
+       [--conflicts error|overwrite|skip]
+                                      repeat a blueprint on a grid, spaced by its own size
+                                        unless told otherwise; with --recipes, each copy's
+                                        machines make the next recipe in the list
+  circuit clock|counter [--period <ticks>] [--simulate <ticks>]
+                                      build one of the example combinator circuits: a clock
+                                        counting to the period, or a counter of its pulses.
+                                        --simulate prints what the circuit outputs on each
+                                        tick instead of the blueprint
+  simulate [<blueprint string file>] [--ticks <count>] [--watch <entity number>,...]
+                                      run the blueprint's combinators, printing what each
+                                        one outputs on every tick, or what the watched
+                                        entities read from their wires
+  station <name> [--wagons <count>] [--locomotives <count>] [--kind loading|unloading]
+          [--chest <name>] [--inserter <name>] [--sides 1|2]
+          [--schedule <station>[:full|empty|<seconds>s],...]
+                                      make a train station for northbound trains, with a
+                                        chest and inserter for each tile of every wagon;
+                                        with --schedule, a train waits in it that goes to
+                                        each station in turn until its condition holds
+
+Inputs default to standard input, and the output defaults to standard output. Commands that
+write blueprint strings take --game-version <version> (like 1.1 or 2.0) to target a particular
+version of the game, converting between the 1.1 and 2.0 blueprint formats and entity names.
+Blueprint strings from either are read automatically.";
+
+type CliResult<T> = Result<T, String>;
+
+fn describe(context: &str, error: impl Display) -> String {
+  format!("{}: {}", context, error)
+}
+
+struct Arguments {
+  positional: Vec<String>,
+  output: Option<String>,
+  options: BTreeMap<String, String>,
+}
+
+impl Arguments {
+  fn parse(arguments: impl Iterator<Item = String>) -> CliResult<Arguments> {
+    let mut result = Arguments {
+      positional: Vec::new(),
+      output: None,
+      options: BTreeMap::new(),
+    };
+    let mut arguments = arguments;
+    while let Some(argument) = arguments.next() {
+      match argument.as_str() {
+        "-o" | "--output" => {
+          result.output = Some(
+            arguments
+              .next()
+              .ok_or_else(|| format!("{} needs a file name", argument))?,
+          )
+        }
+        _ if argument.starts_with("--") => {
+          let value = arguments
+            .next()
+            .ok_or_else(|| format!("{} needs a value", argument))?;
+          result
+            .options
+            .insert(argument.trim_start_matches("--").to_string(), value);
+        }
+        _ => result.positional.push(argument),
+      }
+    }
+    Ok(result)
+  }
+
+  fn option(&self, name: &str) -> Option<&str> {
+    self.options.get(name).map(String::as_str)
+  }
+
+  /// Encodes a blueprint object, converted for the version given with `--game-version` if any.
+  fn write_blueprint(&self, mut object: BlueprintObject) -> CliResult<()> {
+    if let Some(version) = self.option("game-version") {
+      let version: GameVersion = version.parse()?;
+      object.convert_to(version);
+    }
+    self.write_output(&encode_object(&object)?)
+  }
+
+  fn input(&self, index: usize) -> CliResult<String> {
+    read_input(self.positional.get(index))
+  }
+
+  fn write_output(&self, text: &str) -> CliResult<()> {
+    match &self.output {
+      None => {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        writeln!(stdout, "{}", text).map_err(|error| describe("writing standard output", error))
+      }
+      Some(path) => fs::write(path, text).map_err(|error| describe(path, error)),
+    }
+  }
+}
+
+fn read_input(path: Option<&String>) -> CliResult<String> {
+  match path.map(String::as_str) {
+    None | Some("-") => {
+      let mut input = String::new();
+      io::stdin()
+        .read_to_string(&mut input)
+        .map_err(|error| describe("reading standard input", error))?;
+      Ok(input)
+    }
+    Some(path) => fs::read_to_string(path).map_err(|error| describe(path, error)),
+  }
+}
+
+fn decode_string(input: &str) -> CliResult<BlueprintObject> {
+  EncodedBlueprint(input.trim().to_string())
+    .decode()
+    .map_err(|error| describe("decoding blueprint string", error))
+}
+
+fn encode_object(object: &BlueprintObject) -> CliResult<String> {
+  object
+    .encode()
+    .map(|encoded| encoded.0)
+    .map_err(|error| describe("encoding blueprint", error))
+}
+
+fn decode(arguments: &Arguments) -> CliResult<()> {
+  let object = decode_string(&arguments.input(0)?)?;
+  let json = object
+    .to_json_pretty()
+    .map_err(|error| describe("writing JSON", error))?;
+  arguments.write_output(&json)
+}
+
+fn encode(arguments: &Arguments) -> CliResult<()> {
+  let object = BlueprintObject::from_json(&arguments.input(0)?)
+    .map_err(|error| describe("reading JSON", error))?;
+  arguments.write_blueprint(object)
+}
+
+fn route(arguments: &Arguments) -> CliResult<()> {
+  let scenario = arguments
+    .positional
+    .first()
+    .ok_or("route needs a problem file or scenario name")?;
+  if Path::new(scenario).is_file() {
+    let mut problem = load_problem(scenario).map_err(|error| describe(scenario, error))?;
+    if let Some(threads) = arguments.option("threads") {
+      let threads = threads
+        .parse()
+        .map_err(|error| describe("--threads", error))?;
+      problem.threads = Some(threads);
+    }
+    if let Some(seed) = arguments.option("seed") {
+      problem.seed = seed.parse().map_err(|error| describe("--seed", error))?;
+    }
+    if let Some(strategy) = arguments.option("strategy") {
+      let strategy = route_strategy(strategy)?;
+      for endpoint in &mut problem.endpoints {
+        endpoint.strategy = strategy;
+      }
+    }
+    let solution = solve_problem(&problem).map_err(|error| describe(scenario, error))?;
+    eprintln!("total object cost: {}", solution.cost);
+    return arguments.write_blueprint(BlueprintObject::Blueprint(solution.blueprint));
+  }
+  let blueprints = match scenario.as_str() {
+    "lots-of-belts" => vec![Blueprint::simple(
+      "lots of belts".to_string(),
+      belt_routing::lots_of_belts(),
+      GameVersion::default(),
+    )],
+    "assemblers" => belt_routing::assemblers_thingy(),
+    "outpost" => vec![Blueprint::simple(
+      "outpost belts".to_string(),
+      belt_routing::outpost_belts(),
+      GameVersion::default(),
+    )],
+    "advanced-circuits" => belt_routing::advanced_circuits_chunk(),
+    _ => return Err(format!("unknown routing scenario: {}", scenario)),
+  };
+  let book = BlueprintObject::BlueprintBook(BlueprintBook::simple(
+    "routed belts".to_string(),
+    blueprints,
+    GameVersion::default(),
+  ));
+  arguments.write_blueprint(book)
+}
+
+fn route_strategy(name: &str) -> CliResult<RouteStrategy> {
+  match name {
+    "nearest" => Ok(RouteStrategy::Nearest),
+    "spanning-tree" => Ok(RouteStrategy::SpanningTree),
+    "cheapest" => Ok(RouteStrategy::Cheapest),
+    _ => Err(format!("unknown routing strategy: {}", name)),
+  }
+}
+
+fn pixel_art(arguments: &Arguments) -> CliResult<()> {
+  let path = arguments
+    .positional
+    .first()
+    .ok_or("pixel-art needs an image file")?;
+
+  let mut lamps = None;
+  let palette = match arguments.option("palette").unwrap_or("icons") {
+    "icons" => {
+      let mut palette = pixel_art::icon_palette("images", &pixel_art::CHEST_AND_UNDERGROUND_NAMES)
+        .map_err(|error| describe("reading icons", error))?;
+      pixel_art::stretch_palette(&mut palette);
+      palette
+    }
+    "concrete" => pixel_art::concrete_palette(),
+    "lamps" => {
+      lamps = Some(LampColorSource::Signals);
+      Vec::new()
+    }
+    "lamp-colors" => {
+      lamps = Some(LampColorSource::LampColor);
+      Vec::new()
+    }
+    other => return Err(format!("unknown palette: {}", other)),
+  };
+  let mut options = PixelArtOptions::new(palette);
+  if let Some(dither) = arguments.option("dither") {
+    options.dither = match dither {
+      "none" => Dither::None,
+      "ordered" => Dither::Ordered,
+      "floyd-steinberg" => Dither::FloydSteinberg,
+      _ => return Err(format!("unknown dithering: {}", dither)),
+    };
+  }
+  if let Some(size) = arguments.option("size") {
+    let mut parts = size.splitn(2, 'x').map(str::parse::<u32>);
+    options.size = match (parts.next(), parts.next()) {
+      (Some(Ok(width)), Some(Ok(height))) => Some([width, height]),
+      _ => return Err(format!("size should look like 64x48, not {}", size)),
+    };
+  }
+
+  let picture = image::open(path).map_err(|error| describe(path, error))?;
+  let blueprint = match lamps {
+    Some(source) => pixel_art::lamp_art(path.clone(), &picture, &options, source)
+      .map_err(|error| error.to_string())?,
+    None => pixel_art::pixel_art(path.clone(), &picture, &options),
+  };
+  arguments.write_blueprint(BlueprintObject::Blueprint(blueprint))
+}
+
+/// The plan given by `<from>=<to>` arguments, `--belts <from>-<to>` and `--planner <file>`,
+/// and whatever positional argument is left over.
+fn upgrade_plan(arguments: &Arguments) -> CliResult<(UpgradePlan, Option<&String>)> {
+  let mut plan = UpgradePlan::new();
+  let mut input = None;
+  for argument in &arguments.positional {
+    let mut parts = argument.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+      (Some(from), Some(to)) => plan = plan.with(from, to),
+      _ => input = Some(argument),
+    }
+  }
+  if let Some(tiers) = arguments.option("belts") {
+    let tiers = parse_pair("belt tiers", tiers, '-')?;
+    if !tiers
+      .iter()
+      .all(|&tier| tier == 1.0 || tier == 2.0 || tier == 3.0)
+    {
+      return Err("belt tiers go from 1 (yellow) to 3 (blue)".to_string());
+    }
+    let belts = UpgradePlan::belt_tiers(tiers[0] as usize, tiers[1] as usize);
+    plan.mappings.extend(belts.mappings);
+  }
+  if let Some(path) = arguments.option("planner") {
+    match decode_string(&read_input(Some(&path.to_string()))?)? {
+      BlueprintObject::UpgradePlanner(planner) => {
+        plan
+          .mappings
+          .extend(UpgradePlan::from_planner(&planner).mappings);
+      }
+      _ => return Err(format!("{} isn't an upgrade planner", path)),
+    }
+  }
+  if plan.mappings.is_empty() {
+    return Err("needs at least one <from>=<to> replacement".to_string());
+  }
+  Ok((plan, input))
+}
+
+fn upgrade(arguments: &Arguments) -> CliResult<()> {
+  let (plan, input) = upgrade_plan(arguments)?;
+  let mut object = decode_string(&read_input(input)?)?;
+  match arguments.option("undergrounds").unwrap_or("check") {
+    "check" => {
+      if let Err(problems) = plan.apply(&mut object) {
+        let problems: Vec<_> = problems.iter().map(ToString::to_string).collect();
+        return Err(format!(
+          "not upgrading, because some underground belts would be too long:\n{}",
+          problems.join("\n")
+        ));
+      }
+    }
+    "ignore" => {
+      plan.apply_unchecked(&mut object);
+    }
+    other => {
+      return Err(format!(
+        "--undergrounds should be check or ignore, not {}",
+        other
+      ))
+    }
+  }
+  arguments.write_blueprint(object)
+}
+
+fn upgrade_planner(arguments: &Arguments) -> CliResult<()> {
+  let (plan, _) = upgrade_plan(arguments)?;
+  let label = arguments.option("label").unwrap_or("").to_string();
+  let planner = BlueprintObject::UpgradePlanner(plan.to_planner(label));
+  arguments.write_blueprint(planner)
+}
+
+fn stats(arguments: &Arguments) -> CliResult<()> {
+  let object = decode_string(&arguments.input(0)?)?;
+  let report = match object {
+    BlueprintObject::Blueprint(blueprint) => blueprint.statistics().to_string(),
+    BlueprintObject::BlueprintBook(book) => {
+      let parts: Vec<_> = book
+        .blueprints
+        .iter()
+        .map(|entry| entry.blueprint.statistics())
+        .collect();
+      let mut report = String::new();
+      for part in &parts {
+        report.push_str(&part.to_string());
+      }
+      let total = BlueprintStatistics::total(format!("Total for {}", book.label), &parts);
+      report.push_str(&total.to_string());
+      report
+    }
+    BlueprintObject::UpgradePlanner(_) => {
+      return Err("an upgrade planner has no entities to count".to_string())
+    }
+  };
+  arguments.write_output(report.trim_end())
+}
+
+fn preview(arguments: &Arguments) -> CliResult<()> {
+  let output = arguments
+    .output
+    .as_ref()
+    .ok_or("preview needs an output file, given with -o")?;
+  let mut options = PreviewOptions::default();
+  if let Some(tile_size) = arguments.option("tile-size") {
+    options.tile_size = tile_size
+      .parse()
+      .map_err(|error| describe("tile size", error))?;
+  }
+  let object = decode_string(&arguments.input(0)?)?;
+  match object {
+    BlueprintObject::Blueprint(blueprint) => render_blueprint(&blueprint, &options)
+      .save(output)
+      .map_err(|error| describe(output, error)),
+    BlueprintObject::BlueprintBook(book) => {
+      let output = Path::new(output);
+      let stem = output
+        .file_stem()
+        .map_or("preview".into(), |stem| stem.to_string_lossy());
+      for entry in &book.blueprints {
+        let path = output.with_file_name(format!("{}-{}.png", stem, entry.index));
+        render_blueprint(&entry.blueprint, &options)
+          .save(&path)
+          .map_err(|error| describe(&path.to_string_lossy(), error))?;
+      }
+      Ok(())
+    }
+    BlueprintObject::UpgradePlanner(_) => Err("an upgrade planner has nothing to draw".to_string()),
+  }
+}
+
+fn single_blueprint(input: &str) -> CliResult<Blueprint> {
+  match decode_string(input)? {
+    BlueprintObject::Blueprint(blueprint) => Ok(blueprint),
+    BlueprintObject::BlueprintBook(_) => Err("expected a blueprint, not a book".to_string()),
+    BlueprintObject::UpgradePlanner(_) => {
+      Err("expected a blueprint, not an upgrade planner".to_string())
+    }
+  }
+}
+
+fn parse_pair(context: &str, text: &str, separator: char) -> CliResult<[f64; 2]> {
+  let parts: Vec<f64> = text
+    .split(separator)
+    .map(str::parse)
+    .collect::<Result<_, _>>()
+    .map_err(|error| describe(context, error))?;
+  match parts[..] {
+    [x, y] => Ok([x, y]),
+    _ => Err(format!(
+      "{} should be two numbers separated by {}",
+      context, separator
+    )),
+  }
+}
+
+fn diff(arguments: &Arguments) -> CliResult<()> {
+  if arguments.positional.len() != 2 {
+    return Err("diff needs two blueprint string files".to_string());
+  }
+  let before = single_blueprint(&arguments.input(0)?)?;
+  let after = single_blueprint(&arguments.input(1)?)?;
+  let alignment = match arguments.option("offset") {
+    None => DiffAlignment::Exact,
+    Some("auto") => DiffAlignment::Automatic,
+    Some(offset) => DiffAlignment::Offset(parse_pair("offset", offset, ',')?),
+  };
+  let difference = diff_blueprints(&before, &after, alignment);
+  if let Some(png) = arguments.option("png") {
+    difference
+      .png_overlay(&after, &PreviewOptions::default())
+      .save(png)
+      .map_err(|error| describe(png, error))?;
+  }
+  let mut report = difference.to_string();
+  if !difference.is_empty() {
+    report.push('\n');
+    report.push_str(&difference.text_overlay(&after));
+  }
+  arguments.write_output(report.trim_end())
+}
+
+fn conflict_policy(arguments: &Arguments) -> CliResult<ConflictPolicy> {
+  match arguments.option("conflicts") {
+    None | Some("error") => Ok(ConflictPolicy::Error),
+    Some("overwrite") => Ok(ConflictPolicy::Overwrite),
+    Some("skip") => Ok(ConflictPolicy::Skip),
+    Some(other) => Err(format!("unknown conflict policy: {}", other)),
+  }
+}
+
+fn stamp(arguments: &Arguments) -> CliResult<()> {
+  if arguments.positional.len() != 2 {
+    return Err(
+      "stamp needs a base blueprint file and a blueprint file to copy into it".to_string(),
+    );
+  }
+  let mut base = single_blueprint(&arguments.input(0)?)?;
+  let other = single_blueprint(&arguments.input(1)?)?;
+  let offset = match arguments.option("offset") {
+    None => [0.0, 0.0],
+    Some(offset) => parse_pair("offset", offset, ',')?,
+  };
+  let rotation = match arguments.option("rotate").unwrap_or("0") {
+    "0" => Rotation::None,
+    "90" => Rotation::Clockwise,
+    "180" => Rotation::Half,
+    "270" => Rotation::Counterclockwise,
+    other => {
+      return Err(format!(
+        "can only rotate by 0, 90, 180 or 270, not {}",
+        other
+      ))
+    }
+  };
+  base
+    .stamp_with_policy(&other, offset, rotation, conflict_policy(arguments)?)
+    .map_err(|error| describe("stamping", error))?;
+  arguments.write_blueprint(BlueprintObject::Blueprint(base))
+}
+
+fn tile(arguments: &Arguments) -> CliResult<()> {
+  let chunk = single_blueprint(&arguments.input(0)?)?;
+  let grid = parse_pair(
+    "grid",
+    arguments.option("grid").ok_or("tile needs a --grid size")?,
+    'x',
+  )?;
+  let spacing = match arguments.option("spacing") {
+    Some(spacing) => parse_pair("spacing", spacing, ',')?,
+    None => {
+      let [[left, right], [top, bottom]] = chunk.footprint().ok_or("the blueprint is empty")?;
+      [(right - left).ceil(), (bottom - top).ceil()]
+    }
+  };
+  let recipes: Vec<&str> = arguments
+    .option("recipes")
+    .map_or(Vec::new(), |recipes| recipes.split(',').collect());
+  let label = format!("{} ({}x{})", chunk.label, grid[0], grid[1]);
+  let result = Blueprint::tiled(
+    label,
+    &chunk,
+    [grid[0] as u32, grid[1] as u32],
+    spacing,
+    conflict_policy(arguments)?,
+    |[column, row], copy| {
+      if recipes.is_empty() {
+        return;
+      }
+      let recipe = recipes[(row * grid[0] as u32 + column) as usize % recipes.len()];
+      for entity in &mut copy.entities {
+        if entity.recipe.is_some() {
+          entity.recipe = Some(recipe.to_string());
+        }
+      }
+    },
+  )
+  .map_err(|error| describe("tiling", error))?;
+  arguments.write_blueprint(BlueprintObject::Blueprint(result))
+}
+
+fn format_signals(signals: &Signals) -> String {
+  let mut signals: Vec<_> = signals
+    .iter()
+    .map(|(signal, count)| format!("{}={}", signal.name, count))
+    .collect();
+  signals.sort();
+  signals.join(" ")
+}
+
+fn circuit(arguments: &Arguments) -> CliResult<()> {
+  let kind = arguments.positional.first().ok_or("which circuit?")?;
+  let period = match arguments.option("period") {
+    Some(period) => period
+      .parse()
+      .map_err(|error| describe("--period", error))?,
+    None => 60,
+  };
+  let mut circuit = Circuit::new();
+  let clock = circuit.clock(SignalID::virtual_signal("signal-T"), period);
+  let output = match kind.as_str() {
+    "clock" => clock,
+    "counter" => {
+      let pulses = circuit.decider(
+        &[clock],
+        DeciderConditions::new(
+          SignalID::virtual_signal("signal-T"),
+          Comparator::Equal,
+          period,
+          SignalID::virtual_signal("signal-C"),
+          false,
+        ),
+      );
+      circuit.counter(pulses)
+    }
+    _ => return Err(format!("unknown circuit: {}", kind)),
+  };
+
+  if let Some(ticks) = arguments.option("simulate") {
+    let ticks: u64 = ticks
+      .parse()
+      .map_err(|error| describe("--simulate", error))?;
+    let mut simulation = circuit.simulate();
+    let mut trace = String::new();
+    while simulation.tick() <= ticks {
+      trace += &format!(
+        "{}: {}\n",
+        simulation.tick(),
+        format_signals(&simulation.bus(output))
+      );
+      simulation.step();
+    }
+    return arguments.write_output(trace.trim_end());
+  }
+  let result = circuit
+    .blueprint(&format!("{} ({} ticks)", kind, period))
+    .map_err(|error| describe("building the circuit", error))?;
+  arguments.write_blueprint(BlueprintObject::Blueprint(result))
+}
+
+fn simulate(arguments: &Arguments) -> CliResult<()> {
+  let blueprint = single_blueprint(&arguments.input(0)?)?;
+  let ticks: u64 = match arguments.option("ticks") {
+    Some(ticks) => ticks.parse().map_err(|error| describe("--ticks", error))?,
+    None => 10,
+  };
+  let watched = match arguments.option("watch") {
+    Some(watched) => Some(
+      watched
+        .split(',')
+        .map(|number| number.trim().parse())
+        .collect::<Result<Vec<i32>, _>>()
+        .map_err(|error| describe("--watch", error))?,
+    ),
+    None => None,
+  };
+  let mut simulation = BlueprintSimulation::new(&blueprint);
+  let mut trace = String::new();
+  while simulation.tick() <= ticks {
+    let entities: Vec<String> = match &watched {
+      Some(watched) => watched
+        .iter()
+        .map(|&number| format!("#{} {}", number, format_signals(&simulation.input(number))))
+        .collect(),
+      None => simulation
+        .outputs()
+        .map(|(number, signals)| format!("#{} {}", number, format_signals(signals)))
+        .collect(),
+    };
+    trace += &format!("{}: {}\n", simulation.tick(), entities.join("; "));
+    simulation.step();
+  }
+  arguments.write_output(trace.trim_end())
+}
+
+fn schedule_record(text: &str) -> CliResult<ScheduleRecord> {
+  let mut parts = text.splitn(2, ':');
+  let station = parts.next().unwrap_or_default();
+  let conditions = match parts.next() {
+    None => Vec::new(),
+    Some("full") => vec![WaitCondition::new(WaitConditionType::Full)],
+    Some("empty") => vec![WaitCondition::new(WaitConditionType::Empty)],
+    Some(seconds) if seconds.ends_with('s') => {
+      let seconds: u32 = seconds
+        .trim_end_matches('s')
+        .parse()
+        .map_err(|error| describe(text, error))?;
+      let ticks = seconds
+        .checked_mul(60)
+        .ok_or_else(|| format!("{}: waiting {} seconds is too long", station, seconds))?;
+      vec![WaitCondition::time(ticks)]
+    }
+    Some(other) => {
+      return Err(format!(
+        "{}: wait for full, empty or a number of seconds like 30s, not {}",
+        station, other
+      ))
+    }
+  };
+  Ok(ScheduleRecord::new(station, conditions))
+}
+
+fn station(arguments: &Arguments) -> CliResult<()> {
+  let name = arguments
+    .positional
+    .first()
+    .ok_or("the station needs a name")?;
+  let count = |option: &str, default: u32| -> CliResult<u32> {
+    match arguments.option(option) {
+      Some(count) => count
+        .parse()
+        .map_err(|error| describe(&format!("--{}", option), error)),
+      None => Ok(default),
+    }
+  };
+  let kind = match arguments.option("kind") {
+    None | Some("loading") => StationKind::Loading,
+    Some("unloading") => StationKind::Unloading,
+    Some(other) => {
+      return Err(format!(
+        "--kind should be loading or unloading, not {}",
+        other
+      ))
+    }
+  };
+  let mut options = StationOptions::new(kind, count("wagons", 4)?);
+  options.locomotives = count("locomotives", 1)?;
+  options.both_sides = match count("sides", 2)? {
+    1 => false,
+    2 => true,
+    other => return Err(format!("--sides should be 1 or 2, not {}", other)),
+  };
+  if let Some(chest) = arguments.option("chest") {
+    options.chest = chest.to_string();
+  }
+  if let Some(inserter) = arguments.option("inserter") {
+    options.inserter = inserter.to_string();
+  }
+  if let Some(schedule) = arguments.option("schedule") {
+    options.schedule = Some(
+      schedule
+        .split(',')
+        .map(schedule_record)
+        .collect::<CliResult<_>>()?,
+    );
+  }
+  arguments.write_blueprint(BlueprintObject::Blueprint(rails::station(name, &options)))
+}
+
+fn run() -> CliResult<()> {
+  let mut arguments = std::env::args().skip(1);
+  let command = arguments.next().ok_or(USAGE)?;
+  let arguments: Vec<String> = arguments.collect();
+  // only straight after the command, where it can't be the value of some other option
+  if arguments
+    .first()
+    .is_some_and(|argument| argument == "-h" || argument == "--help")
+  {
+    println!("{}", USAGE);
+    return Ok(());
+  }
+  let arguments = Arguments::parse(arguments.into_iter())?;
+  match command.as_str() {
+    "decode" => decode(&arguments),
+    "encode" => encode(&arguments),
+    "route" => route(&arguments),
+    "pixel-art" => pixel_art(&arguments),
+    "upgrade" => upgrade(&arguments),
+    "upgrade-planner" => upgrade_planner(&arguments),
+    "stats" => stats(&arguments),
+    "preview" => preview(&arguments),
+    "diff" => diff(&arguments),
+    "stamp" => stamp(&arguments),
+    "tile" => tile(&arguments),
+    "circuit" => circuit(&arguments),
+    "simulate" => simulate(&arguments),
+    "station" => station(&arguments),
+    "-h" | "--help" | "help" => {
+      println!("{}", USAGE);
+      Ok(())
+    }
+    _ => Err(format!("unknown command: {}\n\n{}", command, USAGE)),
+  }
+}
+
+fn main() {
+  if let Err(error) = run() {
+    eprintln!("{}", error);
+    process::exit(1);
+  }
+}
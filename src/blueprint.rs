@@ -2,10 +2,13 @@ use base64;
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
+use serde::de::Error as _;
 use serde_derive::{Deserialize, Serialize};
 use serde_json::Result;
 use std::collections::HashMap;
 
+pub use super::version::GameVersion;
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum BlueprintObject {
   #[serde(rename = "blueprint")]
@@ -13,6 +16,9 @@ pub enum BlueprintObject {
 
   #[serde(rename = "blueprint_book")]
   BlueprintBook(BlueprintBook),
+
+  #[serde(rename = "upgrade_planner")]
+  UpgradePlanner(UpgradePlanner),
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, Default)]
@@ -24,6 +30,43 @@ pub struct BlueprintBook {
   pub version: u64,
 }
 
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct UpgradePlanner {
+  pub item: String,
+
+  #[serde(default)]
+  pub label: String,
+
+  pub settings: UpgradeSettings,
+  pub version: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct UpgradeSettings {
+  #[serde(default)]
+  pub mappers: Vec<UpgradeMapper>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct UpgradeMapper {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub from: Option<UpgradeTarget>,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub to: Option<UpgradeTarget>,
+
+  pub index: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct UpgradeTarget {
+  pub name: String,
+
+  /// "entity" or "item"
+  #[serde(rename = "type")]
+  pub target_type: String,
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct BlueprintBookEntry {
   pub index: i32,
@@ -42,6 +85,40 @@ pub struct Blueprint {
   #[serde(default)]
   pub icons: Vec<Icon>,
 
+  /// Only used in the 2.0 format, where wires aren't stored on entities: each one is
+  /// `[entity number, connector, entity number, connector]`. See `version.rs`.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub wires: Vec<[i32; 4]>,
+
+  /// The size of the grid that placing the blueprint snaps to, in tiles.
+  #[serde(
+    rename = "snap-to-grid",
+    default,
+    skip_serializing_if = "Option::is_none"
+  )]
+  pub snap_to_grid: Option<TilePosition>,
+
+  /// Whether the grid is fixed to the world, rather than starting wherever the player first
+  /// places the blueprint.
+  #[serde(
+    rename = "absolute-snapping",
+    default,
+    skip_serializing_if = "Option::is_none"
+  )]
+  pub absolute_snapping: Option<bool>,
+
+  /// Where the world's grid lines are, with absolute snapping.
+  #[serde(
+    rename = "position-relative-to-grid",
+    default,
+    skip_serializing_if = "Option::is_none"
+  )]
+  pub position_relative_to_grid: Option<TilePosition>,
+
+  /// Train schedules, each shared by the locomotives it lists.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub schedules: Vec<Schedule>,
+
   pub version: u64,
 }
 
@@ -52,12 +129,44 @@ pub struct Entity {
   pub position: Position,
   pub direction: Option<u8>,
 
+  /// The 2.0 direction that `direction` was rounded down from, if it was between the 8 that 1.1
+  /// has; `export_shapes` writes it back out while `direction` still matches.
+  #[serde(skip)]
+  pub(crate) direction_16: Option<u8>,
+
+  /// 2.0 only: "normal", "uncommon", "rare", "epic" or "legendary"
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub quality: Option<String>,
+
+  /// Which way a locomotive or wagon faces, as a fraction of a turn clockwise from north.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub orientation: Option<f64>,
+
+  /// Circuit wires, keyed by the circuit connector they attach to (combinators have an input
+  /// connector 1 and an output connector 2; most other entities only have 1).
   #[serde(default)]
   pub connections: HashMap<u8, Connection>,
 
-  #[serde(default)]
+  pub control_behavior: Option<ControlBehavior>,
+
+  /// Items to insert, such as modules, counted by name. 2.0 lists which inventory slots they
+  /// go in instead; those lists are read into these counts (leaving out any item qualities), and
+  /// `export_shapes` writes them back out as `insert_plans`.
+  #[serde(
+    default,
+    deserialize_with = "deserialize_items",
+    skip_serializing_if = "HashMap::is_empty"
+  )]
   pub items: HashMap<String, u32>,
 
+  /// The 2.0 form of `items`, only filled in by `export_shapes`.
+  #[serde(
+    rename = "items",
+    skip_deserializing,
+    skip_serializing_if = "Vec::is_empty"
+  )]
+  pub(crate) insert_plans: Vec<ItemInsertPlan>,
+
   pub recipe: Option<String>,
   pub bar: Option<u8>,
   pub infinity_settings: Option<InfinitySettings>,
@@ -84,6 +193,74 @@ pub struct Entity {
   pub station: Option<String>,
 }
 
+/// One item that 2.0 inserts into an entity, and where it goes.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct ItemInsertPlan {
+  pub id: ItemID,
+  pub items: ItemInventoryPositions,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct ItemID {
+  pub name: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub quality: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug, Default)]
+pub struct ItemInventoryPositions {
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub in_inventory: Vec<InventoryPosition>,
+  /// how many go in the entity's equipment grid
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub grid_count: Option<u32>,
+}
+
+/// A slot in one of an entity's inventories, numbered as in the game's `defines.inventory`.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct InventoryPosition {
+  pub inventory: u32,
+  pub stack: u32,
+  /// 1 if left out
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub count: Option<u32>,
+}
+
+impl ItemInsertPlan {
+  /// How many of the item go in altogether.
+  pub fn count(&self) -> u32 {
+    self
+      .items
+      .in_inventory
+      .iter()
+      .map(|position| position.count.unwrap_or(1))
+      .sum::<u32>()
+      + self.items.grid_count.unwrap_or(0)
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EntityItems {
+  Counts(HashMap<String, u32>),
+  InsertPlans(Vec<ItemInsertPlan>),
+}
+
+fn deserialize_items<'de, D: serde::Deserializer<'de>>(
+  deserializer: D,
+) -> std::result::Result<HashMap<String, u32>, D::Error> {
+  Ok(match serde::Deserialize::deserialize(deserializer)? {
+    EntityItems::Counts(counts) => counts,
+    EntityItems::InsertPlans(plans) => {
+      let mut counts = HashMap::new();
+      for plan in plans {
+        *counts.entry(plan.id.name.clone()).or_insert(0) += plan.count();
+      }
+      counts
+    }
+  })
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Tile {
   pub name: String,
@@ -96,16 +273,36 @@ pub struct Icon {
   pub signal: SignalID,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
 pub struct SignalID {
   pub name: String,
 
-  #[serde(rename = "type")]
+  /// 2.0 leaves this out for items
+  #[serde(rename = "type", default)]
   pub signal_type: SignalType,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+impl SignalID {
+  pub fn item(name: &str) -> SignalID {
+    SignalID {
+      name: name.to_string(),
+      signal_type: SignalType::Item,
+    }
+  }
+
+  /// Letters, digits, colors and the special `signal-each`, `signal-anything` and
+  /// `signal-everything`.
+  pub fn virtual_signal(name: &str) -> SignalID {
+    SignalID {
+      name: name.to_string(),
+      signal_type: SignalType::Virtual,
+    }
+  }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug, Default)]
 pub enum SignalType {
+  #[default]
   #[serde(rename = "item")]
   Item,
 
@@ -116,34 +313,491 @@ pub enum SignalType {
   Virtual,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Debug, Default)]
+pub struct TilePosition {
+  pub x: i32,
+  pub y: i32,
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug, Default)]
 pub struct Position {
   pub x: f64,
   pub y: f64,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
 pub struct Connection {
-  pub first_point: ConnectionPoint,
-  pub second_point: ConnectionPoint,
-}
-
-#[derive(Clone, Serialize, Deserialize, Debug)]
-pub struct ConnectionPoint {
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
   pub red: Vec<ConnectionData>,
+
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
   pub green: Vec<ConnectionData>,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
 pub struct ConnectionData {
-  pub entity_id: u8,
-  pub circuit_id: u8,
+  pub entity_id: i32,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub circuit_id: Option<u8>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum WireColor {
+  Red,
+  Green,
+}
+
+impl Connection {
+  pub fn wires(&self, color: WireColor) -> &Vec<ConnectionData> {
+    match color {
+      WireColor::Red => &self.red,
+      WireColor::Green => &self.green,
+    }
+  }
+
+  pub fn wires_mut(&mut self, color: WireColor) -> &mut Vec<ConnectionData> {
+    match color {
+      WireColor::Red => &mut self.red,
+      WireColor::Green => &mut self.green,
+    }
+  }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct ControlBehavior {
+  /// constant combinator outputs
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub filters: Vec<ConstantCombinatorFilter>,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub circuit_condition: Option<CircuitCondition>,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub arithmetic_conditions: Option<ArithmeticConditions>,
+
+  /// 2.0 deciders with a single condition and output are read into this too; `import_shapes`
+  /// leaves any others in `other`, as they were.
+  #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+  pub decider_conditions: Option<DeciderConditions>,
+
+  /// `decider_conditions` as decoded, in either version's form, for `import_shapes` to sort out.
+  #[serde(rename = "decider_conditions", default, skip_serializing)]
+  pub(crate) decider_settings: Option<DeciderSettings>,
+
+  /// 2.0 constant combinator outputs, which `import_shapes` reads into `filters`, and
+  /// `export_shapes` writes `filters` back out as.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) sections: Option<LogisticSections>,
+
+  /// The 2.0 form of `decider_conditions`, only filled in by `export_shapes`.
+  #[serde(
+    rename = "decider_conditions",
+    skip_deserializing,
+    skip_serializing_if = "Option::is_none"
+  )]
+  pub(crate) decider_parameters: Option<DeciderCombinatorParameters>,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub use_colors: Option<bool>,
+
+  /// belts: whether `circuit_condition` turns the belt on and off
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub circuit_enable_disable: Option<bool>,
+
+  /// inserters: 0 to be turned on and off by `circuit_condition`, 3 to ignore it
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub circuit_mode_of_operation: Option<u8>,
+
+  /// belts and inserters: whether to put what they're carrying onto the network
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub circuit_read_hand_contents: Option<bool>,
+
+  /// inserters: 0 to pulse what they pick up, 1 to hold it for as long as it's in hand
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub circuit_hand_read_mode: Option<u8>,
+
+  /// belts: 0 to pulse items as they arrive, 1 to hold the whole contents
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub circuit_contents_read_mode: Option<u8>,
+
+  /// Everything we don't model yet, kept so that decoding and re-encoding doesn't lose it.
+  #[serde(flatten)]
+  pub other: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
-pub struct InfinitySettings {}
+pub struct ConstantCombinatorFilter {
+  pub signal: SignalID,
+  pub count: i32,
+  pub index: u32,
+}
+
+/// 2.0's constant combinator outputs, in sections that can each be switched off.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug, Default)]
+pub struct LogisticSections {
+  #[serde(default)]
+  pub sections: Vec<LogisticSection>,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct LogisticSection {
+  pub index: u32,
+
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub filters: Vec<LogisticSectionFilter>,
+
+  /// the game assumes true if it's missing
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub active: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct LogisticSectionFilter {
+  pub index: u32,
+
+  #[serde(flatten)]
+  pub signal: SignalID,
+
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub quality: Option<String>,
+
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub comparator: Option<String>,
+
+  #[serde(default)]
+  pub count: i32,
+}
+
+#[derive(Clone, Deserialize, Debug, Default)]
+pub struct Schedule {
+  /// entity numbers
+  pub locomotives: Vec<i32>,
+
+  #[serde(deserialize_with = "deserialize_records")]
+  pub schedule: Vec<ScheduleRecord>,
+
+  /// 2.0 puts the records in an object, next to the interrupts (which aren't kept); set by
+  /// `export_shapes`.
+  #[serde(skip)]
+  pub(crate) nested: bool,
+}
+
+impl Schedule {
+  pub fn new(locomotives: Vec<i32>, schedule: Vec<ScheduleRecord>) -> Schedule {
+    Schedule {
+      locomotives,
+      schedule,
+      nested: false,
+    }
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScheduleRecords {
+  List(Vec<ScheduleRecord>),
+  Nested { records: Vec<ScheduleRecord> },
+}
+
+fn deserialize_records<'de, D: serde::Deserializer<'de>>(
+  deserializer: D,
+) -> std::result::Result<Vec<ScheduleRecord>, D::Error> {
+  Ok(match serde::Deserialize::deserialize(deserializer)? {
+    ScheduleRecords::List(records) | ScheduleRecords::Nested { records } => records,
+  })
+}
+
+impl serde::Serialize for Schedule {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    use serde::ser::SerializeStruct;
+    #[derive(Serialize)]
+    struct Nested<'a> {
+      records: &'a Vec<ScheduleRecord>,
+    }
+    let mut result = serializer.serialize_struct("Schedule", 2)?;
+    result.serialize_field("locomotives", &self.locomotives)?;
+    if self.nested {
+      result.serialize_field(
+        "schedule",
+        &Nested {
+          records: &self.schedule,
+        },
+      )?;
+    } else {
+      result.serialize_field("schedule", &self.schedule)?;
+    }
+    result.end()
+  }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct ScheduleRecord {
+  pub station: String,
+
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub wait_conditions: Vec<WaitCondition>,
+
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub temporary: Option<bool>,
+}
+
+impl ScheduleRecord {
+  pub fn new(station: &str, wait_conditions: Vec<WaitCondition>) -> ScheduleRecord {
+    ScheduleRecord {
+      station: station.to_string(),
+      wait_conditions,
+      temporary: None,
+    }
+  }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct WaitCondition {
+  #[serde(rename = "type")]
+  pub condition_type: WaitConditionType,
+
+  /// how this condition combines with the ones before it
+  pub compare_type: CompareType,
+
+  /// for time and inactivity
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub ticks: Option<u32>,
+
+  /// for item, fluid and circuit conditions
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub condition: Option<CircuitCondition>,
+}
+
+impl WaitCondition {
+  pub fn new(condition_type: WaitConditionType) -> WaitCondition {
+    WaitCondition {
+      condition_type,
+      compare_type: CompareType::Or,
+      ticks: None,
+      condition: None,
+    }
+  }
+
+  pub fn time(ticks: u32) -> WaitCondition {
+    WaitCondition {
+      ticks: Some(ticks),
+      ..WaitCondition::new(WaitConditionType::Time)
+    }
+  }
+
+  pub fn inactivity(ticks: u32) -> WaitCondition {
+    WaitCondition {
+      ticks: Some(ticks),
+      ..WaitCondition::new(WaitConditionType::Inactivity)
+    }
+  }
+
+  pub fn and(self) -> WaitCondition {
+    WaitCondition {
+      compare_type: CompareType::And,
+      ..self
+    }
+  }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum WaitConditionType {
+  Time,
+  Inactivity,
+  Full,
+  Empty,
+  ItemCount,
+  FluidCount,
+  Circuit,
+  PassengerPresent,
+  PassengerNotPresent,
+  RobotsInactive,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CompareType {
+  And,
+  Or,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
+pub struct CircuitCondition {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub first_signal: Option<SignalID>,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub second_signal: Option<SignalID>,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub constant: Option<i32>,
+
+  #[serde(default)]
+  pub comparator: Comparator,
+}
+
+/// What an arithmetic combinator computes: `first operation second`, each side being a signal
+/// or a constant.
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
+pub struct ArithmeticConditions {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub first_signal: Option<SignalID>,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub first_constant: Option<i32>,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub second_signal: Option<SignalID>,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub second_constant: Option<i32>,
+
+  pub operation: ArithmeticOperation,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub output_signal: Option<SignalID>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
+pub enum ArithmeticOperation {
+  #[serde(rename = "*")]
+  Multiply,
+
+  #[serde(rename = "/")]
+  Divide,
+
+  #[serde(rename = "+")]
+  Add,
+
+  #[serde(rename = "-")]
+  Subtract,
+
+  #[serde(rename = "%")]
+  Modulo,
+
+  #[serde(rename = "^")]
+  Power,
+
+  #[serde(rename = "<<")]
+  ShiftLeft,
+
+  #[serde(rename = ">>")]
+  ShiftRight,
+
+  #[serde(rename = "AND")]
+  And,
+
+  #[serde(rename = "OR")]
+  Or,
+
+  #[serde(rename = "XOR")]
+  Xor,
+}
+
+/// What a decider combinator tests, and what it outputs while the test passes.
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
+pub struct DeciderConditions {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub first_signal: Option<SignalID>,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub second_signal: Option<SignalID>,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub constant: Option<i32>,
+
+  pub comparator: Comparator,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub output_signal: Option<SignalID>,
+
+  /// output the input's count of the output signal rather than 1; the game assumes true if
+  /// it's missing
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub copy_count_from_input: Option<bool>,
+}
+
+/// 2.0's decider combinator settings, which can have any number of conditions and outputs.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub struct DeciderCombinatorParameters {
+  #[serde(default)]
+  pub conditions: Vec<DeciderCombinatorCondition>,
+
+  #[serde(default)]
+  pub outputs: Vec<DeciderCombinatorOutput>,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub struct DeciderCombinatorCondition {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub first_signal: Option<SignalID>,
+
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub second_signal: Option<SignalID>,
+
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub constant: Option<i32>,
+
+  #[serde(default)]
+  pub comparator: Comparator,
+
+  /// how this condition joins the ones before it: "and" or "or"
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub compare_type: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub struct DeciderCombinatorOutput {
+  pub signal: SignalID,
+
+  /// the game assumes true if it's missing
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub copy_count_from_input: Option<bool>,
+
+  /// what to output when not copying the count; the game assumes 1
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub constant: Option<i32>,
+}
+
+/// A decider's settings as decoded: 1.1's, or 2.0's kept as JSON so that any the library can't
+/// model survive being re-encoded.
+#[derive(Clone, Deserialize, Debug)]
+#[serde(untagged)]
+pub(crate) enum DeciderSettings {
+  Conditions(DeciderConditions),
+  Parameters(serde_json::Value),
+}
+
+/// 2.0 leaves the comparator out of conditions where it's `<`, the default.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug, Default)]
+pub enum Comparator {
+  #[default]
+  #[serde(rename = "<")]
+  Less,
+
+  #[serde(rename = ">")]
+  Greater,
+
+  #[serde(rename = "=")]
+  Equal,
+
+  #[serde(rename = "\u{2265}")]
+  GreaterOrEqual,
+
+  #[serde(rename = "\u{2264}")]
+  LessOrEqual,
+
+  #[serde(rename = "\u{2260}")]
+  NotEqual,
+}
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct InfinitySettings {}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
 pub enum UndergroundBeltOrLoaderType {
   #[serde(rename = "input")]
   Input,
@@ -164,8 +818,20 @@ pub struct SpeakerParameter {}
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct SpeakerAlertParameter {}
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
-pub struct Color {}
+/// Channels are from 0 to 1.
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct Color {
+  pub r: f64,
+  pub g: f64,
+  pub b: f64,
+
+  #[serde(default = "default_alpha")]
+  pub a: f64,
+}
+
+fn default_alpha() -> f64 {
+  1.0
+}
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum SplitterDirection {
@@ -193,20 +859,44 @@ impl EncodedBlueprint {
   }
 
   pub fn decode(&self) -> Result<BlueprintObject> {
-    let bytes: Vec<u8> = base64::decode(self.get_base64()).unwrap();
-    serde_json::from_reader(ZlibDecoder::new(&bytes[..]))
+    let bytes: Vec<u8> = base64::decode(self.get_base64()).map_err(serde_json::Error::custom)?;
+    let mut object: BlueprintObject = serde_json::from_reader(ZlibDecoder::new(&bytes[..]))?;
+    object.visit_blueprints(Blueprint::import_shapes);
+    Ok(object)
   }
 }
 
 impl BlueprintObject {
   pub fn encode(&self) -> Result<EncodedBlueprint> {
     let mut bytes = Vec::new();
-    serde_json::to_writer(ZlibEncoder::new(&mut bytes, Compression::best()), self)?;
+    serde_json::to_writer(
+      ZlibEncoder::new(&mut bytes, Compression::best()),
+      &self.exported(),
+    )?;
     let mut result = "0".to_string();
     base64::encode_config_buf(&bytes, base64::STANDARD, &mut result);
     Ok(EncodedBlueprint(result))
   }
 
+  /// Reads blueprint JSON as it appears inside a blueprint string, in whichever format its
+  /// version uses.
+  pub fn from_json(json: &str) -> Result<BlueprintObject> {
+    let mut object: BlueprintObject = serde_json::from_str(json)?;
+    object.visit_blueprints(Blueprint::import_shapes);
+    Ok(object)
+  }
+
+  /// The JSON that `encode` compresses, in the format of the object's version.
+  pub fn to_json_pretty(&self) -> Result<String> {
+    serde_json::to_string_pretty(&self.exported())
+  }
+
+  fn exported(&self) -> BlueprintObject {
+    let mut exported = self.clone();
+    exported.visit_blueprints(Blueprint::export_shapes);
+    exported
+  }
+
   pub fn visit_blueprints(&mut self, mut visitor: impl FnMut(&mut Blueprint)) {
     match self {
       BlueprintObject::Blueprint(blueprint) => (visitor)(blueprint),
@@ -215,6 +905,7 @@ impl BlueprintObject {
           (visitor)(blueprint)
         }
       }
+      BlueprintObject::UpgradePlanner(_) => {}
     }
   }
 }
@@ -226,12 +917,73 @@ impl Blueprint {
     }
   }
 
-  pub fn simple(name: String, entities: Vec<Entity>) -> Blueprint {
+  /// Adds a wire between two (entity number, circuit connector) pairs, on both ends.
+  pub fn connect(&mut self, color: WireColor, first: (i32, u8), second: (i32, u8)) {
+    for &(from, to) in &[(first, second), (second, first)] {
+      // entities are usually numbered in order, so try that before searching
+      let index = match (from.0 as usize).checked_sub(1) {
+        Some(index)
+          if self
+            .entities
+            .get(index)
+            .is_some_and(|entity| entity.entity_number == from.0) =>
+        {
+          index
+        }
+        _ => self
+          .entities
+          .iter()
+          .position(|entity| entity.entity_number == from.0)
+          .expect("connecting an entity that isn't in the blueprint"),
+      };
+      let entity = &mut self.entities[index];
+      let wires = entity
+        .connections
+        .entry(from.1)
+        .or_default()
+        .wires_mut(color);
+      let data = ConnectionData {
+        entity_id: to.0,
+        circuit_id: Some(to.1),
+      };
+      if !wires.contains(&data) {
+        wires.push(data);
+      }
+    }
+  }
+
+  /// Makes the blueprint snap to a world-aligned grid of cells the size of `template`, which is
+  /// the `[[left, right], [top, bottom]]` edges of the area it was designed for, like a city
+  /// block. The game puts the corner of a grid cell at the blueprint's origin, so everything is
+  /// moved to put the template's top left corner there. `offset` moves the world's grid lines.
+  pub fn align_to_grid(&mut self, template: [[f64; 2]; 2], offset: [i32; 2]) {
+    let [[left, right], [top, bottom]] = template;
+    for position in self
+      .entities
+      .iter_mut()
+      .map(|entity| &mut entity.position)
+      .chain(self.tiles.iter_mut().map(|tile| &mut tile.position))
+    {
+      position.x -= left;
+      position.y -= top;
+    }
+    self.snap_to_grid = Some(TilePosition {
+      x: (right - left).round() as i32,
+      y: (bottom - top).round() as i32,
+    });
+    self.absolute_snapping = Some(true);
+    self.position_relative_to_grid = Some(TilePosition {
+      x: offset[0],
+      y: offset[1],
+    });
+  }
+
+  pub fn simple(name: String, entities: Vec<Entity>, version: GameVersion) -> Blueprint {
     let mut result = Blueprint {
       item: "blueprint".to_string(),
       label: name,
       entities: entities,
-      version: 68722819072,
+      version: version.packed(),
       ..Default::default()
     };
     result.renumber_entities();
@@ -240,7 +992,7 @@ impl Blueprint {
 }
 
 impl BlueprintBook {
-  pub fn simple(name: String, blueprints: Vec<Blueprint>) -> BlueprintBook {
+  pub fn simple(name: String, blueprints: Vec<Blueprint>, version: GameVersion) -> BlueprintBook {
     BlueprintBook {
       item: "blueprint-book".to_string(),
       label: name,
@@ -253,7 +1005,7 @@ impl BlueprintBook {
         })
         .collect(),
       active_index: 0,
-      version: 68722819072,
+      version: version.packed(),
       ..Default::default()
     }
   }
@@ -0,0 +1,1290 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::blueprint::*;
+use super::prototypes::circuit_wire_reach;
+
+/// The signals on a circuit network. Signals with a count of zero are left out.
+pub type Signals = HashMap<SignalID, i32>;
+
+pub const EACH: &str = "signal-each";
+pub const ANYTHING: &str = "signal-anything";
+pub const EVERYTHING: &str = "signal-everything";
+
+fn is_special(signal: &SignalID, name: &str) -> bool {
+  signal.signal_type == SignalType::Virtual && signal.name == name
+}
+
+/// Adds `count` of `signal`, with the game's wrapping arithmetic, forgetting the signal if it
+/// comes to zero.
+pub fn add_signal(signals: &mut Signals, signal: &SignalID, count: i32) {
+  if count == 0 {
+    return;
+  }
+  let total = signals.entry(signal.clone()).or_insert(0);
+  *total = total.wrapping_add(count);
+  if *total == 0 {
+    signals.remove(signal);
+  }
+}
+
+pub fn add_signals(signals: &mut Signals, other: &Signals) {
+  for (signal, &count) in other {
+    add_signal(signals, signal, count);
+  }
+}
+
+impl Comparator {
+  pub fn holds(self, left: i32, right: i32) -> bool {
+    match self {
+      Comparator::Less => left < right,
+      Comparator::Greater => left > right,
+      Comparator::Equal => left == right,
+      Comparator::GreaterOrEqual => left >= right,
+      Comparator::LessOrEqual => left <= right,
+      Comparator::NotEqual => left != right,
+    }
+  }
+}
+
+impl ArithmeticOperation {
+  /// With the game's 32 bit wrapping arithmetic; dividing by zero gives zero.
+  pub fn apply(self, left: i32, right: i32) -> i32 {
+    match self {
+      ArithmeticOperation::Multiply => left.wrapping_mul(right),
+      ArithmeticOperation::Divide => left.checked_div(right).unwrap_or(0),
+      ArithmeticOperation::Add => left.wrapping_add(right),
+      ArithmeticOperation::Subtract => left.wrapping_sub(right),
+      ArithmeticOperation::Modulo => left.checked_rem(right).unwrap_or(0),
+      ArithmeticOperation::Power => {
+        if right < 0 {
+          0
+        } else {
+          left.wrapping_pow(right as u32)
+        }
+      }
+      ArithmeticOperation::ShiftLeft => left.wrapping_shl(right as u32),
+      ArithmeticOperation::ShiftRight => left.wrapping_shr(right as u32),
+      ArithmeticOperation::And => left & right,
+      ArithmeticOperation::Or => left | right,
+      ArithmeticOperation::Xor => left ^ right,
+    }
+  }
+}
+
+/// One side of an arithmetic operation or comparison.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Operand {
+  Signal(SignalID),
+  Constant(i32),
+}
+
+impl From<SignalID> for Operand {
+  fn from(signal: SignalID) -> Operand {
+    Operand::Signal(signal)
+  }
+}
+
+impl From<i32> for Operand {
+  fn from(constant: i32) -> Operand {
+    Operand::Constant(constant)
+  }
+}
+
+impl ArithmeticConditions {
+  pub fn new(
+    first: impl Into<Operand>,
+    operation: ArithmeticOperation,
+    second: impl Into<Operand>,
+    output: SignalID,
+  ) -> ArithmeticConditions {
+    let (first_signal, first_constant) = match first.into() {
+      Operand::Signal(signal) => (Some(signal), None),
+      Operand::Constant(constant) => (None, Some(constant)),
+    };
+    let (second_signal, second_constant) = match second.into() {
+      Operand::Signal(signal) => (Some(signal), None),
+      Operand::Constant(constant) => (None, Some(constant)),
+    };
+    ArithmeticConditions {
+      first_signal,
+      first_constant,
+      second_signal,
+      second_constant,
+      operation,
+      output_signal: Some(output),
+    }
+  }
+}
+
+impl DeciderConditions {
+  /// Outputs `output` while `first comparator second` holds: the input's count of it if
+  /// `copy_count`, otherwise 1.
+  pub fn new(
+    first: SignalID,
+    comparator: Comparator,
+    second: impl Into<Operand>,
+    output: SignalID,
+    copy_count: bool,
+  ) -> DeciderConditions {
+    let (second_signal, constant) = match second.into() {
+      Operand::Signal(signal) => (Some(signal), None),
+      Operand::Constant(constant) => (None, Some(constant)),
+    };
+    DeciderConditions {
+      first_signal: Some(first),
+      second_signal,
+      constant,
+      comparator,
+      output_signal: Some(output),
+      copy_count_from_input: Some(copy_count),
+    }
+  }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Combinator {
+  Constant(Vec<(SignalID, i32)>),
+  Arithmetic(ArithmeticConditions),
+  Decider(DeciderConditions),
+}
+
+fn arithmetic(conditions: &ArithmeticConditions, input: &Signals) -> Signals {
+  let mut result = Signals::new();
+  let output = match &conditions.output_signal {
+    Some(output) => output,
+    None => return result,
+  };
+  let value = |signal: &Option<SignalID>, constant: Option<i32>| match signal {
+    Some(signal) => input.get(signal).cloned().unwrap_or(0),
+    None => constant.unwrap_or(0),
+  };
+  match &conditions.first_signal {
+    Some(first) if is_special(first, EACH) => {
+      for (signal, &count) in input {
+        let second = match &conditions.second_signal {
+          Some(second) if is_special(second, EACH) => count,
+          _ => value(&conditions.second_signal, conditions.second_constant),
+        };
+        let computed = conditions.operation.apply(count, second);
+        if is_special(output, EACH) {
+          add_signal(&mut result, signal, computed);
+        } else {
+          add_signal(&mut result, output, computed);
+        }
+      }
+    }
+    _ => {
+      let computed = conditions.operation.apply(
+        value(&conditions.first_signal, conditions.first_constant),
+        value(&conditions.second_signal, conditions.second_constant),
+      );
+      add_signal(&mut result, output, computed);
+    }
+  }
+  result
+}
+
+fn decider(conditions: &DeciderConditions, input: &Signals) -> Signals {
+  let mut result = Signals::new();
+  let (first, output) = match (&conditions.first_signal, &conditions.output_signal) {
+    (Some(first), Some(output)) => (first, output),
+    _ => return result,
+  };
+  let copy = conditions.copy_count_from_input.unwrap_or(true);
+  let second = match &conditions.second_signal {
+    Some(signal) => input.get(signal).cloned().unwrap_or(0),
+    None => conditions.constant.unwrap_or(0),
+  };
+  let holds = |count: i32| conditions.comparator.holds(count, second);
+
+  if is_special(first, EACH) {
+    for (signal, &count) in input {
+      if holds(count) {
+        let amount = if copy { count } else { 1 };
+        if is_special(output, EACH) {
+          add_signal(&mut result, signal, amount);
+        } else {
+          add_signal(&mut result, output, amount);
+        }
+      }
+    }
+    return result;
+  }
+
+  let passes = if is_special(first, EVERYTHING) {
+    input.values().all(|&count| holds(count))
+  } else if is_special(first, ANYTHING) {
+    input.values().any(|&count| holds(count))
+  } else {
+    holds(input.get(first).cloned().unwrap_or(0))
+  };
+  if passes {
+    if is_special(output, EVERYTHING) {
+      for (signal, &count) in input {
+        add_signal(&mut result, signal, if copy { count } else { 1 });
+      }
+    } else if copy {
+      add_signal(&mut result, output, input.get(output).cloned().unwrap_or(0));
+    } else {
+      add_signal(&mut result, output, 1);
+    }
+  }
+  result
+}
+
+impl Combinator {
+  /// What the combinator outputs on the tick after it sees `input`.
+  pub fn evaluate(&self, input: &Signals) -> Signals {
+    match self {
+      Combinator::Constant(signals) => {
+        let mut result = Signals::new();
+        for (signal, count) in signals {
+          add_signal(&mut result, signal, *count);
+        }
+        result
+      }
+      Combinator::Arithmetic(conditions) => arithmetic(conditions, input),
+      Combinator::Decider(conditions) => decider(conditions, input),
+    }
+  }
+
+  /// Constant combinators only have one circuit connector; the others read from connector 1
+  /// and write to connector 2.
+  pub fn output_connector(&self) -> u8 {
+    match self {
+      Combinator::Constant(_) => 1,
+      _ => 2,
+    }
+  }
+
+  /// The combinator that `entity` is, with its settings, if it's a combinator at all. A
+  /// constant combinator that's been switched off outputs nothing. 2.0 deciders whose settings
+  /// 1.1 can't express (kept in `other`) aren't counted as combinators.
+  pub fn from_entity(entity: &Entity) -> Option<Combinator> {
+    let default = ControlBehavior::default();
+    let behavior = entity.control_behavior.as_ref().unwrap_or(&default);
+    match entity.name.as_str() {
+      "constant-combinator" => {
+        let switched_off = behavior.other.get("is_on") == Some(&serde_json::Value::Bool(false));
+        Some(Combinator::Constant(if switched_off {
+          Vec::new()
+        } else {
+          behavior
+            .filters
+            .iter()
+            .map(|filter| (filter.signal.clone(), filter.count))
+            .collect()
+        }))
+      }
+      "arithmetic-combinator" => Some(Combinator::Arithmetic(
+        behavior
+          .arithmetic_conditions
+          .clone()
+          .unwrap_or(ArithmeticConditions {
+            first_signal: None,
+            first_constant: None,
+            second_signal: None,
+            second_constant: None,
+            operation: ArithmeticOperation::Multiply,
+            output_signal: None,
+          }),
+      )),
+      "decider-combinator" if behavior.other.contains_key("decider_conditions") => None,
+      "decider-combinator" => Some(Combinator::Decider(
+        behavior
+          .decider_conditions
+          .clone()
+          .unwrap_or(DeciderConditions {
+            first_signal: None,
+            second_signal: None,
+            constant: None,
+            comparator: Comparator::Less,
+            output_signal: None,
+            copy_count_from_input: None,
+          }),
+      )),
+      _ => None,
+    }
+  }
+
+  /// The combinator facing north, at the origin.
+  pub fn entity(&self) -> Entity {
+    let (name, control_behavior) = match self {
+      Combinator::Constant(signals) => (
+        "constant-combinator",
+        ControlBehavior {
+          filters: signals
+            .iter()
+            .enumerate()
+            .map(|(index, (signal, count))| ConstantCombinatorFilter {
+              signal: signal.clone(),
+              count: *count,
+              index: index as u32 + 1,
+            })
+            .collect(),
+          ..Default::default()
+        },
+      ),
+      Combinator::Arithmetic(conditions) => (
+        "arithmetic-combinator",
+        ControlBehavior {
+          arithmetic_conditions: Some(conditions.clone()),
+          ..Default::default()
+        },
+      ),
+      Combinator::Decider(conditions) => (
+        "decider-combinator",
+        ControlBehavior {
+          decider_conditions: Some(conditions.clone()),
+          ..Default::default()
+        },
+      ),
+    };
+    Entity {
+      name: name.to_string(),
+      direction: Some(0),
+      control_behavior: Some(control_behavior),
+      ..Default::default()
+    }
+  }
+}
+
+/// A set of combinator outputs that add up, read by other combinators. It becomes one red or
+/// green network in the blueprint.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Bus(usize);
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct CombinatorId(pub usize);
+
+#[derive(Clone, Debug)]
+struct Node {
+  combinator: Combinator,
+  inputs: Vec<Bus>,
+  outputs: Vec<Bus>,
+}
+
+/// A circuit put together out of combinators. Each combinator reads the sum of the buses it's
+/// given, and adds its output to the buses it writes to; writing to a bus that it also reads
+/// makes a loop, which is how memory cells, counters and clocks work. Since each bus becomes
+/// a red or green wire, a combinator can read at most two buses and write at most two.
+#[derive(Clone, Debug, Default)]
+pub struct Circuit {
+  nodes: Vec<Node>,
+  buses: usize,
+}
+
+#[derive(Clone, Debug)]
+pub enum CircuitError {
+  /// a combinator reads or writes more than two buses
+  TooManyBuses(CombinatorId),
+  /// this bus can't be given a color without a combinator reading, or writing, two buses of
+  /// the same color
+  Coloring(Bus),
+  /// two combinators on this bus ended up too far apart for a wire
+  TooFar(Bus),
+}
+
+impl fmt::Display for CircuitError {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CircuitError::TooManyBuses(CombinatorId(index)) => write!(
+        formatter,
+        "combinator {} reads or writes more than two buses",
+        index
+      ),
+      CircuitError::Coloring(Bus(index)) => write!(
+        formatter,
+        "bus {} would share a wire color with another bus on the same combinator",
+        index
+      ),
+      CircuitError::TooFar(Bus(index)) => write!(
+        formatter,
+        "bus {} has combinators too far apart to wire together",
+        index
+      ),
+    }
+  }
+}
+
+impl std::error::Error for CircuitError {}
+
+impl Circuit {
+  pub fn new() -> Circuit {
+    Circuit::default()
+  }
+
+  pub fn bus(&mut self) -> Bus {
+    self.buses += 1;
+    Bus(self.buses - 1)
+  }
+
+  pub fn add(&mut self, combinator: Combinator, inputs: &[Bus], outputs: &[Bus]) -> CombinatorId {
+    self.nodes.push(Node {
+      combinator,
+      inputs: inputs.to_vec(),
+      outputs: outputs.to_vec(),
+    });
+    CombinatorId(self.nodes.len() - 1)
+  }
+
+  /// A constant combinator, on a bus of its own.
+  pub fn constant(&mut self, signals: &[(SignalID, i32)]) -> Bus {
+    let output = self.bus();
+    self.add(Combinator::Constant(signals.to_vec()), &[], &[output]);
+    output
+  }
+
+  /// An arithmetic combinator reading `inputs`, on a bus of its own.
+  pub fn arithmetic(&mut self, inputs: &[Bus], conditions: ArithmeticConditions) -> Bus {
+    let output = self.bus();
+    self.add(Combinator::Arithmetic(conditions), inputs, &[output]);
+    output
+  }
+
+  /// A decider combinator reading `inputs`, on a bus of its own.
+  pub fn decider(&mut self, inputs: &[Bus], conditions: DeciderConditions) -> Bus {
+    let output = self.bus();
+    self.add(Combinator::Decider(conditions), inputs, &[output]);
+    output
+  }
+
+  /// Counts `signal` from 1 up to `period` and back to 1, once a tick.
+  pub fn clock(&mut self, signal: SignalID, period: i32) -> Bus {
+    let output = self.constant(&[(signal.clone(), 1)]);
+    self.add(
+      Combinator::Decider(DeciderConditions::new(
+        signal.clone(),
+        Comparator::Less,
+        period,
+        signal,
+        true,
+      )),
+      &[output],
+      &[output],
+    );
+    output
+  }
+
+  /// Adds up everything that's ever been on `input`, a tick later.
+  pub fn counter(&mut self, input: Bus) -> Bus {
+    let output = self.bus();
+    let each = SignalID::virtual_signal(EACH);
+    self.add(
+      Combinator::Arithmetic(ArithmeticConditions::new(
+        each.clone(),
+        ArithmeticOperation::Add,
+        0,
+        each,
+      )),
+      &[input, output],
+      &[output],
+    );
+    output
+  }
+
+  /// Remembers (adding up) whatever is sent on `set`, until `reset` shows up on it.
+  pub fn memory_cell(&mut self, set: Bus, reset: SignalID) -> Bus {
+    let output = self.bus();
+    let everything = SignalID::virtual_signal(EVERYTHING);
+    self.add(
+      Combinator::Decider(DeciderConditions::new(
+        reset,
+        Comparator::Equal,
+        0,
+        everything,
+        true,
+      )),
+      &[set, output],
+      &[output],
+    );
+    output
+  }
+
+  fn bus_colors(&self) -> Result<Vec<WireColor>, CircuitError> {
+    let mut neighbors = vec![Vec::new(); self.buses];
+    for (index, node) in self.nodes.iter().enumerate() {
+      for group in &[&node.inputs, &node.outputs] {
+        let mut distinct = group.to_vec();
+        distinct.dedup();
+        if distinct.len() > 2 {
+          return Err(CircuitError::TooManyBuses(CombinatorId(index)));
+        }
+        if let [Bus(first), Bus(second)] = distinct[..] {
+          neighbors[first].push(second);
+          neighbors[second].push(first);
+        }
+      }
+    }
+
+    let mut colors = vec![None; self.buses];
+    for start in 0..self.buses {
+      if colors[start].is_some() {
+        continue;
+      }
+      colors[start] = Some(WireColor::Red);
+      let mut stack = vec![start];
+      while let Some(bus) = stack.pop() {
+        let other = match colors[bus] {
+          Some(WireColor::Red) => WireColor::Green,
+          _ => WireColor::Red,
+        };
+        for &neighbor in &neighbors[bus] {
+          match colors[neighbor] {
+            None => {
+              colors[neighbor] = Some(other);
+              stack.push(neighbor);
+            }
+            Some(color) if color != other => return Err(CircuitError::Coloring(Bus(neighbor))),
+            _ => (),
+          }
+        }
+      }
+    }
+    Ok(colors.into_iter().map(Option::unwrap).collect())
+  }
+
+  /// Lays the combinators out in a square grid, in the order they were added, and wires each
+  /// bus together with the shortest wires it can.
+  pub fn blueprint(&self, name: &str) -> Result<Blueprint, CircuitError> {
+    let colors = self.bus_colors()?;
+    let columns = (1..)
+      .find(|columns| columns * columns >= self.nodes.len())
+      .unwrap();
+    let entities = self
+      .nodes
+      .iter()
+      .enumerate()
+      .map(|(index, node)| {
+        let mut entity = node.combinator.entity();
+        let row_top = (index / columns) as f64 * 2.0;
+        entity.position = Position {
+          x: (index % columns) as f64,
+          y: match node.combinator {
+            Combinator::Constant(_) => row_top,
+            _ => row_top + 0.5,
+          },
+        };
+        entity
+      })
+      .collect();
+    let mut result = Blueprint::simple(name.to_string(), entities, GameVersion::default());
+
+    let mut members = vec![Vec::new(); self.buses];
+    for (index, node) in self.nodes.iter().enumerate() {
+      for &Bus(bus) in &node.inputs {
+        members[bus].push((index, 1));
+      }
+      for &Bus(bus) in &node.outputs {
+        members[bus].push((index, node.combinator.output_connector()));
+      }
+    }
+    let mut wires = Vec::new();
+    for (bus, members) in members.iter_mut().enumerate() {
+      members.dedup();
+      // Prim's algorithm, over the positions of the combinators
+      let position = |(index, _): (usize, u8)| &result.entities[index].position;
+      let mut connected = vec![false; members.len()];
+      if let Some(first) = connected.first_mut() {
+        *first = true;
+      }
+      for _ in 1..members.len() {
+        let (from, to, distance) = (0..members.len())
+          .filter(|&from| connected[from])
+          .flat_map(|from| {
+            (0..members.len())
+              .filter(|&to| !connected[to])
+              .map(move |to| (from, to))
+          })
+          .map(|(from, to)| {
+            let (first, second) = (position(members[from]), position(members[to]));
+            (from, to, (first.x - second.x).hypot(first.y - second.y))
+          })
+          .min_by(|first, second| first.2.partial_cmp(&second.2).unwrap())
+          .unwrap();
+        if distance > circuit_wire_reach("arithmetic-combinator") {
+          return Err(CircuitError::TooFar(Bus(bus)));
+        }
+        connected[to] = true;
+        wires.push((colors[bus], members[from], members[to]));
+      }
+    }
+    for (color, (first, first_connector), (second, second_connector)) in wires {
+      let first = result.entities[first].entity_number;
+      let second = result.entities[second].entity_number;
+      result.connect(color, (first, first_connector), (second, second_connector));
+    }
+    Ok(result)
+  }
+
+  /// A simulation of the circuit, starting with every combinator's output empty except the
+  /// constant combinators'.
+  pub fn simulate(&self) -> CircuitSimulation {
+    CircuitSimulation {
+      outputs: self
+        .nodes
+        .iter()
+        .map(|node| match node.combinator {
+          Combinator::Constant(_) => node.combinator.evaluate(&Signals::new()),
+          _ => Signals::new(),
+        })
+        .collect(),
+      nodes: self.nodes.clone(),
+      buses: self.buses,
+      tick: 0,
+    }
+  }
+}
+
+/// Runs a `Circuit` the way the game does: on each tick, every combinator outputs what it
+/// computes from what was on its buses the tick before, so each combinator adds a tick of delay.
+pub struct CircuitSimulation {
+  nodes: Vec<Node>,
+  outputs: Vec<Signals>,
+  buses: usize,
+  tick: u64,
+}
+
+impl CircuitSimulation {
+  pub fn tick(&self) -> u64 {
+    self.tick
+  }
+
+  fn bus_values(&self) -> Vec<Signals> {
+    let mut result = vec![Signals::new(); self.buses];
+    for (node, output) in self.nodes.iter().zip(&self.outputs) {
+      for &Bus(bus) in &node.outputs {
+        add_signals(&mut result[bus], output);
+      }
+    }
+    result
+  }
+
+  /// What's on `bus` this tick.
+  pub fn bus(&self, bus: Bus) -> Signals {
+    self.bus_values().swap_remove(bus.0)
+  }
+
+  /// Changes what a constant combinator outputs, from this tick on, like a player would.
+  pub fn set_constant(&mut self, combinator: CombinatorId, signals: &[(SignalID, i32)]) {
+    let node = &mut self.nodes[combinator.0];
+    assert!(
+      matches!(node.combinator, Combinator::Constant(_)),
+      "only constant combinators can be set"
+    );
+    node.combinator = Combinator::Constant(signals.to_vec());
+    self.outputs[combinator.0] = node.combinator.evaluate(&Signals::new());
+  }
+
+  pub fn step(&mut self) {
+    let buses = self.bus_values();
+    for (node, output) in self.nodes.iter().zip(&mut self.outputs) {
+      let mut input = Signals::new();
+      for &Bus(bus) in &node.inputs {
+        add_signals(&mut input, &buses[bus]);
+      }
+      *output = node.combinator.evaluate(&input);
+    }
+    self.tick += 1;
+  }
+}
+
+/// One end of a wire: an entity number, which of its circuit connectors, and the wire color.
+type WireEnd = (i32, u8, WireColor);
+
+#[derive(Clone, Debug)]
+struct PlacedCombinator {
+  entity_number: i32,
+  combinator: Combinator,
+  inputs: Vec<usize>,
+  outputs: Vec<usize>,
+}
+
+/// Runs the combinators in a blueprint on the circuit networks that its wires make, the way
+/// `CircuitSimulation` runs a `Circuit`: each tick, every combinator outputs what it computes from
+/// the red and green networks on its input the tick before. Other entities only join networks
+/// together, like poles do; whatever they would read from the world can be fed in with
+/// `set_input` instead. Only the 1.1 combinator settings are understood.
+#[derive(Clone, Debug)]
+pub struct BlueprintSimulation {
+  networks: HashMap<WireEnd, usize>,
+  combinators: Vec<PlacedCombinator>,
+  outputs: Vec<Signals>,
+  inputs: HashMap<usize, Signals>,
+  network_count: usize,
+  tick: u64,
+}
+
+fn find(parents: &mut [usize], node: usize) -> usize {
+  let mut root = node;
+  while parents[root] != root {
+    root = parents[root];
+  }
+  let mut node = node;
+  while parents[node] != root {
+    let next = parents[node];
+    parents[node] = root;
+    node = next;
+  }
+  root
+}
+
+impl BlueprintSimulation {
+  pub fn new(blueprint: &Blueprint) -> BlueprintSimulation {
+    // join up the ends of every wire, then number the networks they make
+    let mut ends: HashMap<WireEnd, usize> = HashMap::new();
+    let mut parents = Vec::new();
+    let mut node = |ends: &mut HashMap<WireEnd, usize>, end: WireEnd| {
+      *ends.entry(end).or_insert_with(|| {
+        parents.push(parents.len());
+        parents.len() - 1
+      })
+    };
+    let mut wires = Vec::new();
+    for entity in &blueprint.entities {
+      for (&connector, connection) in &entity.connections {
+        for &color in &[WireColor::Red, WireColor::Green] {
+          for data in connection.wires(color) {
+            let first = node(&mut ends, (entity.entity_number, connector, color));
+            let second = node(
+              &mut ends,
+              (data.entity_id, data.circuit_id.unwrap_or(1), color),
+            );
+            wires.push((first, second));
+          }
+        }
+      }
+    }
+    for (first, second) in wires {
+      let (first, second) = (find(&mut parents, first), find(&mut parents, second));
+      parents[first] = second;
+    }
+    let mut numbers = HashMap::new();
+    let networks: HashMap<WireEnd, usize> = ends
+      .into_iter()
+      .map(|(end, node)| {
+        let root = find(&mut parents, node);
+        let count = numbers.len();
+        (end, *numbers.entry(root).or_insert(count))
+      })
+      .collect();
+
+    let combinators: Vec<PlacedCombinator> = blueprint
+      .entities
+      .iter()
+      .filter_map(|entity| {
+        let combinator = Combinator::from_entity(entity)?;
+        let on_connector = |connector: u8| -> Vec<usize> {
+          [WireColor::Red, WireColor::Green]
+            .iter()
+            .filter_map(|&color| {
+              networks
+                .get(&(entity.entity_number, connector, color))
+                .cloned()
+            })
+            .collect()
+        };
+        Some(PlacedCombinator {
+          entity_number: entity.entity_number,
+          inputs: match combinator {
+            Combinator::Constant(_) => Vec::new(),
+            _ => on_connector(1),
+          },
+          outputs: on_connector(combinator.output_connector()),
+          combinator,
+        })
+      })
+      .collect();
+    BlueprintSimulation {
+      outputs: combinators
+        .iter()
+        .map(|placed| match placed.combinator {
+          Combinator::Constant(_) => placed.combinator.evaluate(&Signals::new()),
+          _ => Signals::new(),
+        })
+        .collect(),
+      network_count: numbers.len(),
+      networks,
+      combinators,
+      inputs: HashMap::new(),
+      tick: 0,
+    }
+  }
+
+  pub fn tick(&self) -> u64 {
+    self.tick
+  }
+
+  fn network_values(&self) -> Vec<Signals> {
+    let mut result = vec![Signals::new(); self.network_count];
+    for (placed, output) in self.combinators.iter().zip(&self.outputs) {
+      for &network in &placed.outputs {
+        add_signals(&mut result[network], output);
+      }
+    }
+    for (&network, signals) in &self.inputs {
+      add_signals(&mut result[network], signals);
+    }
+    result
+  }
+
+  /// What's on the `color` network at one of an entity's circuit connectors this tick; nothing
+  /// if there's no wire of that color there.
+  pub fn signals(&self, entity_number: i32, connector: u8, color: WireColor) -> Signals {
+    match self.networks.get(&(entity_number, connector, color)) {
+      Some(&network) => self.network_values().swap_remove(network),
+      None => Signals::new(),
+    }
+  }
+
+  /// What an entity reads this tick: the red and green networks on its first connector added
+  /// together.
+  pub fn input(&self, entity_number: i32) -> Signals {
+    let mut result = self.signals(entity_number, 1, WireColor::Red);
+    add_signals(
+      &mut result,
+      &self.signals(entity_number, 1, WireColor::Green),
+    );
+    result
+  }
+
+  /// Puts `signals` on the network at one of an entity's connectors from this tick on, as if
+  /// something outside the circuit was sending them, replacing whatever was sent there before.
+  /// Returns false if there's no such network.
+  pub fn set_input(
+    &mut self,
+    entity_number: i32,
+    connector: u8,
+    color: WireColor,
+    signals: Signals,
+  ) -> bool {
+    match self.networks.get(&(entity_number, connector, color)) {
+      Some(&network) => {
+        self.inputs.insert(network, signals);
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Changes what a constant combinator outputs, from this tick on, like a player would.
+  /// Returns false if the entity isn't a constant combinator.
+  pub fn set_constant(&mut self, entity_number: i32, signals: &[(SignalID, i32)]) -> bool {
+    let index = match self.combinators.iter().position(|placed| {
+      placed.entity_number == entity_number && matches!(placed.combinator, Combinator::Constant(_))
+    }) {
+      Some(index) => index,
+      None => return false,
+    };
+    self.combinators[index].combinator = Combinator::Constant(signals.to_vec());
+    self.outputs[index] = self.combinators[index].combinator.evaluate(&Signals::new());
+    true
+  }
+
+  pub fn step(&mut self) {
+    let networks = self.network_values();
+    for (placed, output) in self.combinators.iter().zip(&mut self.outputs) {
+      let mut input = Signals::new();
+      for &network in &placed.inputs {
+        add_signals(&mut input, &networks[network]);
+      }
+      *output = placed.combinator.evaluate(&input);
+    }
+    self.tick += 1;
+  }
+
+  /// The entity numbers of the combinators being simulated, and what each is outputting.
+  pub fn outputs(&self) -> impl Iterator<Item = (i32, &Signals)> {
+    self
+      .combinators
+      .iter()
+      .map(|placed| placed.entity_number)
+      .zip(&self.outputs)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn signal(name: &str) -> SignalID {
+    SignalID::virtual_signal(name)
+  }
+
+  type Counts<'a> = &'a [(&'a str, i32)];
+
+  fn signals(counts: Counts) -> Signals {
+    let mut result = Signals::new();
+    for &(name, count) in counts {
+      add_signal(&mut result, &signal(name), count);
+    }
+    result
+  }
+
+  /// Checks that the blueprint the circuit becomes has the same combinator outputs, and the same
+  /// signals on each bus, as the circuit has in `simulation`.
+  fn assert_blueprint_matches(
+    circuit: &Circuit,
+    simulation: &CircuitSimulation,
+    blueprint: &BlueprintSimulation,
+  ) {
+    let colors = circuit.bus_colors().unwrap();
+    let outputs: Vec<&Signals> = blueprint.outputs().map(|(_, output)| output).collect();
+    assert_eq!(outputs.len(), simulation.outputs.len());
+    for (index, node) in circuit.nodes.iter().enumerate() {
+      assert_eq!(
+        outputs[index],
+        &simulation.outputs[index],
+        "combinator {} on tick {}",
+        index,
+        simulation.tick()
+      );
+      for &bus in &node.outputs {
+        assert_eq!(
+          blueprint.signals(
+            index as i32 + 1,
+            node.combinator.output_connector(),
+            colors[bus.0]
+          ),
+          simulation.bus(bus),
+          "bus {} on tick {}",
+          bus.0,
+          simulation.tick()
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn clock_counts_up_and_wraps() {
+    let mut circuit = Circuit::new();
+    let clock = circuit.clock(signal("signal-T"), 3);
+    let mut simulation = circuit.simulate();
+    let mut blueprint = BlueprintSimulation::new(&circuit.blueprint("clock").unwrap());
+    for &expected in &[1, 2, 3, 1, 2, 3, 1] {
+      assert_eq!(simulation.bus(clock), signals(&[("signal-T", expected)]));
+      assert_blueprint_matches(&circuit, &simulation, &blueprint);
+      simulation.step();
+      blueprint.step();
+    }
+  }
+
+  #[test]
+  fn counter_adds_up_its_input() {
+    let mut circuit = Circuit::new();
+    let input = circuit.constant(&[(signal("signal-A"), 5), (signal("signal-B"), -2)]);
+    let count = circuit.counter(input);
+    let mut simulation = circuit.simulate();
+    let mut blueprint = BlueprintSimulation::new(&circuit.blueprint("counter").unwrap());
+    for tick in 0..6 {
+      assert_eq!(
+        simulation.bus(count),
+        signals(&[("signal-A", 5 * tick), ("signal-B", -2 * tick)])
+      );
+      assert_blueprint_matches(&circuit, &simulation, &blueprint);
+      simulation.step();
+      blueprint.step();
+    }
+  }
+
+  #[test]
+  fn memory_cell_holds_until_reset() {
+    let mut circuit = Circuit::new();
+    let set = circuit.bus();
+    let input = circuit.add(Combinator::Constant(Vec::new()), &[], &[set]);
+    let memory = circuit.memory_cell(set, signal("signal-R"));
+    let mut simulation = circuit.simulate();
+    let mut blueprint = BlueprintSimulation::new(&circuit.blueprint("memory").unwrap());
+    // what the input is set to before each tick, and what the cell holds after it
+    let steps: &[(Counts, Counts)] = &[
+      (&[("signal-A", 3)], &[("signal-A", 3)]),
+      (&[], &[("signal-A", 3)]),
+      (&[], &[("signal-A", 3)]),
+      (&[("signal-B", 4)], &[("signal-A", 3), ("signal-B", 4)]),
+      (&[], &[("signal-A", 3), ("signal-B", 4)]),
+      (&[("signal-R", 1)], &[]),
+      (&[], &[]),
+    ];
+    assert_eq!(simulation.bus(memory), Signals::new());
+    for &(sent, held) in steps {
+      let sent: Vec<(SignalID, i32)> = sent
+        .iter()
+        .map(|&(name, count)| (signal(name), count))
+        .collect();
+      simulation.set_constant(input, &sent);
+      assert!(blueprint.set_constant(input.0 as i32 + 1, &sent));
+      simulation.step();
+      blueprint.step();
+      assert_eq!(simulation.bus(memory), signals(held));
+      assert_blueprint_matches(&circuit, &simulation, &blueprint);
+    }
+  }
+
+  /// An entity number and one of its circuit connectors.
+  type Connector = (i32, u8);
+
+  /// A blueprint of `entities`, numbered from 1 in order, with `wires` between their
+  /// (entity number, connector) ends.
+  fn wired(entities: Vec<Entity>, wires: &[(WireColor, Connector, Connector)]) -> Blueprint {
+    let mut result = Blueprint::simple("test".to_string(), entities, GameVersion::default());
+    for &(color, first, second) in wires {
+      result.connect(color, first, second);
+    }
+    result
+  }
+
+  fn at(combinator: Combinator, x: f64) -> Entity {
+    Entity {
+      position: Position { x, y: 0.5 },
+      ..combinator.entity()
+    }
+  }
+
+  fn output(simulation: &BlueprintSimulation, entity_number: i32) -> Signals {
+    simulation
+      .outputs()
+      .find(|&(number, _)| number == entity_number)
+      .unwrap()
+      .1
+      .clone()
+  }
+
+  #[test]
+  fn blueprint_clock() {
+    // a constant combinator and a looped decider, joined up through a pole
+    let pole = Entity {
+      name: "medium-electric-pole".to_string(),
+      position: Position { x: 2.5, y: 0.5 },
+      ..Default::default()
+    };
+    let blueprint = wired(
+      vec![
+        at(Combinator::Constant(vec![(signal("signal-T"), 1)]), 0.5),
+        at(
+          Combinator::Decider(DeciderConditions::new(
+            signal("signal-T"),
+            Comparator::Less,
+            4,
+            signal("signal-T"),
+            true,
+          )),
+          1.5,
+        ),
+        pole,
+      ],
+      &[
+        (WireColor::Red, (1, 1), (3, 1)),
+        (WireColor::Red, (3, 1), (2, 1)),
+        (WireColor::Red, (2, 1), (2, 2)),
+      ],
+    );
+    let mut simulation = BlueprintSimulation::new(&blueprint);
+    for &expected in &[1, 2, 3, 4, 1, 2, 3, 4, 1] {
+      assert_eq!(simulation.input(3), signals(&[("signal-T", expected)]));
+      simulation.step();
+    }
+    assert_eq!(simulation.tick(), 9);
+  }
+
+  #[test]
+  fn blueprint_sr_latch() {
+    // S > R outputs S = 1, which loops back round to keep it set
+    let blueprint = wired(
+      vec![
+        at(Combinator::Constant(Vec::new()), 0.5),
+        at(
+          Combinator::Decider(DeciderConditions::new(
+            signal("signal-S"),
+            Comparator::Greater,
+            signal("signal-R"),
+            signal("signal-S"),
+            false,
+          )),
+          1.5,
+        ),
+      ],
+      &[
+        (WireColor::Red, (1, 1), (2, 1)),
+        (WireColor::Green, (2, 1), (2, 2)),
+      ],
+    );
+    let mut simulation = BlueprintSimulation::new(&blueprint);
+    let set = [(signal("signal-S"), 1)];
+    let reset = [(signal("signal-R"), 1)];
+    // what the switches are set to before each tick, and whether the latch is set after it
+    let steps: &[(&[(SignalID, i32)], bool)] = &[
+      (&[], false),
+      (&set, true),
+      (&set, true),
+      (&[], true),
+      (&[], true),
+      (&reset, false),
+      (&[], false),
+      (&[], false),
+      (&set, true),
+      (&[], true),
+    ];
+    for &(switches, latched) in steps {
+      assert!(simulation.set_constant(1, switches));
+      simulation.step();
+      let expected = if latched {
+        signals(&[("signal-S", 1)])
+      } else {
+        Signals::new()
+      };
+      assert_eq!(output(&simulation, 2), expected);
+    }
+  }
+
+  #[test]
+  fn blueprint_each_filter() {
+    // The mask adds 2^30 to the signals to keep; the decider passes only those, and the
+    // arithmetic combinator takes the 2^30 back off each of them.
+    let mark = 1 << 30;
+    let blueprint = wired(
+      vec![
+        at(
+          Combinator::Constant(vec![
+            (signal("signal-A"), 5),
+            (signal("signal-B"), 7),
+            (signal("signal-C"), 9),
+          ]),
+          0.5,
+        ),
+        at(
+          Combinator::Constant(vec![(signal("signal-A"), mark), (signal("signal-C"), mark)]),
+          1.5,
+        ),
+        at(
+          Combinator::Decider(DeciderConditions::new(
+            signal(EACH),
+            Comparator::GreaterOrEqual,
+            mark,
+            signal(EACH),
+            true,
+          )),
+          2.5,
+        ),
+        at(
+          Combinator::Arithmetic(ArithmeticConditions::new(
+            signal(EACH),
+            ArithmeticOperation::Subtract,
+            mark,
+            signal(EACH),
+          )),
+          3.5,
+        ),
+      ],
+      &[
+        (WireColor::Red, (1, 1), (3, 1)),
+        (WireColor::Green, (2, 1), (3, 1)),
+        (WireColor::Red, (3, 2), (4, 1)),
+      ],
+    );
+    let mut simulation = BlueprintSimulation::new(&blueprint);
+    simulation.step();
+    simulation.step();
+    assert_eq!(
+      output(&simulation, 4),
+      signals(&[("signal-A", 5), ("signal-C", 9)])
+    );
+    assert_eq!(
+      simulation.signals(4, 2, WireColor::Red),
+      Signals::new(),
+      "nothing is wired to the output"
+    );
+  }
+
+  #[test]
+  fn blueprint_wire_colors_stay_apart() {
+    let double = || {
+      Combinator::Arithmetic(ArithmeticConditions::new(
+        signal(EACH),
+        ArithmeticOperation::Multiply,
+        2,
+        signal(EACH),
+      ))
+    };
+    let blueprint = wired(
+      vec![
+        at(Combinator::Constant(vec![(signal("signal-A"), 1)]), 0.5),
+        at(Combinator::Constant(vec![(signal("signal-A"), 10)]), 1.5),
+        at(double(), 2.5),
+        at(double(), 3.5),
+        at(double(), 4.5),
+      ],
+      &[
+        // the red network reaches all three, the green one only the last two
+        (WireColor::Red, (1, 1), (3, 1)),
+        (WireColor::Red, (3, 1), (4, 1)),
+        (WireColor::Red, (4, 1), (5, 1)),
+        (WireColor::Green, (2, 1), (4, 1)),
+        (WireColor::Green, (4, 1), (5, 1)),
+      ],
+    );
+    let mut simulation = BlueprintSimulation::new(&blueprint);
+    assert_eq!(
+      simulation.signals(5, 1, WireColor::Red),
+      signals(&[("signal-A", 1)])
+    );
+    assert_eq!(
+      simulation.signals(5, 1, WireColor::Green),
+      signals(&[("signal-A", 10)])
+    );
+    assert_eq!(simulation.signals(3, 1, WireColor::Green), Signals::new());
+    assert_eq!(simulation.input(5), signals(&[("signal-A", 11)]));
+    simulation.step();
+    assert_eq!(output(&simulation, 3), signals(&[("signal-A", 2)]));
+    assert_eq!(output(&simulation, 4), signals(&[("signal-A", 22)]));
+    assert_eq!(output(&simulation, 5), signals(&[("signal-A", 22)]));
+  }
+
+  #[test]
+  fn blueprint_combinators_take_a_tick() {
+    let blueprint = wired(
+      vec![
+        at(Combinator::Constant(vec![(signal("signal-A"), 3)]), 0.5),
+        at(
+          Combinator::Arithmetic(ArithmeticConditions::new(
+            signal(EACH),
+            ArithmeticOperation::Multiply,
+            2,
+            signal(EACH),
+          )),
+          1.5,
+        ),
+        at(
+          Combinator::Arithmetic(ArithmeticConditions::new(
+            signal("signal-A"),
+            ArithmeticOperation::Add,
+            1,
+            signal("signal-B"),
+          )),
+          2.5,
+        ),
+      ],
+      &[
+        (WireColor::Red, (1, 1), (2, 1)),
+        (WireColor::Red, (2, 2), (3, 1)),
+      ],
+    );
+    let mut simulation = BlueprintSimulation::new(&blueprint);
+    assert_eq!(output(&simulation, 2), Signals::new());
+    assert_eq!(output(&simulation, 3), Signals::new());
+    simulation.step();
+    assert_eq!(output(&simulation, 2), signals(&[("signal-A", 6)]));
+    assert_eq!(output(&simulation, 3), signals(&[("signal-B", 1)]));
+    simulation.step();
+    assert_eq!(output(&simulation, 3), signals(&[("signal-B", 7)]));
+
+    // a change to the constant takes a tick to reach each combinator in turn
+    assert!(simulation.set_constant(1, &[(signal("signal-A"), 5)]));
+    assert_eq!(simulation.input(2), signals(&[("signal-A", 5)]));
+    simulation.step();
+    assert_eq!(output(&simulation, 2), signals(&[("signal-A", 10)]));
+    assert_eq!(output(&simulation, 3), signals(&[("signal-B", 7)]));
+    simulation.step();
+    assert_eq!(output(&simulation, 3), signals(&[("signal-B", 11)]));
+  }
+}
@@ -0,0 +1,440 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use image::{Rgba, RgbaImage};
+
+use super::blueprint::*;
+use super::preview::{self, PreviewOptions};
+use super::prototypes::*;
+
+/// Positions are compared on a half-tile grid, so that float noise doesn't matter.
+type PositionKey = [i64; 2];
+
+fn position_key(position: &Position, offset: [f64; 2]) -> PositionKey {
+  [
+    ((position.x - offset[0]) * 2.0).round() as i64,
+    ((position.y - offset[1]) * 2.0).round() as i64,
+  ]
+}
+
+/// The name without its tier, so that upgrading a belt or an assembler counts as modifying it
+/// rather than removing one entity and adding another.
+pub fn entity_family(name: &str) -> &str {
+  let name = name
+    .strip_prefix("fast-")
+    .or_else(|| name.strip_prefix("express-"))
+    .unwrap_or(name);
+  match name.rfind('-') {
+    Some(index)
+      if name[index + 1..]
+        .chars()
+        .all(|character| character.is_ascii_digit()) =>
+    {
+      &name[..index]
+    }
+    _ => name,
+  }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum EntityChange {
+  /// a different tier of the same kind of entity
+  Name,
+  Direction,
+  Recipe,
+  Filters,
+  /// modules and other requested items
+  Items,
+  Connections,
+}
+
+#[derive(Clone, Debug)]
+pub struct ModifiedEntity {
+  pub before: Entity,
+  pub after: Entity,
+  pub changes: Vec<EntityChange>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BlueprintDiff {
+  /// what was added to the positions in the first blueprint to line them up with the second
+  pub offset: [f64; 2],
+  pub added: Vec<Entity>,
+  pub removed: Vec<Entity>,
+  pub modified: Vec<ModifiedEntity>,
+  pub unchanged: usize,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DiffAlignment {
+  /// compare entities at the same coordinates
+  Exact,
+  /// compare after moving the first blueprint by this much
+  Offset([f64; 2]),
+  /// find the offset that lines up the most entities
+  Automatic,
+}
+
+/// One end of a wire: the circuit connector, whether it's red, and the other end's position and
+/// connector.
+type WireEnd = (u8, bool, PositionKey, Option<u8>);
+
+/// Each entity's wires, described by where the other end is rather than by entity number,
+/// since entity numbers don't mean anything across blueprints.
+fn wire_sets(blueprint: &Blueprint, offset: [f64; 2]) -> HashMap<i32, BTreeSet<WireEnd>> {
+  let positions: HashMap<i32, PositionKey> = blueprint
+    .entities
+    .iter()
+    .map(|entity| (entity.entity_number, position_key(&entity.position, offset)))
+    .collect();
+  blueprint
+    .entities
+    .iter()
+    .map(|entity| {
+      let mut wires = BTreeSet::new();
+      for (&circuit, connection) in &entity.connections {
+        for (red, list) in &[(true, &connection.red), (false, &connection.green)] {
+          for data in list.iter() {
+            if let Some(&position) = positions.get(&data.entity_id) {
+              wires.insert((circuit, *red, position, data.circuit_id));
+            }
+          }
+        }
+      }
+      (entity.entity_number, wires)
+    })
+    .collect()
+}
+
+fn filters_json(entity: &Entity) -> serde_json::Value {
+  serde_json::json!({
+    "filter": entity.filter,
+    "filters": serde_json::to_value(&entity.filters).unwrap_or_default(),
+  })
+}
+
+fn automatic_offset(before: &Blueprint, after: &Blueprint) -> [f64; 2] {
+  // use the rarest entity that both blueprints have to guess some candidate offsets
+  let mut counts: HashMap<&str, (usize, usize)> = HashMap::new();
+  for entity in &before.entities {
+    counts.entry(&entity.name).or_default().0 += 1;
+  }
+  for entity in &after.entities {
+    counts.entry(&entity.name).or_default().1 += 1;
+  }
+  let rarest = counts
+    .iter()
+    .filter(|(_, &(first, second))| first > 0 && second > 0)
+    .min_by_key(|(name, &(first, second))| (first * second, **name))
+    .map(|(name, _)| *name);
+  let rarest = match rarest {
+    Some(rarest) => rarest,
+    None => return [0.0, 0.0],
+  };
+  let mut votes: HashMap<PositionKey, usize> = HashMap::new();
+  for first in before
+    .entities
+    .iter()
+    .filter(|entity| entity.name == rarest)
+  {
+    for second in after.entities.iter().filter(|entity| entity.name == rarest) {
+      let key = position_key(&second.position, [first.position.x, first.position.y]);
+      *votes.entry(key).or_insert(0) += 1;
+    }
+  }
+  let mut candidates: Vec<_> = votes.into_iter().collect();
+  candidates.sort_by_key(|&(key, count)| (std::cmp::Reverse(count), key));
+  candidates.truncate(8);
+  candidates.push(([0, 0], 0));
+
+  let after_positions: BTreeSet<(PositionKey, &str)> = after
+    .entities
+    .iter()
+    .map(|entity| {
+      (
+        position_key(&entity.position, [0.0, 0.0]),
+        entity.name.as_str(),
+      )
+    })
+    .collect();
+  candidates
+    .into_iter()
+    .map(|(key, _)| [key[0] as f64 / 2.0, key[1] as f64 / 2.0])
+    .max_by_key(|&offset| {
+      let matched = before
+        .entities
+        .iter()
+        .filter(|entity| {
+          let key = position_key(&entity.position, [-offset[0], -offset[1]]);
+          after_positions.contains(&(key, entity.name.as_str()))
+        })
+        .count();
+      // prefer not moving at all when it's a tie
+      (matched, offset == [0.0, 0.0])
+    })
+    .unwrap()
+}
+
+pub fn diff_blueprints(
+  before: &Blueprint,
+  after: &Blueprint,
+  alignment: DiffAlignment,
+) -> BlueprintDiff {
+  let offset = match alignment {
+    DiffAlignment::Exact => [0.0, 0.0],
+    DiffAlignment::Offset(offset) => offset,
+    DiffAlignment::Automatic => automatic_offset(before, after),
+  };
+  // everything is compared in the second blueprint's coordinates
+  let shifted = [-offset[0], -offset[1]];
+  let before_wires = wire_sets(before, shifted);
+  let after_wires = wire_sets(after, [0.0, 0.0]);
+
+  let mut unmatched: HashMap<(PositionKey, &str), Vec<&Entity>> = HashMap::new();
+  for entity in &after.entities {
+    unmatched
+      .entry((
+        position_key(&entity.position, [0.0, 0.0]),
+        entity_family(&entity.name),
+      ))
+      .or_default()
+      .push(entity);
+  }
+
+  let mut result = BlueprintDiff {
+    offset,
+    ..Default::default()
+  };
+  for first in &before.entities {
+    let key = (
+      position_key(&first.position, shifted),
+      entity_family(&first.name),
+    );
+    let second = match unmatched
+      .get_mut(&key)
+      .and_then(|candidates| candidates.pop())
+    {
+      Some(second) => second,
+      None => {
+        result.removed.push(first.clone());
+        continue;
+      }
+    };
+    let mut changes = Vec::new();
+    if first.name != second.name {
+      changes.push(EntityChange::Name);
+    }
+    if first.direction.unwrap_or(0) != second.direction.unwrap_or(0) {
+      changes.push(EntityChange::Direction);
+    }
+    if first.recipe != second.recipe {
+      changes.push(EntityChange::Recipe);
+    }
+    if filters_json(first) != filters_json(second) {
+      changes.push(EntityChange::Filters);
+    }
+    if first.items != second.items {
+      changes.push(EntityChange::Items);
+    }
+    if before_wires.get(&first.entity_number) != after_wires.get(&second.entity_number) {
+      changes.push(EntityChange::Connections);
+    }
+    if changes.is_empty() {
+      result.unchanged += 1;
+    } else {
+      result.modified.push(ModifiedEntity {
+        before: first.clone(),
+        after: second.clone(),
+        changes,
+      });
+    }
+  }
+  for entity in &after.entities {
+    let key = (
+      position_key(&entity.position, [0.0, 0.0]),
+      entity_family(&entity.name),
+    );
+    if unmatched.get(&key).is_some_and(|candidates| {
+      candidates
+        .iter()
+        .any(|candidate| std::ptr::eq(*candidate, entity))
+    }) {
+      result.added.push(entity.clone());
+    }
+  }
+  result
+}
+
+impl BlueprintDiff {
+  pub fn is_empty(&self) -> bool {
+    self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+  }
+
+  /// A removed entity, moved into the second blueprint's coordinates.
+  fn shifted(&self, entity: &Entity) -> Entity {
+    Entity {
+      position: Position {
+        x: entity.position.x + self.offset[0],
+        y: entity.position.y + self.offset[1],
+      },
+      ..entity.clone()
+    }
+  }
+
+  /// One character per tile: `+` added, `-` removed, `~` modified, `.` unchanged.
+  pub fn text_overlay(&self, after: &Blueprint) -> String {
+    let mut marks: Vec<(Entity, char)> = Vec::new();
+    marks.extend(after.entities.iter().map(|entity| (entity.clone(), '.')));
+    marks.extend(
+      self
+        .removed
+        .iter()
+        .map(|entity| (self.shifted(entity), '-')),
+    );
+    marks.extend(self.added.iter().map(|entity| (entity.clone(), '+')));
+    marks.extend(
+      self
+        .modified
+        .iter()
+        .map(|modified| (modified.after.clone(), '~')),
+    );
+    let mut bounds = [[i64::MAX, i64::MIN], [i64::MAX, i64::MIN]];
+    let mut tiles = HashMap::new();
+    for (entity, mark) in &marks {
+      for [x, y] in footprint_tiles(entity) {
+        tiles.insert([x, y], *mark);
+        bounds[0][0] = bounds[0][0].min(x);
+        bounds[0][1] = bounds[0][1].max(x);
+        bounds[1][0] = bounds[1][0].min(y);
+        bounds[1][1] = bounds[1][1].max(y);
+      }
+    }
+    let mut result = String::new();
+    if tiles.is_empty() {
+      return result;
+    }
+    for y in bounds[1][0]..=bounds[1][1] {
+      let line: String = (bounds[0][0]..=bounds[0][1])
+        .map(|x| tiles.get(&[x, y]).cloned().unwrap_or(' '))
+        .collect();
+      result.push_str(line.trim_end());
+      result.push('\n');
+    }
+    result
+  }
+
+  /// The second blueprint with added entities tinted green, removed ones red, and modified
+  /// ones yellow.
+  pub fn png_overlay(&self, after: &Blueprint, options: &PreviewOptions) -> RgbaImage {
+    let mut highlights = Vec::new();
+    for entity in &self.added {
+      highlights.push((entity_footprint(entity), Rgba([60, 220, 60, 255])));
+    }
+    for entity in &self.removed {
+      highlights.push((
+        entity_footprint(&self.shifted(entity)),
+        Rgba([230, 50, 50, 255]),
+      ));
+    }
+    for modified in &self.modified {
+      highlights.push((entity_footprint(&modified.after), Rgba([240, 210, 40, 255])));
+    }
+    preview::render_highlighted(&after.entities, &after.tiles, &highlights, options)
+  }
+}
+
+fn describe_entity(formatter: &mut fmt::Formatter, entity: &Entity) -> fmt::Result {
+  write!(
+    formatter,
+    "{} at ({}, {})",
+    entity.name, entity.position.x, entity.position.y
+  )?;
+  if let Some(direction) = entity.direction {
+    write!(formatter, " facing {}", direction)?;
+  }
+  if let Some(recipe) = &entity.recipe {
+    write!(formatter, " making {}", recipe)?;
+  }
+  Ok(())
+}
+
+impl fmt::Display for BlueprintDiff {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    if self.offset != [0.0, 0.0] {
+      writeln!(
+        formatter,
+        "moved by ({}, {})",
+        self.offset[0], self.offset[1]
+      )?;
+    }
+    writeln!(
+      formatter,
+      "{} added, {} removed, {} modified, {} unchanged",
+      self.added.len(),
+      self.removed.len(),
+      self.modified.len(),
+      self.unchanged
+    )?;
+    for entity in &self.added {
+      write!(formatter, "+ ")?;
+      describe_entity(formatter, entity)?;
+      writeln!(formatter)?;
+    }
+    for entity in &self.removed {
+      write!(formatter, "- ")?;
+      describe_entity(formatter, entity)?;
+      writeln!(formatter)?;
+    }
+    for modified in &self.modified {
+      write!(formatter, "~ ")?;
+      describe_entity(formatter, &modified.after)?;
+      writeln!(formatter, ": {:?}", modified.changes)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn entity(name: &str, x: f64, y: f64) -> Entity {
+    Entity {
+      name: name.to_string(),
+      position: Position { x, y },
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn automatic_alignment_finds_a_translated_copy() {
+    let before = Blueprint::simple(
+      "before".to_string(),
+      vec![
+        entity("assembling-machine-1", 0.5, 0.5),
+        entity("transport-belt", 2.5, -1.5),
+        entity("transport-belt", 2.5, -0.5),
+        entity("transport-belt", 2.5, 0.5),
+        entity("wooden-chest", -2.5, 0.5),
+      ],
+      GameVersion::default(),
+    );
+    let mut after = before.clone();
+    for entity in &mut after.entities {
+      entity.position.x += 5.0;
+      entity.position.y -= 3.0;
+    }
+    after.entities[2].name = "fast-transport-belt".to_string();
+
+    let diff = diff_blueprints(&before, &after, DiffAlignment::Automatic);
+    assert_eq!(diff.offset, [5.0, -3.0]);
+    assert!(diff.added.is_empty() && diff.removed.is_empty());
+    assert_eq!(diff.unchanged, 4);
+    assert_eq!(diff.modified.len(), 1);
+    assert_eq!(diff.modified[0].after.name, "fast-transport-belt");
+    assert_eq!(diff.modified[0].changes, vec![EntityChange::Name]);
+
+    let exact = diff_blueprints(&before, &after, DiffAlignment::Exact);
+    assert_eq!((exact.added.len(), exact.removed.len()), (5, 5));
+  }
+}
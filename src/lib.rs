@@ -1,4 +1,17 @@
 pub mod belt_routing;
 pub mod blueprint;
+pub mod combinators;
+pub mod diff;
 pub mod optimizer;
+pub mod pixel_art;
+pub mod preview;
+pub mod prototypes;
+pub mod rails;
+pub mod routing_problem;
 pub mod simplified;
+pub mod stamp;
+pub mod statistics;
+pub mod text_render;
+pub mod upgrade;
+pub mod version;
+pub mod wiring;
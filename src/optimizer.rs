@@ -277,7 +277,11 @@ pub fn blueprint_thingy() -> Vec<Blueprint> {
           entities.extend(object.render());
         }
       }
-      Blueprint::simple(format!("Score: {}", candidate.score), entities)
+      Blueprint::simple(
+        format!("Score: {}", candidate.score),
+        entities,
+        GameVersion::default(),
+      )
     })
     .collect()
 }
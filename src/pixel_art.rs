@@ -0,0 +1,488 @@
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageResult};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use super::blueprint::*;
+use super::prototypes::circuit_wire_reach;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum PixelKind {
+  Entity,
+  Tile,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PaletteEntry {
+  pub name: String,
+  pub color: [u8; 3],
+  pub kind: PixelKind,
+}
+
+impl PaletteEntry {
+  pub fn new(name: impl Into<String>, color: [u8; 3], kind: PixelKind) -> PaletteEntry {
+    PaletteEntry {
+      name: name.into(),
+      color,
+      kind,
+    }
+  }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Dither {
+  None,
+  Ordered,
+  FloydSteinberg,
+}
+
+#[derive(Clone, Debug)]
+pub struct PixelArtOptions {
+  pub palette: Vec<PaletteEntry>,
+  pub dither: Dither,
+  /// If set, the image is scaled to fit within `[width, height]`, keeping its aspect ratio.
+  pub size: Option<[u32; 2]>,
+  /// Pixels less opaque than this are left empty.
+  pub alpha_threshold: u8,
+}
+
+impl PixelArtOptions {
+  pub fn new(palette: Vec<PaletteEntry>) -> PixelArtOptions {
+    PixelArtOptions {
+      palette,
+      dither: Dither::FloydSteinberg,
+      size: None,
+      alpha_threshold: 128,
+    }
+  }
+}
+
+// Leaving out buffer chest because the viewer I'm using doesn't display it
+pub const CHEST_AND_UNDERGROUND_NAMES: [&str; 10] = [
+  "wooden-chest",
+  "iron-chest",
+  "steel-chest",
+  "underground-belt",
+  "fast-underground-belt",
+  "express-underground-belt",
+  "logistic-chest-active-provider",
+  "logistic-chest-passive-provider",
+  "logistic-chest-storage",
+  "logistic-chest-requester",
+];
+
+/// Builds an entity palette from the average colors of the icons `<directory>/<name>.png`.
+pub fn icon_palette(directory: impl AsRef<Path>, names: &[&str]) -> ImageResult<Vec<PaletteEntry>> {
+  let directory = directory.as_ref();
+  let mut result = Vec::new();
+  for name in names {
+    let icon = image::open(directory.join(format!("{}.png", name)))?;
+    let mut totals = [0f64; 4];
+    for (_, _, image::Rgba(data)) in icon.pixels() {
+      for index in 0..3 {
+        totals[index] += data[index] as f64 * data[3] as f64;
+      }
+      totals[3] += data[3] as f64;
+    }
+    let channel = |index: usize| (totals[index] / totals[3]).round() as u8;
+    result.push(PaletteEntry::new(
+      *name,
+      [channel(0), channel(1), channel(2)],
+      PixelKind::Entity,
+    ));
+  }
+  Ok(result)
+}
+
+/// Stretches the palette colors so that each channel covers the full range.
+/// Useful for icon palettes, since the icons are all fairly dull.
+pub fn stretch_palette(palette: &mut [PaletteEntry]) {
+  let mut ranges = [[255u8, 0u8]; 3];
+  for entry in palette.iter() {
+    for (range, &value) in ranges.iter_mut().zip(&entry.color) {
+      range[0] = range[0].min(value);
+      range[1] = range[1].max(value);
+    }
+  }
+  for entry in palette.iter_mut() {
+    for (value, &[low, high]) in entry.color.iter_mut().zip(&ranges) {
+      if high > low {
+        *value = ((*value - low) as f64 * 255.0 / (high - low) as f64).round() as u8;
+      }
+    }
+  }
+}
+
+/// The floor tiles, with roughly the colors they have in game.
+pub fn concrete_palette() -> Vec<PaletteEntry> {
+  [
+    ("stone-path", [86, 82, 74]),
+    ("concrete", [63, 61, 59]),
+    ("refined-concrete", [49, 48, 45]),
+    ("hazard-concrete-left", [176, 142, 39]),
+    ("refined-hazard-concrete-left", [116, 94, 26]),
+  ]
+  .iter()
+  .map(|&(name, color)| PaletteEntry::new(name, color, PixelKind::Tile))
+  .collect()
+}
+
+/// Maps each pixel of the image to a palette index, or None for transparent pixels.
+/// Returns the indices in row-major order along with the width and height used.
+pub fn quantize(image: &DynamicImage, options: &PixelArtOptions) -> (Vec<Option<usize>>, u32, u32) {
+  let resized;
+  let image = match options.size {
+    Some([width, height]) => {
+      resized = image.resize(width, height, FilterType::Lanczos3);
+      &resized
+    }
+    None => image,
+  };
+  let (width, height) = image.dimensions();
+
+  let palette_colors: Vec<_> = options
+    .palette
+    .iter()
+    .map(|entry| exoquant::Color::new(entry.color[0], entry.color[1], entry.color[2], 255))
+    .collect();
+  let ditherer: Box<dyn exoquant::ditherer::Ditherer> = match options.dither {
+    Dither::None => Box::new(exoquant::ditherer::None),
+    Dither::Ordered => Box::new(exoquant::ditherer::Ordered),
+    Dither::FloydSteinberg => Box::new(exoquant::ditherer::FloydSteinberg::new()),
+  };
+  let colorspace = exoquant::SimpleColorSpace::default();
+  let remapper = exoquant::Remapper::new(&palette_colors, &colorspace, &*ditherer);
+
+  let pixels: Vec<_> = image
+    .pixels()
+    .map(|(_, _, image::Rgba(data))| exoquant::Color::new(data[0], data[1], data[2], 255))
+    .collect();
+  let quantized = remapper.remap_usize(&pixels, width as usize);
+  let indices = image
+    .pixels()
+    .zip(quantized)
+    .map(|((_, _, image::Rgba(data)), palette_index)| {
+      if data[3] >= options.alpha_threshold {
+        Some(palette_index)
+      } else {
+        None
+      }
+    })
+    .collect();
+  (indices, width, height)
+}
+
+/// Renders an image as a blueprint with one entity or tile per pixel, centered on the origin.
+pub fn pixel_art(name: String, image: &DynamicImage, options: &PixelArtOptions) -> Blueprint {
+  let (indices, width, height) = quantize(image, options);
+  let mut entities = Vec::new();
+  let mut tiles = Vec::new();
+  for (index, palette_index) in indices.into_iter().enumerate() {
+    let palette_index = match palette_index {
+      Some(palette_index) => palette_index,
+      None => continue,
+    };
+    let entry = &options.palette[palette_index];
+    let position = Position {
+      x: (index as i32 % width as i32 - width as i32 / 2) as f64,
+      y: (index as i32 / width as i32 - height as i32 / 2) as f64,
+    };
+    match entry.kind {
+      PixelKind::Entity => entities.push(Entity {
+        position,
+        name: entry.name.clone(),
+        underground_type: if entry.name.contains("underground") {
+          Some(UndergroundBeltOrLoaderType::Output)
+        } else {
+          None
+        },
+        ..Default::default()
+      }),
+      PixelKind::Tile => tiles.push(Tile {
+        name: entry.name.clone(),
+        position,
+      }),
+    }
+  }
+  let mut result = Blueprint::simple(name, entities, GameVersion::default());
+  result.tiles = tiles;
+  result
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum LampColorSource {
+  /// Lamps read a color signal from constant combinators, one per network of same-colored lamps,
+  /// placed around the edge of the picture. A network out of reach of the edge needs an unlit
+  /// pixel nearby for its combinator instead.
+  Signals,
+  /// Lamps have their color set directly, so each one can be any color and no wires are needed.
+  /// Only 2.0 supports this, so the blueprint is made for 2.0.
+  LampColor,
+}
+
+/// The colors a lamp can show from a color signal, with roughly how they look in game.
+pub const LAMP_SIGNAL_COLORS: [(&str, [u8; 3]); 7] = [
+  ("signal-red", [255, 40, 40]),
+  ("signal-green", [40, 255, 40]),
+  ("signal-blue", [40, 120, 255]),
+  ("signal-yellow", [255, 255, 40]),
+  ("signal-pink", [255, 60, 200]),
+  ("signal-cyan", [40, 255, 255]),
+  ("signal-white", [255, 255, 255]),
+];
+
+#[derive(Clone, Debug)]
+pub enum LampArtError {
+  /// every spot within wire reach of the network of lamps that includes this pixel, counted
+  /// from the top left of the (resized) image, is already lit or taken by another combinator
+  NoCombinatorSpot { pixel: [i32; 2] },
+}
+
+impl fmt::Display for LampArtError {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      LampArtError::NoCombinatorSpot { pixel } => write!(
+        formatter,
+        "no room for a constant combinator within wire reach of the lamp at pixel ({}, {})",
+        pixel[0], pixel[1]
+      ),
+    }
+  }
+}
+
+impl std::error::Error for LampArtError {}
+
+/// Renders an image as a grid of small lamps, centered on the origin. `options.palette` is ignored.
+pub fn lamp_art(
+  name: String,
+  image: &DynamicImage,
+  options: &PixelArtOptions,
+  source: LampColorSource,
+) -> Result<Blueprint, LampArtError> {
+  match source {
+    LampColorSource::Signals => signal_lamp_art(name, image, options),
+    LampColorSource::LampColor => Ok(colored_lamp_art(name, image, options)),
+  }
+}
+
+fn colored_lamp_art(name: String, image: &DynamicImage, options: &PixelArtOptions) -> Blueprint {
+  let image = match options.size {
+    Some([width, height]) => image.resize(width, height, FilterType::Lanczos3),
+    None => image.clone(),
+  };
+  let (width, height) = image.dimensions();
+  let entities = image
+    .pixels()
+    .filter(|(_, _, image::Rgba(data))| data[3] >= options.alpha_threshold)
+    .map(|(x, y, image::Rgba(data))| Entity {
+      name: "small-lamp".to_string(),
+      position: Position {
+        x: (x as i32 - width as i32 / 2) as f64,
+        y: (y as i32 - height as i32 / 2) as f64,
+      },
+      color: Some(Color {
+        r: data[0] as f64 / 255.0,
+        g: data[1] as f64 / 255.0,
+        b: data[2] as f64 / 255.0,
+        a: 1.0,
+      }),
+      ..Default::default()
+    })
+    .collect();
+  Blueprint::simple(name, entities, GameVersion::V2_0)
+}
+
+fn signal_lamp_art(
+  name: String,
+  image: &DynamicImage,
+  options: &PixelArtOptions,
+) -> Result<Blueprint, LampArtError> {
+  // the first palette entry is "off", which is left empty
+  let mut palette = vec![PaletteEntry::new("", [0, 0, 0], PixelKind::Entity)];
+  palette.extend(
+    LAMP_SIGNAL_COLORS
+      .iter()
+      .map(|&(signal, color)| PaletteEntry::new(signal, color, PixelKind::Entity)),
+  );
+  let options = PixelArtOptions {
+    palette,
+    ..options.clone()
+  };
+  let (indices, width, height) = quantize(image, &options);
+  let (width, height) = (width as i32, height as i32);
+  let color_at = |x: i32, y: i32| -> Option<usize> {
+    if x < 0 || y < 0 || x >= width || y >= height {
+      return None;
+    }
+    indices[(y * width + x) as usize].filter(|&index| index != 0)
+  };
+  let position = |x: i32, y: i32| Position {
+    x: (x - width / 2) as f64,
+    y: (y - height / 2) as f64,
+  };
+
+  let mut entities = Vec::new();
+  let mut lamp_numbers = vec![None; indices.len()];
+  for y in 0..height {
+    for x in 0..width {
+      if let Some(color) = color_at(x, y) {
+        entities.push(Entity {
+          name: "small-lamp".to_string(),
+          position: position(x, y),
+          control_behavior: Some(ControlBehavior {
+            circuit_condition: Some(CircuitCondition {
+              first_signal: Some(SignalID::virtual_signal(&options.palette[color].name)),
+              second_signal: None,
+              constant: Some(0),
+              comparator: Comparator::Greater,
+            }),
+            use_colors: Some(true),
+            ..Default::default()
+          }),
+          ..Default::default()
+        });
+        lamp_numbers[(y * width + x) as usize] = Some(entities.len() as i32);
+      }
+    }
+  }
+
+  // Lamps of the same color can share a network, since wires reach over the lamps between
+  // them, so each network only needs one combinator. The combinators go in a border around
+  // the picture; a network that's out of reach of it gets its combinator on an unlit pixel
+  // instead, where it doesn't show.
+  let reach = circuit_wire_reach("small-lamp").min(circuit_wire_reach("constant-combinator"));
+  let span = reach.floor() as i32;
+  let mut offsets: Vec<(i32, i32)> = (-span..=span)
+    .flat_map(|dy| (-span..=span).map(move |dx| (dx, dy)))
+    .filter(|&(dx, dy)| (dx, dy) != (0, 0) && ((dx * dx + dy * dy) as f64).sqrt() <= reach)
+    .collect();
+  offsets.sort_by_key(|&(dx, dy)| dx * dx + dy * dy);
+  let in_border = |x: i32, y: i32| {
+    (-1..=width).contains(&x)
+      && (-1..=height).contains(&y)
+      && (x == -1 || y == -1 || x == width || y == height)
+  };
+  let unlit =
+    |x: i32, y: i32| x >= 0 && y >= 0 && x < width && y < height && color_at(x, y).is_none();
+
+  let mut wires = Vec::new();
+  let mut visited = vec![false; indices.len()];
+  let mut combinator_spots = HashSet::new();
+  for y in 0..height {
+    for x in 0..width {
+      let color = match color_at(x, y) {
+        Some(color) => color,
+        None => continue,
+      };
+      if visited[(y * width + x) as usize] {
+        continue;
+      }
+      visited[(y * width + x) as usize] = true;
+      let mut network = vec![(x, y)];
+      let mut stack = vec![(x, y)];
+      while let Some((x, y)) = stack.pop() {
+        let here = lamp_numbers[(y * width + x) as usize].unwrap();
+        for &(dx, dy) in &offsets {
+          let (nx, ny) = (x + dx, y + dy);
+          if color_at(nx, ny) == Some(color) && !visited[(ny * width + nx) as usize] {
+            visited[(ny * width + nx) as usize] = true;
+            wires.push((here, lamp_numbers[(ny * width + nx) as usize].unwrap()));
+            network.push((nx, ny));
+            stack.push((nx, ny));
+          }
+        }
+      }
+
+      // the nearest free spot on the border, or failing that on an unlit pixel
+      let spot = [&in_border as &dyn Fn(i32, i32) -> bool, &unlit]
+        .iter()
+        .find_map(|allowed| {
+          network
+            .iter()
+            .flat_map(|&(x, y)| {
+              offsets
+                .iter()
+                .map(move |&(dx, dy)| ((x, y), (x + dx, y + dy), dx * dx + dy * dy))
+            })
+            .filter(|&(_, (x, y), _)| allowed(x, y) && !combinator_spots.contains(&(x, y)))
+            .min_by_key(|&(_, _, distance)| distance)
+        });
+      let ((lamp_x, lamp_y), (spot_x, spot_y), _) =
+        spot.ok_or(LampArtError::NoCombinatorSpot { pixel: [x, y] })?;
+      combinator_spots.insert((spot_x, spot_y));
+      entities.push(Entity {
+        name: "constant-combinator".to_string(),
+        position: position(spot_x, spot_y),
+        direction: Some(0),
+        control_behavior: Some(ControlBehavior {
+          filters: vec![ConstantCombinatorFilter {
+            signal: SignalID::virtual_signal(&options.palette[color].name),
+            count: 1,
+            index: 1,
+          }],
+          ..Default::default()
+        }),
+        ..Default::default()
+      });
+      wires.push((
+        entities.len() as i32,
+        lamp_numbers[(lamp_y * width + lamp_x) as usize].unwrap(),
+      ));
+    }
+  }
+
+  let mut result = Blueprint::simple(name, entities, GameVersion::default());
+  for (first, second) in wires {
+    result.connect(WireColor::Red, (first, 1), (second, 1));
+  }
+  Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// A fully lit red picture, `size` pixels square, with a green pixel in the middle.
+  fn red_with_green_middle(size: u32) -> DynamicImage {
+    DynamicImage::ImageRgba8(image::RgbaImage::from_fn(size, size, |x, y| {
+      if (x, y) == (size / 2, size / 2) {
+        image::Rgba([40, 255, 40, 255])
+      } else {
+        image::Rgba([255, 40, 40, 255])
+      }
+    }))
+  }
+
+  fn lamps(image: &DynamicImage) -> Result<Blueprint, LampArtError> {
+    let options = PixelArtOptions {
+      dither: Dither::None,
+      ..PixelArtOptions::new(Vec::new())
+    };
+    lamp_art(
+      "lamps".to_string(),
+      image,
+      &options,
+      LampColorSource::Signals,
+    )
+  }
+
+  #[test]
+  fn every_network_gets_a_combinator() {
+    let blueprint = lamps(&red_with_green_middle(5)).unwrap();
+    let combinators = blueprint
+      .entities
+      .iter()
+      .filter(|entity| entity.name == "constant-combinator")
+      .count();
+    assert_eq!(combinators, 2);
+  }
+
+  #[test]
+  fn lamps_out_of_reach_of_any_spot_are_an_error() {
+    match lamps(&red_with_green_middle(41)) {
+      Err(LampArtError::NoCombinatorSpot { pixel }) => assert_eq!(pixel, [20, 20]),
+      Ok(_) => panic!("the green lamp has nowhere for its combinator"),
+    }
+  }
+}
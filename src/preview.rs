@@ -0,0 +1,337 @@
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use super::blueprint::*;
+use super::pixel_art;
+use super::prototypes::*;
+use super::simplified::*;
+
+#[derive(Clone, Debug)]
+pub struct PreviewOptions {
+  /// pixels per tile
+  pub tile_size: u32,
+  /// where to look for `<entity name>.png` icons; entities without one are drawn as colored boxes
+  pub icon_directory: Option<PathBuf>,
+  /// empty tiles around the edge of the picture
+  pub margin: u32,
+}
+
+impl Default for PreviewOptions {
+  fn default() -> PreviewOptions {
+    PreviewOptions {
+      tile_size: 16,
+      icon_directory: Some(PathBuf::from("images")),
+      margin: 1,
+    }
+  }
+}
+
+const BACKGROUND: Rgba<u8> = Rgba([36, 36, 36, 255]);
+const GRID: Rgba<u8> = Rgba([48, 48, 48, 255]);
+const OUTLINE: Rgba<u8> = Rgba([12, 12, 12, 255]);
+const ARROW: Rgba<u8> = Rgba([255, 220, 60, 255]);
+const UNDERGROUND_PAIR: Rgba<u8> = Rgba([255, 140, 20, 255]);
+
+fn category_color(category: EntityCategory) -> Rgba<u8> {
+  match category {
+    EntityCategory::Belt => Rgba([200, 170, 60, 255]),
+    EntityCategory::UndergroundBelt => Rgba([170, 140, 50, 255]),
+    EntityCategory::Splitter => Rgba([190, 150, 70, 255]),
+    EntityCategory::Loader => Rgba([150, 120, 60, 255]),
+    EntityCategory::Inserter => Rgba([90, 140, 200, 255]),
+    EntityCategory::Container => Rgba([140, 100, 60, 255]),
+    EntityCategory::Production => Rgba([110, 120, 130, 255]),
+    EntityCategory::Power => Rgba([170, 110, 60, 255]),
+    EntityCategory::Circuit => Rgba([80, 170, 110, 255]),
+    EntityCategory::Fluid => Rgba([70, 110, 160, 255]),
+    EntityCategory::Rail => Rgba([120, 100, 90, 255]),
+    EntityCategory::Other => Rgba([130, 130, 130, 255]),
+  }
+}
+
+/// A stable color for names we know nothing about, so the same entity always looks the same.
+fn name_color(name: &str) -> Rgba<u8> {
+  let hash = name.bytes().fold(2166136261u32, |hash, byte| {
+    (hash ^ byte as u32).wrapping_mul(16777619)
+  });
+  Rgba([
+    96 + (hash & 0x7f) as u8,
+    96 + ((hash >> 8) & 0x7f) as u8,
+    96 + ((hash >> 16) & 0x7f) as u8,
+    255,
+  ])
+}
+
+fn tile_color(name: &str) -> Rgba<u8> {
+  pixel_art::concrete_palette()
+    .into_iter()
+    .find(|entry| entry.name == name)
+    .map_or_else(
+      || {
+        let Rgba([r, g, b, _]) = name_color(name);
+        Rgba([r / 2, g / 2, b / 2, 255])
+      },
+      |entry| Rgba([entry.color[0], entry.color[1], entry.color[2], 255]),
+    )
+}
+
+fn direction_vector(direction: u8) -> [f64; 2] {
+  let angle = direction as f64 * std::f64::consts::PI / 4.0;
+  [angle.sin(), -angle.cos()]
+}
+
+struct Canvas<'a> {
+  image: RgbaImage,
+  origin: [f64; 2],
+  options: &'a PreviewOptions,
+  icons: HashMap<String, Option<RgbaImage>>,
+}
+
+impl<'a> Canvas<'a> {
+  fn pixel(&self, position: [f64; 2]) -> [i64; 2] {
+    let scale = self.options.tile_size as f64;
+    [
+      ((position[0] - self.origin[0]) * scale).round() as i64,
+      ((position[1] - self.origin[1]) * scale).round() as i64,
+    ]
+  }
+
+  fn put(&mut self, x: i64, y: i64, color: Rgba<u8>) {
+    if x >= 0 && y >= 0 && x < self.image.width() as i64 && y < self.image.height() as i64 {
+      self.image.put_pixel(x as u32, y as u32, color);
+    }
+  }
+
+  fn fill(&mut self, area: [[i64; 2]; 2], color: Rgba<u8>) {
+    for y in area[1][0]..area[1][1] {
+      for x in area[0][0]..area[0][1] {
+        self.put(x, y, color);
+      }
+    }
+  }
+
+  fn outline(&mut self, area: [[i64; 2]; 2], color: Rgba<u8>) {
+    for x in area[0][0]..area[0][1] {
+      self.put(x, area[1][0], color);
+      self.put(x, area[1][1] - 1, color);
+    }
+    for y in area[1][0]..area[1][1] {
+      self.put(area[0][0], y, color);
+      self.put(area[0][1] - 1, y, color);
+    }
+  }
+
+  fn highlight(&mut self, area: [[f64; 2]; 2], color: Rgba<u8>) {
+    let [left, top] = self.pixel([area[0][0], area[1][0]]);
+    let [right, bottom] = self.pixel([area[0][1], area[1][1]]);
+    for y in top..bottom {
+      for x in left..right {
+        if x >= 0 && y >= 0 && x < self.image.width() as i64 && y < self.image.height() as i64 {
+          let pixel = self.image.get_pixel_mut(x as u32, y as u32);
+          for channel in 0..3 {
+            pixel.0[channel] = ((pixel.0[channel] as u32 * 2 + color.0[channel] as u32) / 3) as u8;
+          }
+        }
+      }
+    }
+    self.outline([[left, right], [top, bottom]], color);
+    self.outline([[left + 1, right - 1], [top + 1, bottom - 1]], color);
+  }
+
+  fn line(&mut self, from: [i64; 2], to: [i64; 2], color: Rgba<u8>) {
+    let steps = max_abs(to[0] - from[0], to[1] - from[1]).max(1);
+    for step in 0..=steps {
+      let x = from[0] + (to[0] - from[0]) * step / steps;
+      let y = from[1] + (to[1] - from[1]) * step / steps;
+      self.put(x, y, color);
+    }
+  }
+
+  fn icon(&mut self, name: &str) -> Option<&RgbaImage> {
+    let directory = self.options.icon_directory.as_ref()?;
+    self
+      .icons
+      .entry(name.to_string())
+      .or_insert_with(|| {
+        image::open(directory.join(format!("{}.png", name)))
+          .ok()
+          .map(|icon| icon.to_rgba8())
+      })
+      .as_ref()
+  }
+
+  fn draw_tile(&mut self, tile: &Tile) {
+    let footprint = tile_footprint(tile);
+    let [left, top] = self.pixel([footprint[0][0], footprint[1][0]]);
+    let [right, bottom] = self.pixel([footprint[0][1], footprint[1][1]]);
+    self.fill([[left, right], [top, bottom]], tile_color(&tile.name));
+  }
+
+  fn draw_entity(&mut self, entity: &Entity) {
+    let footprint = entity_footprint(entity);
+    let [left, top] = self.pixel([footprint[0][0], footprint[1][0]]);
+    let [right, bottom] = self.pixel([footprint[0][1], footprint[1][1]]);
+    let area = [[left, right], [top, bottom]];
+    let prototype = find_prototype(&entity.name);
+    let color = prototype.map_or_else(
+      || name_color(&entity.name),
+      |prototype| category_color(prototype.category),
+    );
+    self.fill(area, color);
+
+    let side = (right - left).min(bottom - top) as u32;
+    if side > 2 {
+      if let Some(icon) = self.icon(&entity.name) {
+        let icon = imageops::resize(icon, side - 2, side - 2, FilterType::Triangle);
+        let x = (left + right - side as i64) / 2 + 1;
+        let y = (top + bottom - side as i64) / 2 + 1;
+        imageops::overlay(&mut self.image, &icon, x, y);
+      }
+    }
+    self.outline(area, OUTLINE);
+
+    if let Some(direction) = entity.direction {
+      // inserters face the tile they pick up from; show the way items move instead
+      let direction = match prototype.map(|prototype| prototype.category) {
+        Some(EntityCategory::Inserter) => (direction + 4) % 8,
+        _ => direction,
+      };
+      self.arrow(entity, direction);
+    }
+  }
+
+  fn arrow(&mut self, entity: &Entity, direction: u8) {
+    let center = [entity.position.x, entity.position.y];
+    let [dx, dy] = direction_vector(direction);
+    let at = |forward: f64, sideways: f64| {
+      [
+        center[0] + dx * forward - dy * sideways,
+        center[1] + dy * forward + dx * sideways,
+      ]
+    };
+    let tail = self.pixel(at(-0.3, 0.0));
+    let head = self.pixel(at(0.35, 0.0));
+    let left = self.pixel(at(0.1, -0.2));
+    let right = self.pixel(at(0.1, 0.2));
+    self.line(tail, head, ARROW);
+    self.line(left, head, ARROW);
+    self.line(right, head, ARROW);
+  }
+
+  fn draw_underground_pairs(&mut self, entities: &[Entity]) {
+    for (entrance, exit) in underground_pairs(entities) {
+      let from = self.pixel([entities[entrance].position.x, entities[entrance].position.y]);
+      let to = self.pixel([entities[exit].position.x, entities[exit].position.y]);
+      self.line(from, to, UNDERGROUND_PAIR);
+    }
+  }
+}
+
+fn max_abs(first: i64, second: i64) -> i64 {
+  first.abs().max(second.abs())
+}
+
+pub fn render_entities(entities: &[Entity], tiles: &[Tile], options: &PreviewOptions) -> RgbaImage {
+  render_highlighted(entities, tiles, &[], options)
+}
+
+/// Like `render_entities`, but also tints and outlines some areas (`[[left, right], [top, bottom]]`
+/// in tile units), which don't have to be inside the entities' footprint.
+pub fn render_highlighted(
+  entities: &[Entity],
+  tiles: &[Tile],
+  highlights: &[([[f64; 2]; 2], Rgba<u8>)],
+  options: &PreviewOptions,
+) -> RgbaImage {
+  let mut bounds = [[f64::MAX, f64::MIN], [f64::MAX, f64::MIN]];
+  let mut include = |area: [[f64; 2]; 2]| {
+    for axis in 0..2 {
+      bounds[axis][0] = bounds[axis][0].min(area[axis][0]);
+      bounds[axis][1] = bounds[axis][1].max(area[axis][1]);
+    }
+  };
+  for entity in entities {
+    include(entity_footprint(entity));
+  }
+  for tile in tiles {
+    include(tile_footprint(tile));
+  }
+  for &(area, _) in highlights {
+    include(area);
+  }
+  if bounds[0][0] > bounds[0][1] {
+    bounds = [[-0.5, 0.5], [-0.5, 0.5]];
+  }
+  // line the grid up with the edges of what's drawn, which are whole tiles apart
+  let origin = [
+    bounds[0][0] - options.margin as f64,
+    bounds[1][0] - options.margin as f64,
+  ];
+  let size = [
+    ((bounds[0][1] - origin[0]).ceil() as u32 + options.margin) * options.tile_size,
+    ((bounds[1][1] - origin[1]).ceil() as u32 + options.margin) * options.tile_size,
+  ];
+
+  let mut canvas = Canvas {
+    image: RgbaImage::from_pixel(size[0], size[1], BACKGROUND),
+    origin,
+    options,
+    icons: HashMap::new(),
+  };
+  for x in (0..size[0]).step_by(options.tile_size as usize) {
+    canvas.line([x as i64, 0], [x as i64, size[1] as i64], GRID);
+  }
+  for y in (0..size[1]).step_by(options.tile_size as usize) {
+    canvas.line([0, y as i64], [size[0] as i64, y as i64], GRID);
+  }
+  for tile in tiles {
+    canvas.draw_tile(tile);
+  }
+  for entity in entities {
+    canvas.draw_entity(entity);
+  }
+  canvas.draw_underground_pairs(entities);
+  for &(area, color) in highlights {
+    canvas.highlight(area, color);
+  }
+  canvas.image
+}
+
+pub fn render_blueprint(blueprint: &Blueprint, options: &PreviewOptions) -> RgbaImage {
+  render_entities(&blueprint.entities, &blueprint.tiles, options)
+}
+
+pub fn render_objects(objects: &[Object], options: &PreviewOptions) -> RgbaImage {
+  let entities: Vec<Entity> = objects.iter().flat_map(|object| object.render()).collect();
+  render_entities(&entities, &[], options)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn tiles_line_up_with_the_entities_on_them() {
+    let options = PreviewOptions {
+      tile_size: 4,
+      icon_directory: None,
+      margin: 0,
+    };
+    let tile = Tile {
+      name: "concrete".to_string(),
+      position: Position { x: 0.0, y: 0.0 },
+    };
+    let chest = Entity {
+      name: "wooden-chest".to_string(),
+      position: Position { x: 0.5, y: 0.5 },
+      ..Default::default()
+    };
+    let floor = render_entities(&[], std::slice::from_ref(&tile), &options);
+    assert_eq!(floor.dimensions(), (4, 4));
+    assert!(floor.pixels().all(|&pixel| pixel == tile_color(&tile.name)));
+    let image = render_entities(&[chest], &[tile], &options);
+    assert_eq!(image.dimensions(), (4, 4));
+  }
+}
@@ -0,0 +1,245 @@
+use super::blueprint::*;
+
+use UndergroundBeltOrLoaderType::{Input, Output};
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum EntityCategory {
+  Belt,
+  UndergroundBelt,
+  Splitter,
+  Loader,
+  Inserter,
+  Container,
+  Production,
+  Power,
+  Circuit,
+  Fluid,
+  Rail,
+  Other,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct EntityPrototype {
+  pub name: &'static str,
+  pub category: EntityCategory,
+  /// width and height when facing north
+  pub size: [u32; 2],
+  /// how far apart the entrance and exit of an underground belt can be
+  pub max_underground_distance: Option<u8>,
+}
+
+const fn prototype(
+  name: &'static str,
+  category: EntityCategory,
+  size: [u32; 2],
+) -> EntityPrototype {
+  EntityPrototype {
+    name,
+    category,
+    size,
+    max_underground_distance: None,
+  }
+}
+
+const fn underground(name: &'static str, distance: u8) -> EntityPrototype {
+  EntityPrototype {
+    name,
+    category: EntityCategory::UndergroundBelt,
+    size: [1, 1],
+    max_underground_distance: Some(distance),
+  }
+}
+
+use EntityCategory::*;
+
+pub const PROTOTYPES: &[EntityPrototype] = &[
+  prototype("transport-belt", Belt, [1, 1]),
+  prototype("fast-transport-belt", Belt, [1, 1]),
+  prototype("express-transport-belt", Belt, [1, 1]),
+  underground("underground-belt", 5),
+  underground("fast-underground-belt", 7),
+  underground("express-underground-belt", 9),
+  prototype("splitter", Splitter, [2, 1]),
+  prototype("fast-splitter", Splitter, [2, 1]),
+  prototype("express-splitter", Splitter, [2, 1]),
+  prototype("loader", Loader, [1, 2]),
+  prototype("fast-loader", Loader, [1, 2]),
+  prototype("express-loader", Loader, [1, 2]),
+  prototype("burner-inserter", Inserter, [1, 1]),
+  prototype("inserter", Inserter, [1, 1]),
+  prototype("long-handed-inserter", Inserter, [1, 1]),
+  prototype("fast-inserter", Inserter, [1, 1]),
+  prototype("filter-inserter", Inserter, [1, 1]),
+  prototype("stack-inserter", Inserter, [1, 1]),
+  prototype("stack-filter-inserter", Inserter, [1, 1]),
+  prototype("bulk-inserter", Inserter, [1, 1]),
+  prototype("wooden-chest", Container, [1, 1]),
+  prototype("iron-chest", Container, [1, 1]),
+  prototype("steel-chest", Container, [1, 1]),
+  prototype("logistic-chest-active-provider", Container, [1, 1]),
+  prototype("logistic-chest-passive-provider", Container, [1, 1]),
+  prototype("logistic-chest-storage", Container, [1, 1]),
+  prototype("logistic-chest-requester", Container, [1, 1]),
+  prototype("logistic-chest-buffer", Container, [1, 1]),
+  prototype("active-provider-chest", Container, [1, 1]),
+  prototype("passive-provider-chest", Container, [1, 1]),
+  prototype("storage-chest", Container, [1, 1]),
+  prototype("requester-chest", Container, [1, 1]),
+  prototype("buffer-chest", Container, [1, 1]),
+  prototype("assembling-machine-1", Production, [3, 3]),
+  prototype("assembling-machine-2", Production, [3, 3]),
+  prototype("assembling-machine-3", Production, [3, 3]),
+  prototype("chemical-plant", Production, [3, 3]),
+  prototype("oil-refinery", Production, [5, 5]),
+  prototype("centrifuge", Production, [3, 3]),
+  prototype("stone-furnace", Production, [2, 2]),
+  prototype("steel-furnace", Production, [2, 2]),
+  prototype("electric-furnace", Production, [3, 3]),
+  prototype("electric-mining-drill", Production, [3, 3]),
+  prototype("pumpjack", Production, [3, 3]),
+  prototype("lab", Production, [3, 3]),
+  prototype("beacon", Production, [3, 3]),
+  prototype("rocket-silo", Production, [9, 9]),
+  prototype("small-electric-pole", Power, [1, 1]),
+  prototype("medium-electric-pole", Power, [1, 1]),
+  prototype("big-electric-pole", Power, [2, 2]),
+  prototype("substation", Power, [2, 2]),
+  prototype("solar-panel", Power, [3, 3]),
+  prototype("accumulator", Power, [2, 2]),
+  prototype("boiler", Power, [3, 2]),
+  prototype("steam-engine", Power, [3, 5]),
+  prototype("constant-combinator", Circuit, [1, 1]),
+  prototype("arithmetic-combinator", Circuit, [1, 2]),
+  prototype("decider-combinator", Circuit, [1, 2]),
+  prototype("small-lamp", Circuit, [1, 1]),
+  prototype("power-switch", Circuit, [2, 2]),
+  prototype("programmable-speaker", Circuit, [1, 1]),
+  prototype("pipe", Fluid, [1, 1]),
+  prototype("pipe-to-ground", Fluid, [1, 1]),
+  prototype("pump", Fluid, [1, 2]),
+  prototype("storage-tank", Fluid, [3, 3]),
+  prototype("straight-rail", Rail, [2, 2]),
+  prototype("curved-rail", Rail, [4, 8]),
+  prototype("rail-signal", Rail, [1, 1]),
+  prototype("rail-chain-signal", Rail, [1, 1]),
+  prototype("train-stop", Rail, [2, 2]),
+  prototype("locomotive", Rail, [2, 6]),
+  prototype("cargo-wagon", Rail, [2, 6]),
+  prototype("fluid-wagon", Rail, [2, 6]),
+  prototype("stone-wall", Other, [1, 1]),
+  prototype("gate", Other, [1, 1]),
+  prototype("radar", Other, [3, 3]),
+  prototype("roboport", Other, [4, 4]),
+];
+
+pub fn find_prototype(name: &str) -> Option<&'static EntityPrototype> {
+  PROTOTYPES.iter().find(|prototype| prototype.name == name)
+}
+
+/// How far red and green wires reach from an entity, in tiles. Two entities can be wired
+/// together if they are within reach of each other.
+pub fn circuit_wire_reach(name: &str) -> f64 {
+  match name {
+    "small-electric-pole" => 7.5,
+    "medium-electric-pole" => 9.0,
+    "big-electric-pole" => 30.0,
+    "substation" => 18.0,
+    _ => 9.0,
+  }
+}
+
+/// Poles carry wires for other entities without being part of what they do.
+pub fn is_electric_pole(name: &str) -> bool {
+  matches!(
+    name,
+    "small-electric-pole" | "medium-electric-pole" | "big-electric-pole" | "substation"
+  )
+}
+
+/// The width and height of an entity, taking its direction into account, or for locomotives
+/// and wagons the nearest of the four directions to their orientation.
+/// Unknown entities are assumed to be 1×1.
+pub fn entity_size(entity: &Entity) -> [u32; 2] {
+  let [width, height] = find_prototype(&entity.name).map_or([1, 1], |prototype| prototype.size);
+  let direction = match entity.orientation {
+    Some(orientation) => Some(((orientation * 4.0).round() as u8 % 4) * 2),
+    None => entity.direction,
+  };
+  match direction {
+    Some(2) | Some(6) => [height, width],
+    _ => [width, height],
+  }
+}
+
+/// `[[left, right], [top, bottom]]` of the area an entity covers, in the same units as its position.
+pub fn entity_footprint(entity: &Entity) -> [[f64; 2]; 2] {
+  let [width, height] = entity_size(entity);
+  [
+    [
+      entity.position.x - width as f64 / 2.0,
+      entity.position.x + width as f64 / 2.0,
+    ],
+    [
+      entity.position.y - height as f64 / 2.0,
+      entity.position.y + height as f64 / 2.0,
+    ],
+  ]
+}
+
+/// `[[left, right], [top, bottom]]` of the area a tile covers. Unlike entities, tiles are
+/// positioned by their top left corner.
+pub fn tile_footprint(tile: &Tile) -> [[f64; 2]; 2] {
+  [
+    [tile.position.x, tile.position.x + 1.0],
+    [tile.position.y, tile.position.y + 1.0],
+  ]
+}
+
+/// The tiles an entity covers, as the integer positions of their centers.
+pub fn footprint_tiles(entity: &Entity) -> impl Iterator<Item = [i64; 2]> {
+  let [[left, right], [top, bottom]] = entity_footprint(entity);
+  let tile = |value: f64| value.round() as i64;
+  let (left, right) = (tile(left + 0.5), tile(right - 0.5));
+  (tile(top + 0.5)..=tile(bottom - 0.5)).flat_map(move |y| (left..=right).map(move |x| [x, y]))
+}
+
+/// Underground belt entrances and their exits, as indices into `entities`. An entrance goes to
+/// the nearest exit of the same name ahead of it, within its maximum distance.
+pub fn underground_pairs(entities: &[Entity]) -> Vec<(usize, usize)> {
+  let mut result = Vec::new();
+  for (index, entrance) in entities.iter().enumerate() {
+    if entrance.underground_type != Some(Input) {
+      continue;
+    }
+    let distance = match find_prototype(&entrance.name)
+      .and_then(|prototype| prototype.max_underground_distance)
+    {
+      Some(distance) => distance,
+      None => continue,
+    };
+    let direction = entrance.direction.unwrap_or(0);
+    let (dx, dy) = match direction {
+      0 => (0.0, -1.0),
+      2 => (1.0, 0.0),
+      4 => (0.0, 1.0),
+      _ => (-1.0, 0.0),
+    };
+    let exit = (1..=distance).find_map(|step| {
+      let position = [
+        entrance.position.x + dx * step as f64,
+        entrance.position.y + dy * step as f64,
+      ];
+      entities.iter().position(|exit| {
+        exit.name == entrance.name
+          && exit.direction.unwrap_or(0) == direction
+          && exit.underground_type == Some(Output)
+          && (exit.position.x - position[0]).abs() < 0.01
+          && (exit.position.y - position[1]).abs() < 0.01
+      })
+    });
+    if let Some(exit) = exit {
+      result.push((index, exit));
+    }
+  }
+  result
+}
@@ -0,0 +1,413 @@
+use super::blueprint::*;
+
+/// Rails are laid on a grid of 2×2 tile cells. Cell `[x, y]` covers tiles `2x..2x+2` and
+/// `2y..2y+2`, so the straight rail in it is at the odd position `[2x + 1, 2y + 1]`.
+pub type RailCell = [i32; 2];
+
+/// A straight piece of track filling one rail grid cell. Directions 0 and 4 are the same
+/// north-south piece, and 2 and 6 the same east-west one. Odd directions are the diagonal
+/// pieces, which only fill the corner of the cell they're named after: 1 is the northeast
+/// corner, joining the middle of the north edge to the middle of the east edge.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct StraightRail {
+  pub cell: RailCell,
+  pub direction: u8,
+}
+
+/// A curved piece of track, turning from one of the straight directions to a diagonal one or
+/// back. Unlike straight rails, curved rails sit on the even positions between rail cells.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct CurvedRail {
+  pub position: [i32; 2],
+  pub direction: u8,
+}
+
+impl StraightRail {
+  pub fn new(cell: RailCell, direction: u8) -> StraightRail {
+    StraightRail { cell, direction }
+  }
+
+  pub fn is_diagonal(&self) -> bool {
+    self.direction % 2 == 1
+  }
+
+  /// The middles of the two cell edges that the piece joins, in tiles.
+  pub fn ends(&self) -> [[f64; 2]; 2] {
+    let [x, y] = [self.cell[0] as f64 * 2.0, self.cell[1] as f64 * 2.0];
+    let edge = |direction: u8| match direction % 8 {
+      0 => [x + 1.0, y],
+      2 => [x + 2.0, y + 1.0],
+      4 => [x + 1.0, y + 2.0],
+      _ => [x, y + 1.0],
+    };
+    if self.is_diagonal() {
+      [edge(self.direction - 1), edge(self.direction + 1)]
+    } else {
+      [edge(self.direction), edge(self.direction + 4)]
+    }
+  }
+
+  pub fn entity(&self) -> Entity {
+    Entity {
+      name: "straight-rail".to_string(),
+      position: Position {
+        x: (self.cell[0] * 2 + 1) as f64,
+        y: (self.cell[1] * 2 + 1) as f64,
+      },
+      // the game only uses 0 and 2 for pieces that aren't diagonal
+      direction: Some(if self.is_diagonal() {
+        self.direction
+      } else {
+        self.direction % 4
+      }),
+      ..Default::default()
+    }
+  }
+}
+
+impl CurvedRail {
+  pub fn new(position: [i32; 2], direction: u8) -> CurvedRail {
+    assert!(
+      position[0] % 2 == 0 && position[1] % 2 == 0,
+      "curved rails go at even positions"
+    );
+    CurvedRail {
+      position,
+      direction,
+    }
+  }
+
+  pub fn entity(&self) -> Entity {
+    Entity {
+      name: "curved-rail".to_string(),
+      position: Position {
+        x: self.position[0] as f64,
+        y: self.position[1] as f64,
+      },
+      direction: Some(self.direction),
+      ..Default::default()
+    }
+  }
+}
+
+/// `length` straight rails heading in `direction` from `start`. Diagonal track zigzags
+/// between the two corner pieces across the diagonal, so its cells alternate between stepping
+/// one way and the other.
+pub fn straight_track(start: RailCell, direction: u8, length: u32) -> Vec<StraightRail> {
+  let step = |cell: RailCell, direction: u8| -> RailCell {
+    match direction % 8 {
+      0 => [cell[0], cell[1] - 1],
+      2 => [cell[0] + 1, cell[1]],
+      4 => [cell[0], cell[1] + 1],
+      _ => [cell[0] - 1, cell[1]],
+    }
+  };
+  let mut result = Vec::new();
+  let mut cell = start;
+  for index in 0..length {
+    if direction % 2 != 1 {
+      result.push(StraightRail::new(cell, direction % 4));
+      cell = step(cell, direction);
+    } else if index % 2 == 1 {
+      result.push(StraightRail::new(cell, (direction + 2) % 8));
+      cell = step(cell, (direction + 1) % 8);
+    } else {
+      // the piece in the corner to the left of the way it's heading, leaving through the
+      // edge on that side, and then the one to the right
+      result.push(StraightRail::new(cell, (direction + 6) % 8));
+      cell = step(cell, (direction + 7) % 8);
+    }
+  }
+  result
+}
+
+/// The direction of a signal on the right hand side of trains travelling in `travel`, which
+/// are the ones it's for. Signals face the trains coming towards them, where train stops face
+/// the way the trains go.
+pub fn signal_direction(travel: u8) -> u8 {
+  (travel + 4) % 8
+}
+
+pub fn rail_signal(position: [f64; 2], travel: u8, chain: bool) -> Entity {
+  Entity {
+    name: if chain {
+      "rail-chain-signal"
+    } else {
+      "rail-signal"
+    }
+    .to_string(),
+    position: Position {
+      x: position[0],
+      y: position[1],
+    },
+    direction: Some(signal_direction(travel)),
+    ..Default::default()
+  }
+}
+
+/// A train stop in the rail cell at `cell`, for trains travelling in `travel` on the track
+/// next to it.
+pub fn train_stop(cell: RailCell, travel: u8, station: &str) -> Entity {
+  Entity {
+    name: "train-stop".to_string(),
+    position: Position {
+      x: (cell[0] * 2 + 1) as f64,
+      y: (cell[1] * 2 + 1) as f64,
+    },
+    direction: Some(travel),
+    station: Some(station.to_string()),
+    ..Default::default()
+  }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum StationKind {
+  /// inserters take from the chests and fill the wagons
+  Loading,
+  /// inserters empty the wagons into the chests
+  Unloading,
+}
+
+#[derive(Clone, Debug)]
+pub struct StationOptions {
+  pub kind: StationKind,
+  pub locomotives: u32,
+  pub wagons: u32,
+  pub chest: String,
+  pub inserter: String,
+  /// whether there are chests on both sides of the track, or only the left
+  pub both_sides: bool,
+  /// if set, the station comes with a train parked in it, following this schedule
+  pub schedule: Option<Vec<ScheduleRecord>>,
+}
+
+impl StationOptions {
+  pub fn new(kind: StationKind, wagons: u32) -> StationOptions {
+    StationOptions {
+      kind,
+      locomotives: 1,
+      wagons,
+      chest: "steel-chest".to_string(),
+      inserter: "fast-inserter".to_string(),
+      both_sides: true,
+      schedule: None,
+    }
+  }
+}
+
+/// Every locomotive and wagon takes up 6 tiles of track, with a tile between them.
+pub const VEHICLE_SPACING: i32 = 7;
+
+/// A station for trains heading north, with `options.locomotives` locomotives in front of
+/// `options.wagons` cargo wagons. The track runs down the tiles from x = 0 to 2, and the front
+/// of a stopped train is at y = 0, where the train stop is. Each wagon gets six inserters and
+/// chests on each side, with medium electric poles in the gaps between wagons. There's a
+/// signal past the stop and a chain signal behind the last wagon, and the train itself if the
+/// options have a schedule for it.
+pub fn station(name: &str, options: &StationOptions) -> Blueprint {
+  let train_length = VEHICLE_SPACING * (options.locomotives + options.wagons) as i32;
+  // rails from two cells ahead of the train to just behind it
+  let first_cell = -2;
+  let cells = (train_length + 1) / 2 + 1 - first_cell;
+  let mut entities: Vec<Entity> = straight_track([0, first_cell], 4, cells as u32)
+    .iter()
+    .map(StraightRail::entity)
+    .collect();
+  entities.push(train_stop([1, 0], 0, name));
+  entities.push(rail_signal([2.5, -1.5], 0, false));
+  entities.push(rail_signal(
+    [2.5, (first_cell + cells) as f64 * 2.0 - 0.5],
+    0,
+    true,
+  ));
+
+  let mut sides = vec![(-0.5, -1.5, 6)];
+  if options.both_sides {
+    sides.push((2.5, 3.5, 2));
+  }
+  let tile = |x: f64, y: i32, name: &str, direction: Option<u8>| Entity {
+    name: name.to_string(),
+    position: Position {
+      x,
+      y: y as f64 + 0.5,
+    },
+    direction,
+    ..Default::default()
+  };
+  for wagon in 0..options.wagons as i32 {
+    let front = VEHICLE_SPACING * (options.locomotives as i32 + wagon);
+    for row in front..front + VEHICLE_SPACING - 1 {
+      for &(inserter_x, chest_x, towards_chest) in &sides {
+        // inserters face where they pick up from
+        let direction = match options.kind {
+          StationKind::Loading => towards_chest,
+          StationKind::Unloading => (towards_chest + 4) % 8,
+        };
+        entities.push(tile(inserter_x, row, &options.inserter, Some(direction)));
+        entities.push(tile(chest_x, row, &options.chest, None));
+      }
+    }
+  }
+  for wagon in 0..=options.wagons as i32 {
+    let gap = VEHICLE_SPACING * (options.locomotives as i32 + wagon) - 1;
+    entities.push(tile(-0.5, gap, "medium-electric-pole", None));
+  }
+  let mut result = Blueprint::simple(name.to_string(), entities, GameVersion::default());
+  if let Some(schedule) = &options.schedule {
+    add_train(
+      &mut result,
+      [0, 0],
+      0,
+      &Vehicle::consist(options.locomotives, options.wagons, 0),
+      schedule.clone(),
+    );
+  }
+  result
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Vehicle {
+  Locomotive,
+  /// a locomotive facing the back of the train, so it can be driven the other way
+  ReversedLocomotive,
+  CargoWagon,
+  FluidWagon,
+}
+
+impl Vehicle {
+  pub fn name(self) -> &'static str {
+    match self {
+      Vehicle::Locomotive | Vehicle::ReversedLocomotive => "locomotive",
+      Vehicle::CargoWagon => "cargo-wagon",
+      Vehicle::FluidWagon => "fluid-wagon",
+    }
+  }
+
+  /// `front` locomotives, then `wagons` cargo wagons, then `back` locomotives facing
+  /// backwards: what's usually written as a front-wagons-back train, like 1-4-1.
+  pub fn consist(front: u32, wagons: u32, back: u32) -> Vec<Vehicle> {
+    let mut result = vec![Vehicle::Locomotive; front as usize];
+    result.extend(vec![Vehicle::CargoWagon; wagons as usize]);
+    result.extend(vec![Vehicle::ReversedLocomotive; back as usize]);
+    result
+  }
+}
+
+/// Adds a train heading in `travel`, one of the four straight directions, with the front of
+/// its first vehicle at the far edge of the rail cell `front` and the rest of `consist` lined
+/// up behind it, `VEHICLE_SPACING` apart. Its locomotives share `schedule`.
+pub fn add_train(
+  blueprint: &mut Blueprint,
+  front: RailCell,
+  travel: u8,
+  consist: &[Vehicle],
+  schedule: Vec<ScheduleRecord>,
+) {
+  assert!(
+    travel % 2 != 1,
+    "trains can only be added on straight track"
+  );
+  let (dx, dy) = match travel % 8 {
+    0 => (0.0, -1.0),
+    2 => (1.0, 0.0),
+    4 => (0.0, 1.0),
+    _ => (-1.0, 0.0),
+  };
+  let front = [
+    (front[0] * 2 + 1) as f64 + dx,
+    (front[1] * 2 + 1) as f64 + dy,
+  ];
+  let orientation = (travel % 8) as f64 / 8.0;
+  let mut number = blueprint
+    .entities
+    .iter()
+    .map(|entity| entity.entity_number)
+    .max()
+    .unwrap_or(0);
+  let mut locomotives = Vec::new();
+  for (index, &vehicle) in consist.iter().enumerate() {
+    // the middle of the vehicle, half its length behind its front
+    let behind = (VEHICLE_SPACING * index as i32 + 3) as f64;
+    number += 1;
+    blueprint.entities.push(Entity {
+      entity_number: number,
+      name: vehicle.name().to_string(),
+      position: Position {
+        x: front[0] - dx * behind,
+        y: front[1] - dy * behind,
+      },
+      orientation: Some(if vehicle == Vehicle::ReversedLocomotive {
+        (orientation + 0.5) % 1.0
+      } else {
+        orientation
+      }),
+      ..Default::default()
+    });
+    if vehicle.name() == "locomotive" {
+      locomotives.push(number);
+    }
+  }
+  if !locomotives.is_empty() {
+    blueprint
+      .schedules
+      .push(Schedule::new(locomotives, schedule));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::prototypes::{entity_footprint, footprint_tiles};
+  use std::collections::HashSet;
+
+  #[test]
+  fn station_entities_stay_apart() {
+    let station = station("Iron", &StationOptions::new(StationKind::Unloading, 3));
+    let mut covered = HashSet::new();
+    for entity in &station.entities {
+      for tile in footprint_tiles(entity) {
+        assert!(
+          covered.insert(tile),
+          "{} at {:?} overlaps something else",
+          entity.name,
+          entity.position
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn station_inserters_line_up_with_the_wagons() {
+    let options = StationOptions {
+      schedule: Some(Vec::new()),
+      ..StationOptions::new(StationKind::Loading, 3)
+    };
+    let station = station("Iron", &options);
+    let named = |name: &str| -> Vec<&Entity> {
+      station
+        .entities
+        .iter()
+        .filter(|entity| entity.name == name)
+        .collect()
+    };
+    let wagons = named("cargo-wagon");
+    let inserters = named(&options.inserter);
+    assert_eq!(wagons.len(), 3);
+    for wagon in &wagons {
+      let [[left, right], [top, bottom]] = entity_footprint(wagon);
+      let alongside: Vec<&&Entity> = inserters
+        .iter()
+        .filter(|inserter| (top..bottom).contains(&inserter.position.y))
+        .collect();
+      assert_eq!(alongside.len(), 12, "the wagon at {:?}", wagon.position);
+      for inserter in alongside {
+        // right next to the wagon, on one side or the other
+        let gap = (inserter.position.x - left)
+          .abs()
+          .min((inserter.position.x - right).abs());
+        assert_eq!(gap, 0.5, "the inserter at {:?}", inserter.position);
+      }
+    }
+    assert_eq!(inserters.len(), 36);
+  }
+}
@@ -0,0 +1,617 @@
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::belt_routing::*;
+use super::blueprint::*;
+use super::simplified::*;
+use super::wiring::*;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoutingProblem {
+  #[serde(default = "default_name")]
+  pub name: String,
+  /// `[[min_x, max_x], [min_y, max_y]]`, inclusive
+  pub bounds: [[i32; 2]; 2],
+  #[serde(default)]
+  pub obstructions: Vec<[[i32; 2]; 2]>,
+  #[serde(default)]
+  pub objects: Vec<ObjectSpec>,
+  #[serde(default)]
+  pub assembler_groups: Vec<AssemblerGroup>,
+  /// Entities copied into the result as-is, such as electric poles. They do not obstruct routing.
+  #[serde(default)]
+  pub entities: Vec<EntitySpec>,
+  pub endpoints: Vec<EndpointSpec>,
+  #[serde(default = "default_iterations")]
+  pub iterations: usize,
+  #[serde(default)]
+  pub costs: CostModel,
+  /// Make the blueprint snap to a world-aligned grid the size of `bounds`, such as city blocks.
+  #[serde(default)]
+  pub snap_to_grid: bool,
+  /// Route every endpoint at once on this many threads (see `find_routes_parallel`), rather
+  /// than one endpoint after another.
+  #[serde(default)]
+  pub threads: Option<usize>,
+  /// Decides the result of parallel routing; the same seed gives the same routes on any number
+  /// of threads.
+  #[serde(default)]
+  pub seed: u64,
+  /// Circuit networks to wire up between `objects`, through the poles in `entities` if needed.
+  #[serde(default)]
+  pub networks: Vec<NetworkSpec>,
+}
+
+fn default_name() -> String {
+  "routed belts".to_string()
+}
+
+fn default_iterations() -> usize {
+  8
+}
+
+fn default_level() -> u8 {
+  1
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AssemblerGroup {
+  #[serde(default = "default_assembler_name")]
+  pub name: String,
+  pub recipe: Option<String>,
+  pub centers: Vec<Coordinates>,
+}
+
+fn default_assembler_name() -> String {
+  "assembling-machine-2".to_string()
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EntitySpec {
+  pub name: String,
+  pub position: [f64; 2],
+  pub direction: Option<u8>,
+}
+
+/// A conveyor edge: items leave the tile `from` travelling in `direction`.
+/// `from` may be just outside the bounds, for belts entering from the edge of the chunk.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct EdgeSpec {
+  pub from: Coordinates,
+  pub direction: u8,
+}
+
+impl EdgeSpec {
+  fn edge(self) -> DirectedEdge {
+    DirectedEdge::from_before(self.from, self.direction)
+  }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ObjectSpec {
+  Belt {
+    position: Coordinates,
+    direction: u8,
+    /// only run while this holds
+    #[serde(default)]
+    condition: Option<CircuitCondition>,
+    #[serde(default)]
+    read_contents: bool,
+  },
+  UndergroundBelt {
+    input: EdgeSpec,
+    length: u8,
+  },
+  Splitter {
+    left: Coordinates,
+    direction: u8,
+  },
+  Inserter {
+    position: Coordinates,
+    direction: u8,
+    #[serde(default = "default_level")]
+    length: u8,
+    /// only run while this holds
+    #[serde(default)]
+    condition: Option<CircuitCondition>,
+    #[serde(default)]
+    read_contents: bool,
+  },
+  Chest {
+    position: Coordinates,
+    chest: ChestType,
+  },
+  Loader {
+    edge: EdgeSpec,
+    loading: bool,
+  },
+}
+
+/// Some of the problem's `objects`, by index, to wire together on one circuit network.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NetworkSpec {
+  pub color: WireColor,
+  pub objects: Vec<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SourceSpec {
+  Conveyor {
+    edge: EdgeSpec,
+  },
+  Assembler {
+    center: Coordinates,
+  },
+  Chest {
+    position: Coordinates,
+    chest: ChestType,
+  },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DestinationSpec {
+  Conveyor {
+    edge: EdgeSpec,
+  },
+  Assembler {
+    center: Coordinates,
+  },
+  /// every assembler in `assembler_groups[group]`
+  AssemblerGroup {
+    group: usize,
+  },
+  Chest {
+    position: Coordinates,
+    chest: ChestType,
+  },
+  Loader {
+    edge: EdgeSpec,
+    loading: bool,
+  },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EndpointSpec {
+  pub source: SourceSpec,
+  pub destinations: Vec<DestinationSpec>,
+  #[serde(default)]
+  pub backwards: bool,
+  /// how to connect several destinations: `nearest`, `spanning_tree` or `cheapest`
+  #[serde(default)]
+  pub strategy: RouteStrategy,
+}
+
+#[derive(Debug)]
+pub enum ProblemError {
+  Io(io::Error),
+  Toml(toml::de::Error),
+  Json(serde_json::Error),
+  Invalid(String),
+}
+
+impl fmt::Display for ProblemError {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ProblemError::Io(error) => write!(formatter, "{}", error),
+      ProblemError::Toml(error) => write!(formatter, "{}", error),
+      ProblemError::Json(error) => write!(formatter, "{}", error),
+      ProblemError::Invalid(message) => write!(formatter, "{}", message),
+    }
+  }
+}
+
+impl std::error::Error for ProblemError {}
+
+fn control(condition: &Option<CircuitCondition>, read_contents: bool) -> Option<CircuitControl> {
+  if condition.is_some() || read_contents {
+    Some(CircuitControl {
+      condition: condition.clone(),
+      read_contents,
+    })
+  } else {
+    None
+  }
+}
+
+impl ObjectSpec {
+  pub fn object(&self) -> Object {
+    match *self {
+      ObjectSpec::Belt {
+        position,
+        direction,
+        ref condition,
+        read_contents,
+      } => {
+        let belt = Belt::new(position, direction);
+        Object::Belt(match control(condition, read_contents) {
+          Some(control) => belt.with_control(control),
+          None => belt,
+        })
+      }
+      ObjectSpec::UndergroundBelt { input, length } => {
+        Object::UndergroundBelt(UndergroundBelt::from_input(input.edge(), length))
+      }
+      ObjectSpec::Splitter { left, direction } => {
+        Object::Splitter(Splitter::from_left(left, direction))
+      }
+      ObjectSpec::Inserter {
+        position,
+        direction,
+        length,
+        ref condition,
+        read_contents,
+      } => {
+        let inserter = Inserter::new(position, direction, length);
+        Object::Inserter(match control(condition, read_contents) {
+          Some(control) => inserter.with_control(control),
+          None => inserter,
+        })
+      }
+      ObjectSpec::Chest { position, chest } => Object::Chest(Chest::new(position, chest)),
+      ObjectSpec::Loader { edge, loading } => Object::Loader(if loading {
+        Loader::loading(edge.edge())
+      } else {
+        Loader::unloading(edge.edge())
+      }),
+    }
+  }
+}
+
+impl RoutingProblem {
+  /// Reads a problem from a JSON string if it looks like one, or TOML otherwise.
+  pub fn parse(text: &str) -> Result<RoutingProblem, ProblemError> {
+    if text.trim_start().starts_with('{') {
+      serde_json::from_str(text).map_err(ProblemError::Json)
+    } else {
+      toml::from_str(text).map_err(ProblemError::Toml)
+    }
+  }
+
+  fn assembler_group(&self, group: usize) -> Result<&AssemblerGroup, ProblemError> {
+    self.assembler_groups.get(group).ok_or_else(|| {
+      ProblemError::Invalid(format!(
+        "there is no assembler group {} (there are {})",
+        group,
+        self.assembler_groups.len()
+      ))
+    })
+  }
+
+  pub fn endpoints(&self) -> Result<Vec<RouteEndpoint>, ProblemError> {
+    let mut result = Vec::new();
+    for endpoint in &self.endpoints {
+      let source = match endpoint.source {
+        SourceSpec::Conveyor { edge } => RouteSource::Conveyor(edge.edge()),
+        SourceSpec::Assembler { center } => RouteSource::Assembler(Assembler { center }),
+        SourceSpec::Chest { position, chest } => RouteSource::Chest(Chest::new(position, chest)),
+      };
+      let mut destinations = Vec::new();
+      for destination in &endpoint.destinations {
+        match *destination {
+          DestinationSpec::Conveyor { edge } => {
+            destinations.push(RouteDestination::Conveyor(edge.edge()))
+          }
+          DestinationSpec::Assembler { center } => {
+            destinations.push(RouteDestination::Assembler(Assembler { center }))
+          }
+          DestinationSpec::AssemblerGroup { group } => destinations.extend(
+            self
+              .assembler_group(group)?
+              .centers
+              .iter()
+              .map(|&center| RouteDestination::Assembler(Assembler { center })),
+          ),
+          DestinationSpec::Chest { position, chest } => {
+            destinations.push(RouteDestination::Chest(Chest::new(position, chest)))
+          }
+          DestinationSpec::Loader { edge, loading } => {
+            if loading == endpoint.backwards {
+              return Err(ProblemError::Invalid(format!(
+                "{} loader at {:?} can only be reached by a {} route",
+                if loading { "a loading" } else { "an unloading" },
+                edge.from,
+                if loading { "forward" } else { "backwards" }
+              )));
+            }
+            destinations.push(RouteDestination::Loader(if loading {
+              Loader::loading(edge.edge())
+            } else {
+              Loader::unloading(edge.edge())
+            }))
+          }
+        }
+      }
+      result.push((source, destinations, endpoint.backwards, endpoint.strategy));
+    }
+    Ok(result)
+  }
+
+  /// The objects that are in place before routing: the listed objects, plus any chests and
+  /// loaders that endpoints start or end at.
+  pub fn fixed_objects(&self) -> Vec<Object> {
+    let mut result: Vec<Object> = self.objects.iter().map(ObjectSpec::object).collect();
+    let mut add = |object: Object| {
+      if !result.contains(&object) {
+        result.push(object);
+      }
+    };
+    for endpoint in &self.endpoints {
+      if let SourceSpec::Chest { position, chest } = endpoint.source {
+        add(Object::Chest(Chest::new(position, chest)));
+      }
+      for destination in &endpoint.destinations {
+        match *destination {
+          DestinationSpec::Chest { position, chest } => {
+            add(Object::Chest(Chest::new(position, chest)))
+          }
+          DestinationSpec::Loader { edge, loading } => add(Object::Loader(if loading {
+            Loader::loading(edge.edge())
+          } else {
+            Loader::unloading(edge.edge())
+          })),
+          _ => (),
+        }
+      }
+    }
+    result
+  }
+
+  pub fn networks(&self) -> Result<Vec<CircuitNetwork>, ProblemError> {
+    self
+      .networks
+      .iter()
+      .enumerate()
+      .map(|(index, network)| {
+        let members = network
+          .objects
+          .iter()
+          .map(|&object| {
+            self
+              .objects
+              .get(object)
+              .map(ObjectSpec::object)
+              .ok_or_else(|| {
+                ProblemError::Invalid(format!(
+                  "network {} refers to object {}, but there are only {} objects",
+                  index,
+                  object,
+                  self.objects.len()
+                ))
+              })
+          })
+          .collect::<Result<_, _>>()?;
+        Ok(CircuitNetwork {
+          color: network.color,
+          members,
+        })
+      })
+      .collect()
+  }
+
+  /// The centers of assemblers that endpoints start or end at without being in any of
+  /// `assembler_groups`. They're built like the assemblers in groups, with the default name and
+  /// no recipe.
+  pub fn ungrouped_assemblers(&self) -> Vec<Coordinates> {
+    let mut result = Vec::new();
+    for endpoint in &self.endpoints {
+      let source = match endpoint.source {
+        SourceSpec::Assembler { center } => Some(center),
+        _ => None,
+      };
+      let destinations =
+        endpoint
+          .destinations
+          .iter()
+          .filter_map(|destination| match *destination {
+            DestinationSpec::Assembler { center } => Some(center),
+            _ => None,
+          });
+      for center in source.into_iter().chain(destinations) {
+        let grouped = self
+          .assembler_groups
+          .iter()
+          .any(|group| group.centers.contains(&center));
+        if !grouped && !result.contains(&center) {
+          result.push(center);
+        }
+      }
+    }
+    result
+  }
+
+  /// The map with every obstruction, fixed object and assembler marked as obstructed.
+  pub fn map(&self) -> RoutingMap {
+    let mut map = RoutingMap::new(Rectangle::new(self.bounds));
+    let mut obstruct = |rectangle: Rectangle| {
+      for tile in rectangle.tiles() {
+        map.set_obstructed(tile, true);
+      }
+    };
+    for &obstruction in &self.obstructions {
+      obstruct(Rectangle::new(obstruction));
+    }
+    for object in self.fixed_objects() {
+      for &rectangle in object.solid_rectangles().iter() {
+        obstruct(rectangle);
+      }
+    }
+    let grouped = self
+      .assembler_groups
+      .iter()
+      .flat_map(|group| group.centers.iter().cloned());
+    for center in grouped.chain(self.ungrouped_assemblers()) {
+      obstruct(Assembler { center }.shape());
+    }
+    map
+  }
+}
+
+pub fn load_problem(path: impl AsRef<Path>) -> Result<RoutingProblem, ProblemError> {
+  let path = path.as_ref();
+  let text = fs::read_to_string(path).map_err(ProblemError::Io)?;
+  if path
+    .extension()
+    .is_some_and(|extension| extension == "json")
+  {
+    serde_json::from_str(&text).map_err(ProblemError::Json)
+  } else {
+    RoutingProblem::parse(&text)
+  }
+}
+
+/// A solved routing problem: the blueprint, and the total cost of the objects the routes
+/// are made of.
+#[derive(Clone, Debug)]
+pub struct Solution {
+  pub blueprint: Blueprint,
+  pub cost: usize,
+}
+
+pub fn solve_problem(problem: &RoutingProblem) -> Result<Solution, ProblemError> {
+  if problem.endpoints.is_empty() || problem.iterations == 0 {
+    return Err(ProblemError::Invalid(
+      "a routing problem needs at least one endpoint and at least one iteration".to_string(),
+    ));
+  }
+  problem.costs.check().map_err(ProblemError::Invalid)?;
+  let map = problem.map();
+  let endpoints = problem.endpoints()?;
+
+  let mut entities = Vec::new();
+  let ungrouped = AssemblerGroup {
+    name: default_assembler_name(),
+    recipe: None,
+    centers: problem.ungrouped_assemblers(),
+  };
+  for group in problem.assembler_groups.iter().chain(Some(&ungrouped)) {
+    for center in &group.centers {
+      entities.push(Entity {
+        name: group.name.clone(),
+        position: Position {
+          x: center[0] as f64,
+          y: center[1] as f64,
+        },
+        recipe: group.recipe.clone(),
+        ..Default::default()
+      });
+    }
+  }
+  for object in problem.fixed_objects() {
+    entities.extend(object.render());
+  }
+  let history = match problem.threads {
+    Some(threads) => find_routes_parallel(
+      &map,
+      &endpoints,
+      problem.iterations,
+      &problem.costs,
+      ParallelRouting {
+        threads,
+        seed: problem.seed,
+      },
+    ),
+    None => find_routes(&map, &endpoints, problem.iterations, &problem.costs),
+  };
+  let routes = history.into_iter().last().unwrap();
+  let cost = objects_cost(&routes, &problem.costs);
+  for object in routes {
+    entities.extend(object.render());
+  }
+  for entity in &problem.entities {
+    entities.push(Entity {
+      name: entity.name.clone(),
+      position: Position {
+        x: entity.position[0],
+        y: entity.position[1],
+      },
+      direction: entity.direction,
+      ..Default::default()
+    });
+  }
+  let mut blueprint = Blueprint::simple(problem.name.clone(), entities, GameVersion::default());
+  wire_networks(&mut blueprint, &problem.networks()?)
+    .map_err(|error| ProblemError::Invalid(error.to_string()))?;
+  if problem.snap_to_grid {
+    blueprint.align_to_grid(Rectangle::new(problem.bounds).edges(), [0, 0]);
+  }
+  Ok(Solution { blueprint, cost })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn ungrouped_assemblers_are_built() {
+    let problem = RoutingProblem::parse(
+      r#"
+        bounds = [[-6, 6], [-6, 6]]
+
+        [[endpoints]]
+        source = { type = "assembler", center = [-3, 0] }
+        destinations = [{ type = "assembler", center = [3, 0] }]
+      "#,
+    )
+    .unwrap();
+    assert_eq!(problem.ungrouped_assemblers(), vec![[-3, 0], [3, 0]]);
+    let map = problem.map();
+    for &center in &[[-3, 0], [3, 0]] {
+      let shape = Assembler { center }.shape();
+      for tile in shape.tiles() {
+        assert!(map.obstructed(tile));
+      }
+    }
+
+    let solution = solve_problem(&problem).unwrap();
+    let assemblers: Vec<[f64; 2]> = solution
+      .blueprint
+      .entities
+      .iter()
+      .filter(|entity| entity.name == "assembling-machine-2")
+      .map(|entity| [entity.position.x, entity.position.y])
+      .collect();
+    assert_eq!(assemblers, vec![[-3.0, 0.0], [3.0, 0.0]]);
+    assert!(solution
+      .blueprint
+      .entities
+      .iter()
+      .any(|entity| entity.name.contains("inserter")));
+  }
+
+  #[test]
+  fn costs_cheaper_than_the_tiles_crossed_are_refused() {
+    let problem = |costs: &str| {
+      RoutingProblem::parse(&format!(
+        r#"
+          bounds = [[-6, 6], [-6, 6]]
+
+          [[endpoints]]
+          source = {{ type = "conveyor", edge = {{ from = [-7, 0], direction = 2 }} }}
+          destinations = [{{ type = "conveyor", edge = {{ from = [6, 0], direction = 2 }} }}]
+
+          [costs]
+          {}
+        "#,
+        costs
+      ))
+      .unwrap()
+    };
+    assert!(solve_problem(&problem("belt = 1")).is_ok());
+    for costs in &[
+      "belt = 0",
+      "underground_belt = [1, 2, 3]",
+      "inserter = [5, 3]",
+    ] {
+      match solve_problem(&problem(costs)) {
+        Err(ProblemError::Invalid(_)) => {}
+        other => panic!("{} gave {:?}", costs, other.map(|solution| solution.cost)),
+      }
+    }
+  }
+}
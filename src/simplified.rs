@@ -1,6 +1,7 @@
 use super::blueprint::*;
 use array_ext::Array;
 use arrayvec::ArrayVec;
+use serde_derive::{Deserialize, Serialize};
 use std::cmp::{max, min};
 use std::iter::FromIterator;
 
@@ -47,6 +48,20 @@ impl Rectangle {
       .flat_map(move |x| (self.bounds[1][0]..=self.bounds[1][1]).map(move |y| [x, y]))
   }
 
+  /// `[[left, right], [top, bottom]]` of the area the tiles cover, in entity position units.
+  pub fn edges(self) -> [[f64; 2]; 2] {
+    [
+      [
+        self.bounds[0][0] as f64 - 0.5,
+        self.bounds[0][1] as f64 + 0.5,
+      ],
+      [
+        self.bounds[1][0] as f64 - 0.5,
+        self.bounds[1][1] as f64 + 0.5,
+      ],
+    ]
+  }
+
   pub fn nowhere() -> Rectangle {
     Rectangle {
       bounds: [
@@ -268,6 +283,7 @@ pub struct Belt {
   position: Coordinates,
   direction: u8,
   level: u8,
+  control: Option<Box<CircuitControl>>,
 }
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub struct UndergroundBelt {
@@ -286,11 +302,45 @@ pub struct Inserter {
   position: Coordinates,
   direction: u8,
   length: u8,
+  control: Option<Box<CircuitControl>>,
 }
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub struct Assembler {
   pub center: Coordinates,
 }
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChestType {
+  Wooden,
+  Iron,
+  Steel,
+  ActiveProvider,
+  PassiveProvider,
+  Storage,
+  Requester,
+  Buffer,
+}
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Chest {
+  pub position: Coordinates,
+  pub chest_type: ChestType,
+}
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Loader {
+  belt_part: Coordinates,
+  direction: u8,
+  loading: bool,
+  level: u8,
+}
+/// What a belt or inserter does with the circuit network it's wired to (see `wiring.rs` for
+/// the wiring itself).
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct CircuitControl {
+  /// only move items while this holds
+  pub condition: Option<CircuitCondition>,
+  /// put what's on the belt, or in the inserter's hand, onto the network
+  pub read_contents: bool,
+}
 
 impl Belt {
   pub fn new(position: Coordinates, direction: u8) -> Belt {
@@ -298,8 +348,18 @@ impl Belt {
       position,
       direction,
       level: 1,
+      control: None,
+    }
+  }
+  pub fn with_control(self, control: CircuitControl) -> Belt {
+    Belt {
+      control: Some(Box::new(control)),
+      ..self
     }
   }
+  pub fn control(&self) -> Option<&CircuitControl> {
+    self.control.as_deref()
+  }
   pub fn output(&self) -> DirectedEdge {
     DirectedEdge::from_before(self.position, self.direction)
   }
@@ -423,11 +483,24 @@ impl Inserter {
       position,
       direction,
       length,
+      control: None,
     }
   }
+  pub fn with_control(self, control: CircuitControl) -> Inserter {
+    Inserter {
+      control: Some(Box::new(control)),
+      ..self
+    }
+  }
+  pub fn control(&self) -> Option<&CircuitControl> {
+    self.control.as_deref()
+  }
   pub fn input(&self) -> Coordinates {
     further_coordinates(self.position, self.direction, self.length as i32)
   }
+  pub fn length(&self) -> u8 {
+    self.length
+  }
   pub fn output(&self) -> Coordinates {
     further_coordinates(self.position, self.direction, -(self.length as i32))
   }
@@ -438,11 +511,122 @@ impl Inserter {
     self.position
   }
 }
+impl CircuitControl {
+  fn belt_behavior(&self) -> ControlBehavior {
+    ControlBehavior {
+      circuit_condition: self.condition.clone(),
+      circuit_enable_disable: Some(self.condition.is_some()),
+      circuit_read_hand_contents: Some(self.read_contents),
+      circuit_contents_read_mode: if self.read_contents { Some(1) } else { None },
+      ..Default::default()
+    }
+  }
+  fn inserter_behavior(&self) -> ControlBehavior {
+    ControlBehavior {
+      circuit_condition: self.condition.clone(),
+      circuit_mode_of_operation: Some(if self.condition.is_some() { 0 } else { 3 }),
+      circuit_read_hand_contents: Some(self.read_contents),
+      circuit_hand_read_mode: if self.read_contents { Some(1) } else { None },
+      ..Default::default()
+    }
+  }
+}
 impl Assembler {
   pub fn shape(&self) -> Rectangle {
     Rectangle::singleton(self.center).outset(1)
   }
 }
+impl ChestType {
+  pub fn name(self) -> &'static str {
+    match self {
+      ChestType::Wooden => "wooden-chest",
+      ChestType::Iron => "iron-chest",
+      ChestType::Steel => "steel-chest",
+      ChestType::ActiveProvider => "logistic-chest-active-provider",
+      ChestType::PassiveProvider => "logistic-chest-passive-provider",
+      ChestType::Storage => "logistic-chest-storage",
+      ChestType::Requester => "logistic-chest-requester",
+      ChestType::Buffer => "logistic-chest-buffer",
+    }
+  }
+}
+impl Chest {
+  pub fn new(position: Coordinates, chest_type: ChestType) -> Chest {
+    Chest {
+      position,
+      chest_type,
+    }
+  }
+}
+impl Loader {
+  /// A loader that takes items from the belt that conveys into it through `input`,
+  /// and loads them into whatever is 2 tiles further along.
+  pub fn loading(input: DirectedEdge) -> Loader {
+    Loader {
+      belt_part: input.after_coordinates(),
+      direction: input.direction(),
+      loading: true,
+      level: 1,
+    }
+  }
+  /// A loader that unloads items from whatever is 2 tiles behind `output`,
+  /// and puts them on a belt through `output`.
+  pub fn unloading(output: DirectedEdge) -> Loader {
+    Loader {
+      belt_part: output.before_coordinates(),
+      direction: output.direction(),
+      loading: false,
+      level: 1,
+    }
+  }
+  pub fn belt_part(&self) -> Coordinates {
+    self.belt_part
+  }
+  pub fn container_part(&self) -> Coordinates {
+    if self.loading {
+      next_coordinates(self.belt_part, self.direction)
+    } else {
+      previous_coordinates(self.belt_part, self.direction)
+    }
+  }
+  /// The tile of the chest, wagon, or other container that this loader interacts with.
+  pub fn container(&self) -> Coordinates {
+    if self.loading {
+      next_coordinates(self.container_part(), self.direction)
+    } else {
+      previous_coordinates(self.container_part(), self.direction)
+    }
+  }
+  pub fn input(&self) -> Option<DirectedEdge> {
+    if self.loading {
+      Some(DirectedEdge::from_after(self.belt_part, self.direction))
+    } else {
+      None
+    }
+  }
+  pub fn output(&self) -> Option<DirectedEdge> {
+    if self.loading {
+      None
+    } else {
+      Some(DirectedEdge::from_before(self.belt_part, self.direction))
+    }
+  }
+  pub fn direction(&self) -> u8 {
+    self.direction
+  }
+  pub fn is_loading(&self) -> bool {
+    self.loading
+  }
+  pub fn level(&self) -> u8 {
+    self.level
+  }
+  pub fn shape(&self) -> Rectangle {
+    Rectangle::including_both(
+      Rectangle::singleton(self.belt_part),
+      Rectangle::singleton(self.container_part()),
+    )
+  }
+}
 
 /*
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
@@ -461,6 +645,8 @@ macro_rules! delegate {
       Object::Splitter($object) => $expression,
       Object::Inserter($object) => $expression,
       Object::Assembler($object) => $expression,
+      Object::Chest($object) => $expression,
+      Object::Loader($object) => $expression,
     }
   };
 }
@@ -678,6 +864,7 @@ objects! {
           y: self.position[1] as f64,
         },
         direction: Some(self.direction),
+        control_behavior: self.control().map(CircuitControl::belt_behavior),
         ..Default::default()
       }]
     }
@@ -919,6 +1106,7 @@ objects! {
           y: self.position[1] as f64,
         },
         direction: Some(self.direction),
+        control_behavior: self.control().map(CircuitControl::inserter_behavior),
         ..Default::default()
       }]
     }
@@ -981,6 +1169,138 @@ objects! {
   }
 
 
+  Chest as_chest {
+    fn solid_rectangles (&self) -> [Rectangle; 1] {
+      [Rectangle::singleton (self.position)]
+    }
+
+    fn solid_tiles (&self) -> [Coordinates; 1] {
+      [self.position]
+    }
+
+    fn conveyor_outputs (&self)->[DirectedEdge; 0] {
+      []
+    }
+
+    fn conveyor_inputs (&self)->[DirectedEdge; 0] {
+      []
+    }
+
+    fn insertable_tiles (&self) -> [Coordinates; 1] {
+      self.solid_tiles()
+    }
+
+    fn overlaps_solid_rectangle (&self, other: Rectangle)->bool {
+      other.contains (self.position)
+    }
+
+    fn overlaps_solid_tile (&self, coordinates: Coordinates)->bool {
+      coordinates == self.position
+    }
+
+    fn physically_incompatible <Other: ObjectTrait> (&self, other: & Other)->bool {
+      other.overlaps_solid_tile (self.position)
+    }
+
+    fn physical_bounding_box (&self)->Rectangle {
+      Rectangle::singleton (self.position)
+    }
+
+    fn interaction_bounding_box (&self)->Rectangle {
+      Rectangle::singleton (self.position)
+    }
+
+    fn render(&self) -> Vec<Entity> {
+      vec![Entity {
+        name: self.chest_type.name().to_string(),
+        position: Position {
+          x: self.position[0] as f64,
+          y: self.position[1] as f64,
+        },
+        ..Default::default()
+      }]
+    }
+  }
+
+
+  Loader as_loader {
+    fn solid_rectangles (&self) -> [Rectangle; 1] {
+      [self.shape()]
+    }
+
+    fn solid_tiles (&self) -> [Coordinates; 2] {
+      [self.belt_part, self.container_part()]
+    }
+
+    fn conveyor_outputs (&self)->ArrayVec<[DirectedEdge; 1]> {
+      self.output().into_iter().collect()
+    }
+
+    fn conveyor_inputs (&self)->ArrayVec<[DirectedEdge; 1]> {
+      self.input().into_iter().collect()
+    }
+
+    fn insertable_tiles (&self) -> [Coordinates; 0] {
+      []
+    }
+
+    fn overlaps_solid_rectangle (&self, other: Rectangle)->bool {
+      other.overlaps (self.shape())
+    }
+
+    fn overlaps_solid_tile (&self, coordinates: Coordinates)->bool {
+      self.shape().contains (coordinates)
+    }
+
+    fn physically_incompatible <Other: ObjectTrait> (&self, other: & Other)->bool {
+      other.overlaps_solid_rectangle (self.shape())
+    }
+
+    fn interaction_incompatible_one_sided <Other: ObjectTrait> (&self, other: & Other) -> bool {
+      other
+        .conveyor_outputs()
+        .as_ref().iter()
+        .any(|&output| self.shape().contains (output.after_coordinates()) && Some (output) != self.input())
+    }
+
+    fn physical_bounding_box (&self)->Rectangle {
+      self.shape()
+    }
+
+    fn interaction_bounding_box (&self)->Rectangle {
+      let belt_side = further_coordinates (self.belt_part, self.direction, if self.loading {-1} else {1});
+      Rectangle::including_both(
+        Rectangle::singleton(belt_side),
+        Rectangle::singleton(self.container()),
+      )
+    }
+
+    fn render(&self) -> Vec<Entity> {
+      let (first, second) = (self.belt_part, self.container_part());
+      vec![Entity {
+        name: match self.level {
+          1 => "loader",
+          2 => "fast-loader",
+          3 => "express-loader",
+          _ => unreachable!(),
+        }
+        .to_string(),
+        position: Position {
+          x: (first[0] + second[0]) as f64 * 0.5,
+          y: (first[1] + second[1]) as f64 * 0.5,
+        },
+        direction: Some(self.direction),
+        underground_type: Some(if self.loading {
+          UndergroundBeltOrLoaderType::Input
+        } else {
+          UndergroundBeltOrLoaderType::Output
+        }),
+        ..Default::default()
+      }]
+    }
+  }
+
+
 }
 
 impl Object {
@@ -989,6 +1309,7 @@ impl Object {
       Object::Belt(object) => object.level = max(object.level, level),
       Object::UndergroundBelt(object) => object.level = max(object.level, level),
       Object::Splitter(object) => object.level = max(object.level, level),
+      Object::Loader(object) => object.level = max(object.level, level),
       _ => (),
     }
   }
@@ -996,8 +1317,12 @@ impl Object {
 
 pub fn dump_objects(objects: &[Object]) -> String {
   let entities = objects.iter().flat_map(|object| object.render()).collect();
-  BlueprintObject::Blueprint(Blueprint::simple("routed belts".to_string(), entities))
-    .encode()
-    .unwrap()
-    .0
+  BlueprintObject::Blueprint(Blueprint::simple(
+    "routed belts".to_string(),
+    entities,
+    GameVersion::default(),
+  ))
+  .encode()
+  .unwrap()
+  .0
 }
@@ -0,0 +1,414 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use super::blueprint::*;
+use super::prototypes::*;
+
+/// Turning a blueprint around its origin, in quarter turns, like pressing R while placing it.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Rotation {
+  None,
+  Clockwise,
+  Half,
+  Counterclockwise,
+}
+
+impl Rotation {
+  pub fn quarter_turns(self) -> u8 {
+    match self {
+      Rotation::None => 0,
+      Rotation::Clockwise => 1,
+      Rotation::Half => 2,
+      Rotation::Counterclockwise => 3,
+    }
+  }
+
+  pub fn from_quarter_turns(turns: u8) -> Rotation {
+    match turns % 4 {
+      0 => Rotation::None,
+      1 => Rotation::Clockwise,
+      2 => Rotation::Half,
+      _ => Rotation::Counterclockwise,
+    }
+  }
+
+  /// `y` points down, so a clockwise turn takes north (0, -1) to east (1, 0).
+  pub fn apply(self, [x, y]: [f64; 2]) -> [f64; 2] {
+    match self {
+      Rotation::None => [x, y],
+      Rotation::Clockwise => [-y, x],
+      Rotation::Half => [-x, -y],
+      Rotation::Counterclockwise => [y, -x],
+    }
+  }
+
+  pub fn apply_direction(self, direction: u8) -> u8 {
+    (direction + 2 * self.quarter_turns()) % 8
+  }
+}
+
+/// What to do when a stamped entity overlaps one that is already there.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ConflictPolicy {
+  /// give up, leaving the blueprint as it was
+  Error,
+  /// remove whatever was in the way
+  Overwrite,
+  /// leave out the stamped entity
+  Skip,
+}
+
+#[derive(Clone, Debug)]
+pub enum StampError {
+  Collision {
+    stamped: String,
+    existing: String,
+    tile: [i64; 2],
+  },
+}
+
+impl fmt::Display for StampError {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      StampError::Collision {
+        stamped,
+        existing,
+        tile,
+      } => write!(
+        formatter,
+        "{} would overlap {} at ({}, {})",
+        stamped, existing, tile[0], tile[1]
+      ),
+    }
+  }
+}
+
+impl std::error::Error for StampError {}
+
+fn place(position: &Position, offset: [f64; 2], rotation: Rotation) -> Position {
+  let [x, y] = rotation.apply([position.x, position.y]);
+  Position {
+    x: x + offset[0],
+    y: y + offset[1],
+  }
+}
+
+/// Tiles are positioned by their top left corner, so it's their middle that has to go where
+/// `place` would put it.
+fn place_tile(position: &Position, offset: [f64; 2], rotation: Rotation) -> Position {
+  let middle = Position {
+    x: position.x + 0.5,
+    y: position.y + 0.5,
+  };
+  let placed = place(&middle, offset, rotation);
+  Position {
+    x: placed.x - 0.5,
+    y: placed.y - 0.5,
+  }
+}
+
+fn rotate_entity(entity: &Entity, offset: [f64; 2], rotation: Rotation) -> Entity {
+  let mut result = entity.clone();
+  result.position = place(&entity.position, offset, rotation);
+  // locomotives and wagons turn by their orientation instead
+  if let Some(orientation) = entity.orientation {
+    result.orientation = Some((orientation + rotation.quarter_turns() as f64 * 0.25) % 1.0);
+    return result;
+  }
+  let rotatable = entity.direction.is_some() || {
+    let [width, height] = entity_size(entity);
+    width != height
+  };
+  if rotatable && rotation != Rotation::None {
+    result.direction = Some(rotation.apply_direction(entity.direction.unwrap_or(0)));
+  }
+  // these are relative to the inserter, so they turn but don't move
+  for vector in result
+    .drop_position
+    .iter_mut()
+    .chain(result.pickup_position.iter_mut())
+  {
+    *vector = place(vector, [0.0, 0.0], rotation);
+  }
+  result
+}
+
+fn tile_key(position: &Position) -> [i64; 2] {
+  [position.x.round() as i64, position.y.round() as i64]
+}
+
+impl Blueprint {
+  /// Removes entities, along with any wires that led to them, and numbers the rest from 1 again.
+  pub fn retain_entities(&mut self, mut keep: impl FnMut(&Entity) -> bool) {
+    self.entities.retain(|entity| keep(entity));
+    let numbers: HashMap<i32, i32> = self
+      .entities
+      .iter()
+      .enumerate()
+      .map(|(index, entity)| (entity.entity_number, index as i32 + 1))
+      .collect();
+    for entity in &mut self.entities {
+      entity.entity_number = numbers[&entity.entity_number];
+      for connection in entity.connections.values_mut() {
+        for wires in [&mut connection.red, &mut connection.green] {
+          wires.retain(|data| numbers.contains_key(&data.entity_id));
+          for data in wires.iter_mut() {
+            data.entity_id = numbers[&data.entity_id];
+          }
+        }
+      }
+      entity
+        .connections
+        .retain(|_, connection| !connection.red.is_empty() || !connection.green.is_empty());
+    }
+    for schedule in &mut self.schedules {
+      schedule
+        .locomotives
+        .retain(|number| numbers.contains_key(number));
+      for number in &mut schedule.locomotives {
+        *number = numbers[number];
+      }
+    }
+    self
+      .schedules
+      .retain(|schedule| !schedule.locomotives.is_empty());
+  }
+
+  /// Copies `other` into this blueprint, turned by `rotation` around its origin and then moved by
+  /// `offset`, failing if anything would overlap.
+  pub fn stamp(
+    &mut self,
+    other: &Blueprint,
+    offset: [f64; 2],
+    rotation: Rotation,
+  ) -> Result<(), StampError> {
+    self.stamp_with_policy(other, offset, rotation, ConflictPolicy::Error)
+  }
+
+  /// Like `stamp`, with a choice of what to do about overlaps. Wires inside `other` come along,
+  /// renumbered to match; with `Skip`, wires to left out entities are dropped. Tiles that land
+  /// on a different tile follow the same policy.
+  pub fn stamp_with_policy(
+    &mut self,
+    other: &Blueprint,
+    offset: [f64; 2],
+    rotation: Rotation,
+    policy: ConflictPolicy,
+  ) -> Result<(), StampError> {
+    let mut occupied: HashMap<[i64; 2], usize> = HashMap::new();
+    for (index, entity) in self.entities.iter().enumerate() {
+      for tile in footprint_tiles(entity) {
+        occupied.insert(tile, index);
+      }
+    }
+
+    let mut removed = vec![false; self.entities.len()];
+    let mut stamped = Vec::new();
+    'entities: for entity in &other.entities {
+      let entity = rotate_entity(entity, offset, rotation);
+      let mut overlapping = Vec::new();
+      for tile in footprint_tiles(&entity) {
+        if let Some(&index) = occupied.get(&tile) {
+          if removed[index] {
+            continue;
+          }
+          match policy {
+            ConflictPolicy::Error => {
+              return Err(StampError::Collision {
+                stamped: entity.name.clone(),
+                existing: self.entities[index].name.clone(),
+                tile,
+              })
+            }
+            ConflictPolicy::Skip => continue 'entities,
+            ConflictPolicy::Overwrite => overlapping.push(index),
+          }
+        }
+      }
+      for index in overlapping {
+        removed[index] = true;
+      }
+      stamped.push(entity);
+    }
+
+    let mut tiles = self.tiles.clone();
+    let mut tile_indices: HashMap<[i64; 2], usize> = tiles
+      .iter()
+      .enumerate()
+      .map(|(index, tile)| (tile_key(&tile.position), index))
+      .collect();
+    for tile in &other.tiles {
+      let tile = Tile {
+        name: tile.name.clone(),
+        position: place_tile(&tile.position, offset, rotation),
+      };
+      let key = tile_key(&tile.position);
+      match tile_indices.get(&key) {
+        None => {
+          tile_indices.insert(key, tiles.len());
+          tiles.push(tile);
+        }
+        Some(&index) if tiles[index].name == tile.name => {}
+        Some(&index) => match policy {
+          ConflictPolicy::Error => {
+            return Err(StampError::Collision {
+              stamped: tile.name,
+              existing: tiles[index].name.clone(),
+              tile: key,
+            })
+          }
+          ConflictPolicy::Skip => {}
+          ConflictPolicy::Overwrite => tiles[index] = tile,
+        },
+      }
+    }
+    self.tiles = tiles;
+
+    // give the stamped entities numbers that can't clash with ours, then let `retain_entities`
+    // drop the overwritten entities, the wires to skipped ones, and the gaps
+    let first_number = self
+      .entities
+      .iter()
+      .map(|entity| entity.entity_number)
+      .max()
+      .unwrap_or(0)
+      .max(0)
+      + 1;
+    let numbers: HashMap<i32, i32> = other
+      .entities
+      .iter()
+      .map(|entity| entity.entity_number)
+      .enumerate()
+      .map(|(index, number)| (number, first_number + index as i32))
+      .collect();
+    let removed_numbers: HashSet<i32> = self
+      .entities
+      .iter()
+      .zip(&removed)
+      .filter(|(_, &removed)| removed)
+      .map(|(entity, _)| entity.entity_number)
+      .collect();
+    for mut entity in stamped {
+      entity.entity_number = numbers[&entity.entity_number];
+      for connection in entity.connections.values_mut() {
+        for wires in [&mut connection.red, &mut connection.green] {
+          for data in wires.iter_mut() {
+            // 0 is never an entity number, so `retain_entities` drops wires that led outside
+            data.entity_id = numbers.get(&data.entity_id).cloned().unwrap_or(0);
+          }
+        }
+      }
+      self.entities.push(entity);
+    }
+    for schedule in &other.schedules {
+      let mut schedule = schedule.clone();
+      for number in &mut schedule.locomotives {
+        *number = numbers.get(number).cloned().unwrap_or(0);
+      }
+      self.schedules.push(schedule);
+    }
+    self.retain_entities(|entity| !removed_numbers.contains(&entity.entity_number));
+    Ok(())
+  }
+
+  /// Lays out `columns` × `rows` copies of `chunk`, `spacing` apart, after letting `customize`
+  /// change each copy (to give it its own recipes, say). Copies are stamped left to right and
+  /// then top to bottom, with the given policy for overlaps.
+  pub fn tiled(
+    label: String,
+    chunk: &Blueprint,
+    [columns, rows]: [u32; 2],
+    spacing: [f64; 2],
+    policy: ConflictPolicy,
+    mut customize: impl FnMut([u32; 2], &mut Blueprint),
+  ) -> Result<Blueprint, StampError> {
+    let mut result = Blueprint::simple(label, Vec::new(), chunk.game_version());
+    for row in 0..rows {
+      for column in 0..columns {
+        let mut copy = chunk.clone();
+        customize([column, row], &mut copy);
+        let offset = [column as f64 * spacing[0], row as f64 * spacing[1]];
+        result.stamp_with_policy(&copy, offset, Rotation::None, policy)?;
+      }
+    }
+    Ok(result)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The top left corners of the tiles under an entity.
+  fn tiles_under(entity: &Entity) -> Vec<[i64; 2]> {
+    let [[left, right], [top, bottom]] = entity_footprint(entity);
+    let (left, right, top, bottom) = (
+      left.round() as i64,
+      right.round() as i64,
+      top.round() as i64,
+      bottom.round() as i64,
+    );
+    (top..bottom)
+      .flat_map(|y| (left..right).map(move |x| [x, y]))
+      .collect()
+  }
+
+  #[test]
+  fn tiles_stay_under_their_entities() {
+    let entities = vec![
+      Entity {
+        name: "assembling-machine-2".to_string(),
+        position: Position { x: 1.5, y: 1.5 },
+        ..Default::default()
+      },
+      Entity {
+        name: "iron-chest".to_string(),
+        position: Position { x: 4.5, y: 0.5 },
+        ..Default::default()
+      },
+      Entity {
+        name: "splitter".to_string(),
+        position: Position { x: 4.0, y: 2.5 },
+        direction: Some(0),
+        ..Default::default()
+      },
+    ];
+    let mut chunk = Blueprint::simple("floored".to_string(), entities, GameVersion::default());
+    chunk.tiles = chunk
+      .entities
+      .iter()
+      .flat_map(tiles_under)
+      .map(|[x, y]| Tile {
+        name: "concrete".to_string(),
+        position: Position {
+          x: x as f64,
+          y: y as f64,
+        },
+      })
+      .collect();
+
+    for turns in 0..4 {
+      let mut result = Blueprint::simple("stamped".to_string(), Vec::new(), GameVersion::default());
+      result
+        .stamp(&chunk, [10.0, -20.0], Rotation::from_quarter_turns(turns))
+        .unwrap();
+      let floor: HashSet<[i64; 2]> = result
+        .tiles
+        .iter()
+        .map(|tile| tile_key(&tile.position))
+        .collect();
+      assert_eq!(floor.len(), chunk.tiles.len());
+      for entity in &result.entities {
+        for tile in tiles_under(entity) {
+          assert!(
+            floor.contains(&tile),
+            "{} turned {} times has no floor at {:?}",
+            entity.name,
+            turns,
+            tile
+          );
+        }
+      }
+    }
+  }
+}
@@ -0,0 +1,728 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::blueprint::*;
+use super::prototypes::*;
+
+/// (ingredient, amount) pairs
+pub type Ingredients = &'static [(&'static str, f64)];
+
+/// (product, how many one craft makes, ingredients). Normal mode recipes.
+/// Anything without a recipe here counts as a raw resource.
+pub const RECIPES: &[(&str, f64, Ingredients)] = &[
+  ("iron-plate", 1.0, &[("iron-ore", 1.0)]),
+  ("copper-plate", 1.0, &[("copper-ore", 1.0)]),
+  ("steel-plate", 1.0, &[("iron-plate", 5.0)]),
+  ("stone-brick", 1.0, &[("stone", 2.0)]),
+  (
+    "plastic-bar",
+    2.0,
+    &[("petroleum-gas", 20.0), ("coal", 1.0)],
+  ),
+  ("sulfur", 2.0, &[("water", 30.0), ("petroleum-gas", 30.0)]),
+  (
+    "sulfuric-acid",
+    50.0,
+    &[("sulfur", 5.0), ("iron-plate", 1.0), ("water", 100.0)],
+  ),
+  ("lubricant", 10.0, &[("heavy-oil", 10.0)]),
+  ("iron-gear-wheel", 1.0, &[("iron-plate", 2.0)]),
+  ("iron-stick", 2.0, &[("iron-plate", 1.0)]),
+  ("copper-cable", 2.0, &[("copper-plate", 1.0)]),
+  ("pipe", 1.0, &[("iron-plate", 1.0)]),
+  (
+    "electronic-circuit",
+    1.0,
+    &[("iron-plate", 1.0), ("copper-cable", 3.0)],
+  ),
+  (
+    "advanced-circuit",
+    1.0,
+    &[
+      ("plastic-bar", 2.0),
+      ("copper-cable", 4.0),
+      ("electronic-circuit", 2.0),
+    ],
+  ),
+  (
+    "processing-unit",
+    1.0,
+    &[
+      ("electronic-circuit", 20.0),
+      ("advanced-circuit", 2.0),
+      ("sulfuric-acid", 5.0),
+    ],
+  ),
+  (
+    "engine-unit",
+    1.0,
+    &[
+      ("steel-plate", 1.0),
+      ("iron-gear-wheel", 1.0),
+      ("pipe", 2.0),
+    ],
+  ),
+  (
+    "battery",
+    1.0,
+    &[
+      ("iron-plate", 1.0),
+      ("copper-plate", 1.0),
+      ("sulfuric-acid", 20.0),
+    ],
+  ),
+  (
+    "concrete",
+    10.0,
+    &[("stone-brick", 5.0), ("iron-ore", 1.0), ("water", 100.0)],
+  ),
+  ("hazard-concrete", 10.0, &[("concrete", 10.0)]),
+  (
+    "refined-concrete",
+    10.0,
+    &[
+      ("concrete", 20.0),
+      ("iron-stick", 8.0),
+      ("steel-plate", 1.0),
+      ("water", 100.0),
+    ],
+  ),
+  (
+    "refined-hazard-concrete",
+    10.0,
+    &[("refined-concrete", 10.0)],
+  ),
+  (
+    "transport-belt",
+    2.0,
+    &[("iron-plate", 1.0), ("iron-gear-wheel", 1.0)],
+  ),
+  (
+    "fast-transport-belt",
+    1.0,
+    &[("iron-gear-wheel", 5.0), ("transport-belt", 1.0)],
+  ),
+  (
+    "express-transport-belt",
+    1.0,
+    &[
+      ("iron-gear-wheel", 10.0),
+      ("fast-transport-belt", 1.0),
+      ("lubricant", 20.0),
+    ],
+  ),
+  (
+    "underground-belt",
+    2.0,
+    &[("iron-plate", 10.0), ("transport-belt", 5.0)],
+  ),
+  (
+    "fast-underground-belt",
+    2.0,
+    &[("iron-gear-wheel", 40.0), ("underground-belt", 2.0)],
+  ),
+  (
+    "express-underground-belt",
+    2.0,
+    &[
+      ("iron-gear-wheel", 80.0),
+      ("fast-underground-belt", 2.0),
+      ("lubricant", 40.0),
+    ],
+  ),
+  (
+    "splitter",
+    1.0,
+    &[
+      ("electronic-circuit", 5.0),
+      ("iron-plate", 5.0),
+      ("transport-belt", 4.0),
+    ],
+  ),
+  (
+    "fast-splitter",
+    1.0,
+    &[
+      ("iron-gear-wheel", 10.0),
+      ("electronic-circuit", 10.0),
+      ("splitter", 1.0),
+    ],
+  ),
+  (
+    "express-splitter",
+    1.0,
+    &[
+      ("iron-gear-wheel", 10.0),
+      ("advanced-circuit", 10.0),
+      ("fast-splitter", 1.0),
+      ("lubricant", 80.0),
+    ],
+  ),
+  (
+    "burner-inserter",
+    1.0,
+    &[("iron-plate", 1.0), ("iron-gear-wheel", 1.0)],
+  ),
+  (
+    "inserter",
+    1.0,
+    &[
+      ("electronic-circuit", 1.0),
+      ("iron-gear-wheel", 1.0),
+      ("iron-plate", 1.0),
+    ],
+  ),
+  (
+    "long-handed-inserter",
+    1.0,
+    &[
+      ("iron-gear-wheel", 1.0),
+      ("iron-plate", 1.0),
+      ("inserter", 1.0),
+    ],
+  ),
+  (
+    "fast-inserter",
+    1.0,
+    &[
+      ("electronic-circuit", 2.0),
+      ("iron-plate", 2.0),
+      ("inserter", 1.0),
+    ],
+  ),
+  (
+    "filter-inserter",
+    1.0,
+    &[("electronic-circuit", 4.0), ("fast-inserter", 1.0)],
+  ),
+  (
+    "stack-inserter",
+    1.0,
+    &[
+      ("iron-gear-wheel", 15.0),
+      ("electronic-circuit", 15.0),
+      ("advanced-circuit", 1.0),
+      ("fast-inserter", 1.0),
+    ],
+  ),
+  (
+    "bulk-inserter",
+    1.0,
+    &[
+      ("iron-gear-wheel", 15.0),
+      ("electronic-circuit", 15.0),
+      ("advanced-circuit", 1.0),
+      ("fast-inserter", 1.0),
+    ],
+  ),
+  (
+    "stack-filter-inserter",
+    1.0,
+    &[("electronic-circuit", 5.0), ("stack-inserter", 1.0)],
+  ),
+  ("wooden-chest", 1.0, &[("wood", 2.0)]),
+  ("iron-chest", 1.0, &[("iron-plate", 8.0)]),
+  ("steel-chest", 1.0, &[("steel-plate", 8.0)]),
+  ("logistic-chest-active-provider", 1.0, LOGISTIC_CHEST),
+  ("logistic-chest-passive-provider", 1.0, LOGISTIC_CHEST),
+  ("logistic-chest-storage", 1.0, LOGISTIC_CHEST),
+  ("logistic-chest-requester", 1.0, LOGISTIC_CHEST),
+  ("logistic-chest-buffer", 1.0, LOGISTIC_CHEST),
+  ("active-provider-chest", 1.0, LOGISTIC_CHEST),
+  ("passive-provider-chest", 1.0, LOGISTIC_CHEST),
+  ("storage-chest", 1.0, LOGISTIC_CHEST),
+  ("requester-chest", 1.0, LOGISTIC_CHEST),
+  ("buffer-chest", 1.0, LOGISTIC_CHEST),
+  (
+    "assembling-machine-1",
+    1.0,
+    &[
+      ("electronic-circuit", 3.0),
+      ("iron-gear-wheel", 5.0),
+      ("iron-plate", 9.0),
+    ],
+  ),
+  (
+    "assembling-machine-2",
+    1.0,
+    &[
+      ("steel-plate", 2.0),
+      ("electronic-circuit", 3.0),
+      ("iron-gear-wheel", 5.0),
+      ("assembling-machine-1", 1.0),
+    ],
+  ),
+  (
+    "assembling-machine-3",
+    1.0,
+    &[("speed-module", 4.0), ("assembling-machine-2", 2.0)],
+  ),
+  (
+    "chemical-plant",
+    1.0,
+    &[
+      ("steel-plate", 5.0),
+      ("electronic-circuit", 5.0),
+      ("iron-gear-wheel", 5.0),
+      ("pipe", 5.0),
+    ],
+  ),
+  (
+    "oil-refinery",
+    1.0,
+    &[
+      ("steel-plate", 15.0),
+      ("iron-gear-wheel", 10.0),
+      ("stone-brick", 10.0),
+      ("electronic-circuit", 10.0),
+      ("pipe", 10.0),
+    ],
+  ),
+  ("stone-furnace", 1.0, &[("stone", 5.0)]),
+  (
+    "steel-furnace",
+    1.0,
+    &[("steel-plate", 6.0), ("stone-brick", 10.0)],
+  ),
+  (
+    "electric-furnace",
+    1.0,
+    &[
+      ("steel-plate", 10.0),
+      ("advanced-circuit", 5.0),
+      ("stone-brick", 10.0),
+    ],
+  ),
+  (
+    "electric-mining-drill",
+    1.0,
+    &[
+      ("electronic-circuit", 3.0),
+      ("iron-gear-wheel", 5.0),
+      ("iron-plate", 10.0),
+    ],
+  ),
+  (
+    "lab",
+    1.0,
+    &[
+      ("electronic-circuit", 10.0),
+      ("iron-gear-wheel", 10.0),
+      ("transport-belt", 4.0),
+    ],
+  ),
+  (
+    "beacon",
+    1.0,
+    &[
+      ("electronic-circuit", 20.0),
+      ("advanced-circuit", 20.0),
+      ("steel-plate", 10.0),
+      ("copper-cable", 10.0),
+    ],
+  ),
+  ("speed-module", 1.0, MODULE_1),
+  ("productivity-module", 1.0, MODULE_1),
+  ("effectivity-module", 1.0, MODULE_1),
+  (
+    "speed-module-2",
+    1.0,
+    &[
+      ("speed-module", 4.0),
+      ("advanced-circuit", 5.0),
+      ("processing-unit", 5.0),
+    ],
+  ),
+  (
+    "productivity-module-2",
+    1.0,
+    &[
+      ("productivity-module", 4.0),
+      ("advanced-circuit", 5.0),
+      ("processing-unit", 5.0),
+    ],
+  ),
+  (
+    "effectivity-module-2",
+    1.0,
+    &[
+      ("effectivity-module", 4.0),
+      ("advanced-circuit", 5.0),
+      ("processing-unit", 5.0),
+    ],
+  ),
+  (
+    "speed-module-3",
+    1.0,
+    &[
+      ("speed-module-2", 5.0),
+      ("advanced-circuit", 5.0),
+      ("processing-unit", 5.0),
+    ],
+  ),
+  (
+    "productivity-module-3",
+    1.0,
+    &[
+      ("productivity-module-2", 5.0),
+      ("advanced-circuit", 5.0),
+      ("processing-unit", 5.0),
+    ],
+  ),
+  (
+    "effectivity-module-3",
+    1.0,
+    &[
+      ("effectivity-module-2", 5.0),
+      ("advanced-circuit", 5.0),
+      ("processing-unit", 5.0),
+    ],
+  ),
+  (
+    "small-electric-pole",
+    2.0,
+    &[("wood", 1.0), ("copper-cable", 2.0)],
+  ),
+  (
+    "medium-electric-pole",
+    1.0,
+    &[
+      ("copper-plate", 2.0),
+      ("steel-plate", 2.0),
+      ("iron-stick", 4.0),
+    ],
+  ),
+  (
+    "big-electric-pole",
+    1.0,
+    &[
+      ("copper-plate", 5.0),
+      ("steel-plate", 5.0),
+      ("iron-stick", 8.0),
+    ],
+  ),
+  (
+    "substation",
+    1.0,
+    &[
+      ("advanced-circuit", 5.0),
+      ("copper-plate", 5.0),
+      ("steel-plate", 10.0),
+    ],
+  ),
+  (
+    "solar-panel",
+    1.0,
+    &[
+      ("copper-plate", 5.0),
+      ("steel-plate", 15.0),
+      ("electronic-circuit", 15.0),
+    ],
+  ),
+  ("accumulator", 1.0, &[("iron-plate", 2.0), ("battery", 5.0)]),
+  (
+    "constant-combinator",
+    1.0,
+    &[("copper-cable", 5.0), ("electronic-circuit", 2.0)],
+  ),
+  (
+    "arithmetic-combinator",
+    1.0,
+    &[("copper-cable", 5.0), ("electronic-circuit", 5.0)],
+  ),
+  (
+    "decider-combinator",
+    1.0,
+    &[("copper-cable", 5.0), ("electronic-circuit", 5.0)],
+  ),
+  (
+    "small-lamp",
+    1.0,
+    &[
+      ("electronic-circuit", 1.0),
+      ("copper-cable", 3.0),
+      ("iron-plate", 1.0),
+    ],
+  ),
+  (
+    "pipe-to-ground",
+    2.0,
+    &[("pipe", 10.0), ("iron-plate", 5.0)],
+  ),
+  (
+    "storage-tank",
+    1.0,
+    &[("iron-plate", 20.0), ("steel-plate", 5.0)],
+  ),
+  (
+    "pump",
+    1.0,
+    &[("engine-unit", 1.0), ("steel-plate", 1.0), ("pipe", 1.0)],
+  ),
+  ("stone-wall", 1.0, &[("stone-brick", 5.0)]),
+  (
+    "rail",
+    2.0,
+    &[("stone", 1.0), ("iron-stick", 1.0), ("steel-plate", 1.0)],
+  ),
+  (
+    "rail-signal",
+    1.0,
+    &[("electronic-circuit", 1.0), ("iron-plate", 5.0)],
+  ),
+  (
+    "rail-chain-signal",
+    1.0,
+    &[("electronic-circuit", 1.0), ("iron-plate", 5.0)],
+  ),
+  (
+    "train-stop",
+    1.0,
+    &[
+      ("electronic-circuit", 5.0),
+      ("iron-plate", 6.0),
+      ("iron-stick", 6.0),
+      ("steel-plate", 3.0),
+    ],
+  ),
+  (
+    "locomotive",
+    1.0,
+    &[
+      ("engine-unit", 20.0),
+      ("electronic-circuit", 10.0),
+      ("steel-plate", 30.0),
+    ],
+  ),
+  (
+    "cargo-wagon",
+    1.0,
+    &[
+      ("iron-gear-wheel", 10.0),
+      ("iron-plate", 20.0),
+      ("steel-plate", 20.0),
+    ],
+  ),
+  (
+    "fluid-wagon",
+    1.0,
+    &[
+      ("iron-gear-wheel", 10.0),
+      ("steel-plate", 16.0),
+      ("pipe", 8.0),
+      ("storage-tank", 1.0),
+    ],
+  ),
+];
+
+const LOGISTIC_CHEST: Ingredients = &[
+  ("steel-chest", 1.0),
+  ("electronic-circuit", 3.0),
+  ("advanced-circuit", 1.0),
+];
+const MODULE_1: Ingredients = &[("advanced-circuit", 5.0), ("electronic-circuit", 5.0)];
+
+pub fn find_recipe(product: &str) -> Option<(f64, Ingredients)> {
+  RECIPES
+    .iter()
+    .find(|(name, _, _)| *name == product)
+    .map(|&(_, count, ingredients)| (count, ingredients))
+}
+
+/// The item used to place a tile; most tiles share their item's name, but the hazard
+/// patterns have a left and right variant.
+pub fn tile_item(tile_name: &str) -> &str {
+  match tile_name {
+    "stone-path" => "stone-brick",
+    "hazard-concrete-left" | "hazard-concrete-right" => "hazard-concrete",
+    "refined-hazard-concrete-left" | "refined-hazard-concrete-right" => "refined-hazard-concrete",
+    other => other,
+  }
+}
+
+/// The item used to place an entity, and how many of it; rails are placed with rail items,
+/// four of them for a curve.
+pub fn entity_item(entity_name: &str) -> (&str, u32) {
+  match entity_name {
+    "straight-rail" => ("rail", 1),
+    "curved-rail" => ("rail", 4),
+    other => (other, 1),
+  }
+}
+
+/// Breaks items down into the raw resources needed to craft them, following `RECIPES`.
+pub fn raw_materials(items: &BTreeMap<String, u32>) -> BTreeMap<String, f64> {
+  fn add(result: &mut BTreeMap<String, f64>, item: &str, amount: f64, depth: usize) {
+    match find_recipe(item) {
+      // the depth limit is just in case someone adds a cyclic recipe to the table
+      Some((count, ingredients)) if depth < 32 => {
+        for &(ingredient, ingredient_amount) in ingredients {
+          add(
+            result,
+            ingredient,
+            amount * ingredient_amount / count,
+            depth + 1,
+          );
+        }
+      }
+      _ => *result.entry(item.to_string()).or_insert(0.0) += amount,
+    }
+  }
+  let mut result = BTreeMap::new();
+  for (item, &count) in items {
+    add(&mut result, item, count as f64, 0);
+  }
+  result
+}
+
+impl Blueprint {
+  /// How many of each item it takes to build the blueprint: the entities, the modules and
+  /// other items requested inside them, and the tiles.
+  pub fn bill_of_materials(&self) -> BTreeMap<String, u32> {
+    let mut result = BTreeMap::new();
+    for entity in &self.entities {
+      let (item, count) = entity_item(&entity.name);
+      *result.entry(item.to_string()).or_insert(0) += count;
+      for (item, &count) in &entity.items {
+        *result.entry(item.clone()).or_insert(0) += count;
+      }
+    }
+    for tile in &self.tiles {
+      *result.entry(tile_item(&tile.name).to_string()).or_insert(0) += 1;
+    }
+    result
+  }
+
+  /// `[[left, right], [top, bottom]]` around every entity and tile, or None if it's empty.
+  pub fn footprint(&self) -> Option<[[f64; 2]; 2]> {
+    let areas = self
+      .entities
+      .iter()
+      .map(entity_footprint)
+      .chain(self.tiles.iter().map(tile_footprint));
+    areas.fold(None, |bounds: Option<[[f64; 2]; 2]>, area| {
+      Some(match bounds {
+        None => area,
+        Some(bounds) => [
+          [bounds[0][0].min(area[0][0]), bounds[0][1].max(area[0][1])],
+          [bounds[1][0].min(area[1][0]), bounds[1][1].max(area[1][1])],
+        ],
+      })
+    })
+  }
+
+  pub fn category_counts(&self) -> BTreeMap<EntityCategory, u32> {
+    let mut result = BTreeMap::new();
+    for entity in &self.entities {
+      let category =
+        find_prototype(&entity.name).map_or(EntityCategory::Other, |prototype| prototype.category);
+      *result.entry(category).or_insert(0) += 1;
+    }
+    result
+  }
+
+  pub fn statistics(&self) -> BlueprintStatistics {
+    let materials = self.bill_of_materials();
+    BlueprintStatistics {
+      label: self.label.clone(),
+      blueprints: 1,
+      entities: self.entities.len() as u32,
+      footprint: self.footprint(),
+      categories: self.category_counts(),
+      raw_materials: raw_materials(&materials),
+      materials,
+    }
+  }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BlueprintStatistics {
+  pub label: String,
+  pub blueprints: u32,
+  pub entities: u32,
+  /// for totals over several blueprints, the largest width and height of any of them
+  pub footprint: Option<[[f64; 2]; 2]>,
+  pub categories: BTreeMap<EntityCategory, u32>,
+  pub materials: BTreeMap<String, u32>,
+  pub raw_materials: BTreeMap<String, f64>,
+}
+
+impl BlueprintStatistics {
+  pub fn size(&self) -> Option<[f64; 2]> {
+    self
+      .footprint
+      .map(|[[left, right], [top, bottom]]| [right - left, bottom - top])
+  }
+
+  pub fn total<'a>(
+    label: String,
+    parts: impl IntoIterator<Item = &'a BlueprintStatistics>,
+  ) -> BlueprintStatistics {
+    let mut result = BlueprintStatistics {
+      label,
+      ..Default::default()
+    };
+    for part in parts {
+      result.blueprints += part.blueprints;
+      result.entities += part.entities;
+      if let Some([width, height]) = part.size() {
+        let [old_width, old_height] = result.size().unwrap_or([0.0, 0.0]);
+        result.footprint = Some([[0.0, old_width.max(width)], [0.0, old_height.max(height)]]);
+      }
+      for (&category, &count) in &part.categories {
+        *result.categories.entry(category).or_insert(0) += count;
+      }
+      for (item, &count) in &part.materials {
+        *result.materials.entry(item.clone()).or_insert(0) += count;
+      }
+      for (item, &amount) in &part.raw_materials {
+        *result.raw_materials.entry(item.clone()).or_insert(0.0) += amount;
+      }
+    }
+    result
+  }
+}
+
+impl fmt::Display for BlueprintStatistics {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    write!(formatter, "{} ({} entities", self.label, self.entities)?;
+    if self.blueprints > 1 {
+      write!(formatter, " in {} blueprints", self.blueprints)?;
+    }
+    if let Some([width, height]) = self.size() {
+      write!(formatter, ", {}×{} tiles", width, height)?;
+    }
+    writeln!(formatter, ")")?;
+    writeln!(formatter, "  by category:")?;
+    for (category, count) in &self.categories {
+      writeln!(formatter, "    {:>8} {:?}", count, category)?;
+    }
+    writeln!(formatter, "  materials:")?;
+    for (item, count) in &self.materials {
+      writeln!(formatter, "    {:>8} {}", count, item)?;
+    }
+    writeln!(formatter, "  raw materials:")?;
+    for (item, amount) in &self.raw_materials {
+      writeln!(formatter, "    {:>8.1} {}", amount, item)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn floors_reach_from_their_top_left_corners() {
+    let mut blueprint = Blueprint::simple("floor".to_string(), Vec::new(), GameVersion::default());
+    blueprint.tiles = vec![[0.0, 0.0], [2.0, -1.0]]
+      .into_iter()
+      .map(|[x, y]| Tile {
+        name: "concrete".to_string(),
+        position: Position { x, y },
+      })
+      .collect();
+    assert_eq!(blueprint.footprint(), Some([[0.0, 3.0], [-1.0, 1.0]]));
+  }
+}
@@ -0,0 +1,205 @@
+use std::fmt::Write;
+
+use super::belt_routing::*;
+use super::simplified::*;
+
+/// Draws objects on a grid of characters, one tile per two columns so that the picture isn't
+/// squashed. Useful for looking at routing results from test output or a terminal.
+pub struct TextRenderer<'a> {
+  pub bounds: Rectangle,
+  /// obstructed tiles are shown as walls
+  pub map: Option<&'a RoutingMap>,
+  /// if given, the accumulated congestion costs are printed as a second grid below the first
+  pub search_map: Option<&'a RouteSearchMap>,
+  /// box drawing characters and arrows; otherwise plain ASCII
+  pub unicode: bool,
+}
+
+#[derive(Copy, Clone)]
+struct Cell {
+  glyph: char,
+  /// what goes in the gap between this tile and the one to its right
+  join: char,
+}
+
+const EMPTY: Cell = Cell {
+  glyph: ' ',
+  join: ' ',
+};
+
+impl Default for Cell {
+  fn default() -> Cell {
+    EMPTY
+  }
+}
+
+impl<'a> TextRenderer<'a> {
+  pub fn new(bounds: Rectangle) -> TextRenderer<'a> {
+    TextRenderer {
+      bounds,
+      map: None,
+      search_map: None,
+      unicode: true,
+    }
+  }
+
+  pub fn for_map(map: &'a RoutingMap) -> TextRenderer<'a> {
+    TextRenderer {
+      map: Some(map),
+      ..TextRenderer::new(map.bounds())
+    }
+  }
+
+  fn pick(&self, unicode: char, ascii: char) -> char {
+    if self.unicode {
+      unicode
+    } else {
+      ascii
+    }
+  }
+
+  fn arrow(&self, direction: u8) -> char {
+    let index = (direction / 2) as usize;
+    if self.unicode {
+      ['↑', '→', '↓', '←'][index]
+    } else {
+      ['^', '>', 'v', '<'][index]
+    }
+  }
+
+  fn inserter(&self, direction: u8) -> char {
+    // inserters face the tile they take from; draw the way items move
+    let index = ((direction + 4) % 8 / 2) as usize;
+    self.pick(['⇑', '⇒', '⇓', '⇐'][index], 'i')
+  }
+
+  fn underground_end(&self, direction: u8, entrance: bool) -> char {
+    let index = (direction / 2) as usize;
+    match (self.unicode, entrance) {
+      (true, true) => ['▲', '▶', '▼', '◀'][index],
+      (true, false) => ['△', '▷', '▽', '◁'][index],
+      (false, true) => ['n', 'e', 's', 'w'][index],
+      (false, false) => ['N', 'E', 'S', 'W'][index],
+    }
+  }
+
+  fn draw_object(&self, grid: &mut Grid<Cell>, object: &Object) {
+    let mut set = |coordinates: Coordinates, glyph: char| {
+      if let Some(cell) = grid.get_mut(coordinates) {
+        cell.glyph = glyph;
+      }
+    };
+    match object {
+      Object::Belt(belt) => set(belt.position(), self.arrow(belt.direction())),
+      Object::UndergroundBelt(underground) => {
+        let passage = if underground.horizontal() {
+          self.pick('┄', '-')
+        } else {
+          self.pick('┆', ':')
+        };
+        let floor = self.pick('·', '.');
+        for tile in underground.underground_tiles() {
+          if let Some(cell) = grid.get_mut(tile) {
+            if cell.glyph == ' ' || cell.glyph == floor {
+              cell.glyph = passage;
+            }
+          }
+        }
+        let direction = underground.direction();
+        if let Some(cell) = grid.get_mut(underground.entrance()) {
+          cell.glyph = self.underground_end(direction, true);
+        }
+        if let Some(cell) = grid.get_mut(underground.exit()) {
+          cell.glyph = self.underground_end(direction, false);
+        }
+      }
+      Object::Splitter(splitter) => {
+        set(splitter.left_part(), self.pick('╪', '='));
+        set(splitter.right_part(), self.pick('╪', '='));
+      }
+      Object::Inserter(inserter) => set(inserter.position(), self.inserter(inserter.direction())),
+      Object::Assembler(assembler) => {
+        let [[left, right], [top, bottom]] = assembler.shape().bounds;
+        for tile in assembler.shape().tiles() {
+          let [x, y] = tile;
+          let edge_row = y == top || y == bottom;
+          let glyph = match (x == left, x == right, y == top, y == bottom) {
+            (true, _, true, _) => self.pick('┌', '+'),
+            (_, true, true, _) => self.pick('┐', '+'),
+            (true, _, _, true) => self.pick('└', '+'),
+            (_, true, _, true) => self.pick('┘', '+'),
+            _ if edge_row => self.pick('─', '-'),
+            (true, _, _, _) | (_, true, _, _) => self.pick('│', '|'),
+            _ if tile == assembler.center => 'A',
+            _ => ' ',
+          };
+          if let Some(cell) = grid.get_mut(tile) {
+            cell.glyph = glyph;
+            cell.join = if x != right && edge_row {
+              self.pick('─', '-')
+            } else {
+              ' '
+            };
+          }
+        }
+      }
+      Object::Chest(chest) => set(chest.position, self.pick('▣', 'C')),
+      Object::Loader(loader) => {
+        set(loader.belt_part(), self.arrow(loader.direction()));
+        set(loader.container_part(), 'L');
+      }
+    }
+  }
+
+  pub fn render(&self, objects: &[Object]) -> String {
+    let mut grid: Grid<Cell> = Grid::new(self.bounds);
+    if let Some(map) = self.map {
+      let wall = self.pick('█', '#');
+      for (coordinates, cell) in grid.tiles_mut() {
+        if map.obstructed(coordinates) {
+          cell.glyph = wall;
+          if map.obstructed([coordinates[0] + 1, coordinates[1]]) {
+            cell.join = wall;
+          }
+        } else {
+          cell.glyph = self.pick('·', '.');
+        }
+      }
+    }
+    for object in objects {
+      self.draw_object(&mut grid, object);
+    }
+
+    let [[left, right], [top, bottom]] = self.bounds.bounds;
+    let mut result = String::new();
+    for y in top..=bottom {
+      let mut line = String::new();
+      for x in left..=right {
+        let cell = grid.get([x, y]).cloned().unwrap_or(EMPTY);
+        line.push(cell.glyph);
+        if x != right {
+          line.push(cell.join);
+        }
+      }
+      result.push_str(line.trim_end());
+      result.push('\n');
+    }
+
+    if let Some(search_map) = self.search_map {
+      result.push('\n');
+      for y in top..=bottom {
+        let mut line = String::new();
+        for x in left..=right {
+          match search_map.accumulated_cost([x, y]) {
+            Some(0) | None => line.push_str("   ."),
+            Some(cost) if cost > 999 => line.push_str(" 999"),
+            Some(cost) => write!(line, "{:>4}", cost).unwrap(),
+          }
+        }
+        result.push_str(line.trim_end());
+        result.push('\n');
+      }
+    }
+    result
+  }
+}
@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::blueprint::*;
+use super::prototypes::*;
+
+/// The belt family at each tier, from yellow to blue.
+const BELT_TIER_PREFIXES: [&str; 3] = ["", "fast-", "express-"];
+const BELT_FAMILY: [&str; 4] = ["transport-belt", "underground-belt", "splitter", "loader"];
+
+/// Replaces entity names, like an upgrade planner does in game.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct UpgradePlan {
+  pub mappings: BTreeMap<String, String>,
+}
+
+/// An underground belt pair that wouldn't reach any more after the plan replaced it.
+#[derive(Clone, Debug)]
+pub struct BrokenUnderground {
+  /// the name the pair has now, and the one it would get
+  pub from: String,
+  pub to: String,
+  pub entrance: Position,
+  pub distance: u8,
+  pub max_distance: u8,
+}
+
+impl fmt::Display for BrokenUnderground {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      formatter,
+      "{} at ({}, {}) reaches {} tiles, but {} only reaches {}",
+      self.from, self.entrance.x, self.entrance.y, self.distance, self.to, self.max_distance
+    )
+  }
+}
+
+impl std::error::Error for BrokenUnderground {}
+
+impl UpgradePlan {
+  pub fn new() -> UpgradePlan {
+    UpgradePlan::default()
+  }
+
+  pub fn with(mut self, from: &str, to: &str) -> UpgradePlan {
+    self.mappings.insert(from.to_string(), to.to_string());
+    self
+  }
+
+  /// Belts, undergrounds, splitters and loaders from one tier to another, where 1 is yellow, 2
+  /// red and 3 blue.
+  pub fn belt_tiers(from: usize, to: usize) -> UpgradePlan {
+    assert!(
+      (1..=3).contains(&from) && (1..=3).contains(&to),
+      "belt tiers go from 1 to 3"
+    );
+    let mut result = UpgradePlan::new();
+    for name in &BELT_FAMILY {
+      result = result.with(
+        &format!("{}{}", BELT_TIER_PREFIXES[from - 1], name),
+        &format!("{}{}", BELT_TIER_PREFIXES[to - 1], name),
+      );
+    }
+    result
+  }
+
+  /// The plan that undoes this one, as far as that's possible when several names map to one.
+  pub fn reversed(&self) -> UpgradePlan {
+    UpgradePlan {
+      mappings: self
+        .mappings
+        .iter()
+        .map(|(from, to)| (to.clone(), from.clone()))
+        .collect(),
+    }
+  }
+
+  /// Underground belts whose entrance and exit would be too far apart after replacing them.
+  pub fn check(&self, blueprint: &Blueprint) -> Vec<BrokenUnderground> {
+    let mut result = Vec::new();
+    for (entrance, exit) in underground_pairs(&blueprint.entities) {
+      let (entrance, exit) = (&blueprint.entities[entrance], &blueprint.entities[exit]);
+      let to = match self.mappings.get(&entrance.name) {
+        Some(to) => to,
+        None => continue,
+      };
+      let distance = ((exit.position.x - entrance.position.x).abs()
+        + (exit.position.y - entrance.position.y).abs())
+      .round() as u8;
+      // replacing an underground with something else entirely isn't our business
+      if let Some(max_distance) =
+        find_prototype(to).and_then(|prototype| prototype.max_underground_distance)
+      {
+        if distance > max_distance {
+          result.push(BrokenUnderground {
+            from: entrance.name.clone(),
+            to: to.clone(),
+            entrance: entrance.position.clone(),
+            distance,
+            max_distance,
+          });
+        }
+      }
+    }
+    result
+  }
+
+  /// Replaces names without checking anything, returning how many entities changed.
+  pub fn apply_unchecked(&self, object: &mut BlueprintObject) -> usize {
+    let mut count = 0;
+    object.visit_blueprints(|blueprint| {
+      for entity in &mut blueprint.entities {
+        if let Some(replacement) = self.mappings.get(&entity.name) {
+          entity.name = replacement.clone();
+          count += 1;
+        }
+      }
+    });
+    count
+  }
+
+  /// Replaces names throughout a blueprint or book, unless that would leave an underground belt
+  /// pair too far apart, in which case nothing is changed and the broken pairs are returned.
+  pub fn apply(&self, object: &mut BlueprintObject) -> Result<usize, Vec<BrokenUnderground>> {
+    let mut problems = Vec::new();
+    object.visit_blueprints(|blueprint| problems.extend(self.check(blueprint)));
+    if !problems.is_empty() {
+      return Err(problems);
+    }
+    Ok(self.apply_unchecked(object))
+  }
+
+  /// An upgrade planner item with one mapper per entity, for importing into the game.
+  pub fn to_planner(&self, label: String) -> UpgradePlanner {
+    let target = |name: &String| {
+      Some(UpgradeTarget {
+        name: name.clone(),
+        target_type: "entity".to_string(),
+      })
+    };
+    UpgradePlanner {
+      item: "upgrade-planner".to_string(),
+      label,
+      settings: UpgradeSettings {
+        mappers: self
+          .mappings
+          .iter()
+          .enumerate()
+          .map(|(index, (from, to))| UpgradeMapper {
+            from: target(from),
+            to: target(to),
+            index: index as u32,
+          })
+          .collect(),
+      },
+      version: GameVersion::default().packed(),
+    }
+  }
+
+  /// The entity mappers of a planner; item mappers (for modules) and half-filled ones are skipped.
+  pub fn from_planner(planner: &UpgradePlanner) -> UpgradePlan {
+    let entity = |target: &Option<UpgradeTarget>| {
+      target
+        .as_ref()
+        .filter(|target| target.target_type == "entity")
+        .map(|target| target.name.clone())
+    };
+    UpgradePlan {
+      mappings: planner
+        .settings
+        .mappers
+        .iter()
+        .filter_map(|mapper| Some((entity(&mapper.from)?, entity(&mapper.to)?)))
+        .collect(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// An express underground pair heading east, `distance` tiles from entrance to exit.
+  fn express_pair(y: f64, distance: f64) -> Vec<Entity> {
+    [
+      (0.5, UndergroundBeltOrLoaderType::Input),
+      (0.5 + distance, UndergroundBeltOrLoaderType::Output),
+    ]
+    .iter()
+    .map(|&(x, underground_type)| Entity {
+      name: "express-underground-belt".to_string(),
+      position: Position { x, y },
+      direction: Some(2),
+      underground_type: Some(underground_type),
+      ..Default::default()
+    })
+    .collect()
+  }
+
+  #[test]
+  fn downgrading_long_undergrounds_is_refused() {
+    let mut entities = express_pair(0.5, 9.0);
+    entities.extend(express_pair(2.5, 4.0));
+    let blueprint = Blueprint::simple("undergrounds".to_string(), entities, GameVersion::default());
+    let plan = UpgradePlan::belt_tiers(3, 1);
+
+    let broken = plan.check(&blueprint);
+    assert_eq!(broken.len(), 1);
+    assert_eq!(broken[0].to, "underground-belt");
+    assert_eq!((broken[0].distance, broken[0].max_distance), (9, 5));
+    assert_eq!(broken[0].entrance.y, 0.5);
+
+    let mut object = BlueprintObject::Blueprint(blueprint);
+    assert!(plan.apply(&mut object).is_err());
+    match object {
+      BlueprintObject::Blueprint(blueprint) => assert!(blueprint
+        .entities
+        .iter()
+        .all(|entity| entity.name == "express-underground-belt")),
+      _ => unreachable!(),
+    }
+  }
+}
@@ -0,0 +1,875 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use super::blueprint::*;
+
+/// A Factorio version, as packed into the `version` field of blueprints: four 16 bit numbers,
+/// major in the highest bits and build in the lowest.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct GameVersion {
+  pub major: u16,
+  pub minor: u16,
+  pub patch: u16,
+  pub build: u16,
+}
+
+impl GameVersion {
+  /// What this library has always written; every later version can import it.
+  pub const V0_16: GameVersion = GameVersion::new(0, 16, 51);
+  pub const V1_1: GameVersion = GameVersion::new(1, 1, 0);
+  /// 16-way directions and a blueprint-wide list of wires.
+  pub const V2_0: GameVersion = GameVersion::new(2, 0, 0);
+
+  pub const fn new(major: u16, minor: u16, patch: u16) -> GameVersion {
+    GameVersion {
+      major,
+      minor,
+      patch,
+      build: 0,
+    }
+  }
+
+  pub fn from_packed(packed: u64) -> GameVersion {
+    GameVersion {
+      major: (packed >> 48) as u16,
+      minor: (packed >> 32) as u16,
+      patch: (packed >> 16) as u16,
+      build: packed as u16,
+    }
+  }
+
+  pub fn packed(self) -> u64 {
+    (self.major as u64) << 48
+      | (self.minor as u64) << 32
+      | (self.patch as u64) << 16
+      | self.build as u64
+  }
+
+  /// Whether blueprints for this version use the 2.0 shapes: directions out of 16, and wires
+  /// listed for the whole blueprint rather than on each entity.
+  pub fn uses_2_0_format(self) -> bool {
+    self >= GameVersion::V2_0
+  }
+}
+
+impl Default for GameVersion {
+  fn default() -> GameVersion {
+    GameVersion::V0_16
+  }
+}
+
+impl fmt::Display for GameVersion {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    write!(formatter, "{}.{}.{}", self.major, self.minor, self.patch)?;
+    if self.build != 0 {
+      write!(formatter, ".{}", self.build)?;
+    }
+    Ok(())
+  }
+}
+
+impl FromStr for GameVersion {
+  type Err = String;
+
+  /// Parses versions like `1.1`, `1.1.110` or `2.0.28.1`.
+  fn from_str(text: &str) -> Result<GameVersion, String> {
+    let parts = text
+      .split('.')
+      .map(str::parse)
+      .collect::<Result<Vec<u16>, _>>()
+      .map_err(|error| format!("{}: {}", text, error))?;
+    match parts[..] {
+      [major, minor] => Ok(GameVersion::new(major, minor, 0)),
+      [major, minor, patch] => Ok(GameVersion::new(major, minor, patch)),
+      [major, minor, patch, build] => Ok(GameVersion {
+        major,
+        minor,
+        patch,
+        build,
+      }),
+      _ => Err(format!(
+        "{} should look like <major>.<minor>[.<patch>[.<build>]]",
+        text
+      )),
+    }
+  }
+}
+
+/// Entities that 2.0 gave new names, without changing what they do. 2.0 reuses the 1.1 rail names
+/// for its new rails, which go in 16 directions.
+pub const RENAMED_IN_2_0: &[(&str, &str)] = &[
+  ("logistic-chest-active-provider", "active-provider-chest"),
+  ("logistic-chest-passive-provider", "passive-provider-chest"),
+  ("logistic-chest-storage", "storage-chest"),
+  ("logistic-chest-requester", "requester-chest"),
+  ("logistic-chest-buffer", "buffer-chest"),
+  ("stack-inserter", "bulk-inserter"),
+  ("straight-rail", "legacy-straight-rail"),
+  ("curved-rail", "legacy-curved-rail"),
+];
+
+/// What an entity or item called `name` in the other format is called in `version`.
+pub fn name_for_version(name: &str, version: GameVersion) -> &str {
+  let renamed = RENAMED_IN_2_0.iter().find_map(|&(old, new)| {
+    if version.uses_2_0_format() && name == old {
+      Some(new)
+    } else if !version.uses_2_0_format() && name == new {
+      Some(old)
+    } else {
+      None
+    }
+  });
+  renamed.unwrap_or(name)
+}
+
+/// The 2.0 number for one end of a 1.1 wire: 1 and 2 are the red and green wires on a
+/// combinator's input (or the only connector on most entities), 3 and 4 those on its output.
+fn wire_connector(circuit_id: u8, color: WireColor) -> u8 {
+  let output = if circuit_id == 2 { 2 } else { 0 };
+  match color {
+    WireColor::Red => 1 + output,
+    WireColor::Green => 2 + output,
+  }
+}
+
+/// The 1.1 circuit connector and wire color for a 2.0 connector, if it's a circuit wire at all
+/// (5 and up are copper cables, which we don't model).
+fn circuit_connector(connector: i32) -> Option<(u8, WireColor)> {
+  match connector {
+    1 => Some((1, WireColor::Red)),
+    2 => Some((1, WireColor::Green)),
+    3 => Some((2, WireColor::Red)),
+    4 => Some((2, WireColor::Green)),
+    _ => None,
+  }
+}
+
+fn is_module(item: &str) -> bool {
+  item.contains("-module")
+}
+
+/// Which of an entity's inventories 2.0 puts `item` in: modules go in its module slots, whose
+/// number depends on the kind of entity, and anything else (fuel, ammunition, chest contents) in
+/// its first inventory.
+fn item_inventory(entity: &str, item: &str) -> u32 {
+  if !is_module(item) {
+    return 1;
+  }
+  match entity {
+    "beacon" => 1,
+    "electric-mining-drill" | "pumpjack" => 2,
+    "lab" => 3,
+    _ => 4,
+  }
+}
+
+/// How many of `item` fit in one of 2.0's inventory slots, for the fuel and ammunition that
+/// blueprints usually insert. Anything else is taken to stack to 50, the most common size.
+fn stack_size(item: &str) -> u32 {
+  match item {
+    "nuclear-fuel" | "artillery-shell" => 1,
+    "rocket-fuel" => 20,
+    "wood" | "flamethrower-ammo" | "rocket" | "explosive-rocket" => 100,
+    "firearm-magazine" | "piercing-rounds-magazine" | "uranium-rounds-magazine" => 200,
+    "cannon-shell"
+    | "explosive-cannon-shell"
+    | "uranium-cannon-shell"
+    | "explosive-uranium-cannon-shell" => 200,
+    _ => 50,
+  }
+}
+
+/// 2.0's list of where `items` go: modules one to a slot, and other items in as few full stacks
+/// as they fill, filling each inventory from its first slot.
+fn insert_plans(entity: &str, items: &HashMap<String, u32>) -> Vec<ItemInsertPlan> {
+  let mut names: Vec<&String> = items.keys().collect();
+  names.sort();
+  let mut next_stack: HashMap<u32, u32> = HashMap::new();
+  names
+    .into_iter()
+    .map(|name| {
+      let count = items[name];
+      let inventory = item_inventory(entity, name);
+      let stack = next_stack.entry(inventory).or_insert(0);
+      let in_inventory = if is_module(name) {
+        (0..count)
+          .map(|_| {
+            *stack += 1;
+            InventoryPosition {
+              inventory,
+              stack: *stack - 1,
+              count: None,
+            }
+          })
+          .collect()
+      } else {
+        let size = stack_size(name);
+        (0..count.div_ceil(size))
+          .map(|full_stacks| {
+            *stack += 1;
+            InventoryPosition {
+              inventory,
+              stack: *stack - 1,
+              count: Some(size.min(count - full_stacks * size)),
+            }
+          })
+          .collect()
+      };
+      ItemInsertPlan {
+        id: ItemID {
+          name: name.clone(),
+          quality: None,
+        },
+        items: ItemInventoryPositions {
+          in_inventory,
+          grid_count: None,
+        },
+      }
+    })
+    .collect()
+}
+
+impl DeciderConditions {
+  /// The 1.1 settings for a 2.0 decider, if it has no more than one condition and one output
+  /// (which 2.0 can set to a constant other than 1, and 1.1 can't).
+  pub(crate) fn from_parameters(
+    parameters: &DeciderCombinatorParameters,
+  ) -> Option<DeciderConditions> {
+    let condition = match &parameters.conditions[..] {
+      [] => None,
+      [condition] => Some(condition),
+      _ => return None,
+    };
+    let output = match &parameters.outputs[..] {
+      [] => None,
+      [output] => Some(output),
+      _ => return None,
+    };
+    if output.is_some_and(|output| {
+      output.copy_count_from_input == Some(false) && output.constant.unwrap_or(1) != 1
+    }) {
+      return None;
+    }
+    Some(DeciderConditions {
+      first_signal: condition.and_then(|condition| condition.first_signal.clone()),
+      second_signal: condition.and_then(|condition| condition.second_signal.clone()),
+      constant: condition.and_then(|condition| condition.constant),
+      comparator: condition.map_or(Comparator::Less, |condition| condition.comparator),
+      output_signal: output.map(|output| output.signal.clone()),
+      copy_count_from_input: Some(
+        output.is_none_or(|output| output.copy_count_from_input != Some(false)),
+      ),
+    })
+  }
+
+  pub(crate) fn parameters(&self) -> DeciderCombinatorParameters {
+    DeciderCombinatorParameters {
+      conditions: vec![DeciderCombinatorCondition {
+        first_signal: self.first_signal.clone(),
+        second_signal: self.second_signal.clone(),
+        constant: self.constant,
+        comparator: self.comparator,
+        compare_type: None,
+      }],
+      outputs: self
+        .output_signal
+        .iter()
+        .map(|signal| DeciderCombinatorOutput {
+          signal: signal.clone(),
+          copy_count_from_input: match self.copy_count_from_input {
+            Some(false) => Some(false),
+            _ => None,
+          },
+          constant: None,
+        })
+        .collect(),
+    }
+  }
+}
+
+impl Blueprint {
+  pub fn game_version(&self) -> GameVersion {
+    GameVersion::from_packed(self.version)
+  }
+
+  /// Rewrites a freshly decoded blueprint into the 1.1 shapes that the rest of the library uses.
+  /// Names are left alone; use `BlueprintObject::convert_to` to change those. 2.0 directions
+  /// between the 8 that 1.1 has (half-diagonal rails) are rounded down to one of them, and kept
+  /// to be written back out as they were while the entity still faces that way. Copper cables
+  /// stay in `wires`. Constant combinators get the outputs of their sections that are switched
+  /// on, and deciders get their 1.1 settings if they have any; those that don't keep their 2.0
+  /// settings in `other`. (2.0 item lists don't need anything here: they're read into the 1.1
+  /// shape as they're decoded.)
+  pub(crate) fn import_shapes(&mut self) {
+    for entity in &mut self.entities {
+      if let Some(behavior) = &mut entity.control_behavior {
+        match behavior.decider_settings.take() {
+          None => {}
+          Some(DeciderSettings::Conditions(conditions)) => {
+            behavior.decider_conditions = Some(conditions)
+          }
+          Some(DeciderSettings::Parameters(json)) => {
+            let conditions = serde_json::from_value(json.clone())
+              .ok()
+              .and_then(|parameters| DeciderConditions::from_parameters(&parameters));
+            match conditions {
+              Some(conditions) => behavior.decider_conditions = Some(conditions),
+              None => {
+                behavior
+                  .other
+                  .insert("decider_conditions".to_string(), json);
+              }
+            }
+          }
+        }
+      }
+    }
+    if !self.game_version().uses_2_0_format() {
+      return;
+    }
+    for entity in &mut self.entities {
+      entity.direction_16 = entity.direction.filter(|direction| direction % 2 == 1);
+      entity.direction = entity.direction.map(|direction| direction / 2);
+      if let Some(behavior) = &mut entity.control_behavior {
+        if let Some(sections) = behavior.sections.take() {
+          let signals = sections
+            .sections
+            .into_iter()
+            .filter(|section| section.active != Some(false))
+            .flat_map(|section| section.filters)
+            .map(|filter| (filter.signal, filter.count));
+          behavior.filters = signals
+            .enumerate()
+            .map(|(index, (signal, count))| ConstantCombinatorFilter {
+              signal,
+              count,
+              index: index as u32 + 1,
+            })
+            .collect();
+        }
+      }
+    }
+    let wires = std::mem::take(&mut self.wires);
+    let numbers: BTreeSet<i32> = self
+      .entities
+      .iter()
+      .map(|entity| entity.entity_number)
+      .collect();
+    for wire in wires {
+      let [first, first_connector, second, second_connector] = wire;
+      let ends = (
+        circuit_connector(first_connector),
+        circuit_connector(second_connector),
+      );
+      match ends {
+        (Some((first_circuit, color)), Some((second_circuit, second_color)))
+          if color == second_color && numbers.contains(&first) && numbers.contains(&second) =>
+        {
+          self.connect(color, (first, first_circuit), (second, second_circuit))
+        }
+        _ => self.wires.push(wire),
+      }
+    }
+  }
+
+  /// Rewrites this blueprint into the shapes that its version expects. Everything else in this
+  /// library works with the 1.1 shapes, so this only happens on the way out.
+  pub(crate) fn export_shapes(&mut self) {
+    if !self.game_version().uses_2_0_format() {
+      return;
+    }
+    let mut wires: BTreeSet<[i32; 4]> = self.wires.drain(..).collect();
+    for entity in &mut self.entities {
+      entity.direction = match entity.direction_16 {
+        Some(direction) if entity.direction == Some(direction / 2) => Some(direction),
+        _ => entity.direction.map(|direction| direction * 2),
+      };
+      for (&circuit_id, connection) in &entity.connections {
+        for &color in &[WireColor::Red, WireColor::Green] {
+          for data in connection.wires(color) {
+            let first = [
+              entity.entity_number,
+              wire_connector(circuit_id, color) as i32,
+            ];
+            let second = [
+              data.entity_id,
+              wire_connector(data.circuit_id.unwrap_or(1), color) as i32,
+            ];
+            // each wire is listed on both of its ends; keep one of them
+            let (first, second) = if first <= second {
+              (first, second)
+            } else {
+              (second, first)
+            };
+            wires.insert([first[0], first[1], second[0], second[1]]);
+          }
+        }
+      }
+      entity.connections.clear();
+      entity.insert_plans = insert_plans(&entity.name, &entity.items);
+      entity.items.clear();
+      if let Some(behavior) = &mut entity.control_behavior {
+        if !behavior.filters.is_empty() {
+          let filters = behavior
+            .filters
+            .drain(..)
+            .map(|filter| LogisticSectionFilter {
+              index: filter.index,
+              signal: filter.signal,
+              quality: Some("normal".to_string()),
+              comparator: Some("=".to_string()),
+              count: filter.count,
+            })
+            .collect();
+          behavior.sections = Some(LogisticSections {
+            sections: vec![LogisticSection {
+              index: 1,
+              filters,
+              active: None,
+            }],
+          });
+        }
+        behavior.decider_parameters = behavior
+          .decider_conditions
+          .take()
+          .map(|conditions| conditions.parameters());
+      }
+    }
+    self.wires = wires.into_iter().collect();
+    for schedule in &mut self.schedules {
+      schedule.nested = true;
+    }
+  }
+}
+
+impl BlueprintBook {
+  pub fn game_version(&self) -> GameVersion {
+    GameVersion::from_packed(self.version)
+  }
+}
+
+impl BlueprintObject {
+  /// Prepares a blueprint, book or upgrade planner for importing into `version`: renames
+  /// entities and icons that have different names there, drops qualities if it doesn't have
+  /// them, and marks everything with the version so that it's encoded in the right format.
+  /// Names are only changed in what's made for the other format.
+  pub fn convert_to(&mut self, version: GameVersion) {
+    let rename = |name: &mut String, from: GameVersion| {
+      if from.uses_2_0_format() == version.uses_2_0_format() {
+        return;
+      }
+      let renamed = name_for_version(name, version);
+      if renamed != name {
+        *name = renamed.to_string();
+      }
+    };
+    self.visit_blueprints(|blueprint| {
+      let from = blueprint.game_version();
+      for entity in &mut blueprint.entities {
+        rename(&mut entity.name, from);
+        if !version.uses_2_0_format() {
+          entity.quality = None;
+        }
+      }
+      for icon in &mut blueprint.icons {
+        rename(&mut icon.signal.name, from);
+      }
+    });
+    if let BlueprintObject::UpgradePlanner(planner) = self {
+      let from = GameVersion::from_packed(planner.version);
+      for mapper in &mut planner.settings.mappers {
+        for target in mapper.from.iter_mut().chain(mapper.to.iter_mut()) {
+          rename(&mut target.name, from);
+        }
+      }
+    }
+    self.set_game_version(version);
+  }
+
+  /// Marks everything as made for `version`, which decides the shapes it's encoded with.
+  pub fn set_game_version(&mut self, version: GameVersion) {
+    match self {
+      BlueprintObject::Blueprint(blueprint) => blueprint.version = version.packed(),
+      BlueprintObject::BlueprintBook(book) => {
+        book.version = version.packed();
+        for entry in &mut book.blueprints {
+          entry.blueprint.version = version.packed();
+        }
+      }
+      BlueprintObject::UpgradePlanner(planner) => planner.version = version.packed(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::combinators::Combinator;
+
+  #[test]
+  fn rails_become_legacy_rails_in_2_0() {
+    let mut object = BlueprintObject::Blueprint(Blueprint::simple(
+      "rails".to_string(),
+      vec![
+        Entity {
+          entity_number: 1,
+          name: "straight-rail".to_string(),
+          position: Position { x: 1.0, y: 1.0 },
+          direction: Some(1),
+          ..Default::default()
+        },
+        Entity {
+          entity_number: 2,
+          name: "curved-rail".to_string(),
+          position: Position { x: 4.0, y: 6.0 },
+          direction: Some(3),
+          ..Default::default()
+        },
+      ],
+      GameVersion::V1_1,
+    ));
+    object.convert_to(GameVersion::V2_0);
+    let exported: serde_json::Value =
+      serde_json::from_str(&object.to_json_pretty().unwrap()).unwrap();
+    let entities = &exported["blueprint"]["entities"];
+    assert_eq!(entities[0]["name"], "legacy-straight-rail");
+    assert_eq!(entities[0]["direction"], 2);
+    assert_eq!(entities[1]["name"], "legacy-curved-rail");
+    assert_eq!(entities[1]["direction"], 6);
+
+    let mut reimported = BlueprintObject::from_json(&exported.to_string()).unwrap();
+    reimported.convert_to(GameVersion::V1_1);
+    match reimported {
+      BlueprintObject::Blueprint(blueprint) => {
+        let rails: Vec<(&str, Option<u8>)> = blueprint
+          .entities
+          .iter()
+          .map(|entity| (entity.name.as_str(), entity.direction))
+          .collect();
+        assert_eq!(
+          rails,
+          [("straight-rail", Some(1)), ("curved-rail", Some(3))]
+        );
+      }
+      _ => panic!("expected a blueprint"),
+    }
+  }
+
+  #[test]
+  fn rails_new_in_2_0_survive_2_0() {
+    let entities = serde_json::json!([
+      {"entity_number": 1, "name": "straight-rail", "position": {"x": 1, "y": 1}, "direction": 2},
+      {"entity_number": 2, "name": "half-diagonal-rail", "position": {"x": 4, "y": 4},
+        "direction": 3},
+      {"entity_number": 3, "name": "rail-signal", "position": {"x": 5.5, "y": 2.5},
+        "direction": 11}
+    ]);
+    let json = serde_json::json!({"blueprint": {
+      "item": "blueprint",
+      "label": "rails",
+      "version": 562949954076673u64,
+      "entities": entities
+    }});
+    let mut object = BlueprintObject::from_json(&json.to_string()).unwrap();
+    object.convert_to(GameVersion::V2_0);
+    let exported: serde_json::Value =
+      serde_json::from_str(&object.to_json_pretty().unwrap()).unwrap();
+    let exported: Vec<(&serde_json::Value, &serde_json::Value)> = exported["blueprint"]["entities"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|entity| (&entity["name"], &entity["direction"]))
+      .collect();
+    let expected: Vec<(&serde_json::Value, &serde_json::Value)> = entities
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|entity| (&entity["name"], &entity["direction"]))
+      .collect();
+    assert_eq!(exported, expected);
+  }
+
+  #[test]
+  fn items_survive_2_0() {
+    let json = r#"{"blueprint": {
+      "item": "blueprint",
+      "label": "modules",
+      "version": 562949954076673,
+      "entities": [{
+        "entity_number": 1,
+        "name": "assembling-machine-2",
+        "position": {"x": 0.5, "y": 0.5},
+        "items": [
+          {"id": {"name": "speed-module", "quality": "normal"},
+            "items": {"in_inventory": [{"inventory": 4, "stack": 0}, {"inventory": 4, "stack": 1}]}},
+          {"id": {"name": "coal"}, "items": {"in_inventory": [{"inventory": 1, "stack": 0, "count": 5}]}}
+        ]
+      }]
+    }}"#;
+    let object = BlueprintObject::from_json(json).unwrap();
+    let blueprint = match &object {
+      BlueprintObject::Blueprint(blueprint) => blueprint,
+      _ => panic!("expected a blueprint"),
+    };
+    let items = &blueprint.entities[0].items;
+    assert_eq!(items.get("speed-module"), Some(&2));
+    assert_eq!(items.get("coal"), Some(&5));
+
+    let exported: serde_json::Value =
+      serde_json::from_str(&object.to_json_pretty().unwrap()).unwrap();
+    let plans = &exported["blueprint"]["entities"][0]["items"];
+    assert_eq!(
+      plans,
+      &serde_json::json!([
+        {"id": {"name": "coal"}, "items": {"in_inventory": [{"inventory": 1, "stack": 0, "count": 5}]}},
+        {"id": {"name": "speed-module"},
+          "items": {"in_inventory": [{"inventory": 4, "stack": 0}, {"inventory": 4, "stack": 1}]}}
+      ])
+    );
+    let reimported = BlueprintObject::from_json(&exported.to_string()).unwrap();
+    match reimported {
+      BlueprintObject::Blueprint(blueprint) => assert_eq!(&blueprint.entities[0].items, items),
+      _ => panic!("expected a blueprint"),
+    }
+  }
+
+  #[test]
+  fn items_fill_stacks_in_2_0() {
+    let mut object = BlueprintObject::Blueprint(Blueprint::simple(
+      "boiler".to_string(),
+      vec![Entity {
+        entity_number: 1,
+        name: "boiler".to_string(),
+        position: Position { x: 1.5, y: 1.0 },
+        items: vec![("coal".to_string(), 120), ("rocket-fuel".to_string(), 20)]
+          .into_iter()
+          .collect(),
+        ..Default::default()
+      }],
+      GameVersion::V1_1,
+    ));
+    object.convert_to(GameVersion::V2_0);
+    let exported: serde_json::Value =
+      serde_json::from_str(&object.to_json_pretty().unwrap()).unwrap();
+    // coal stacks to 50, and rocket fuel to 20
+    assert_eq!(
+      exported["blueprint"]["entities"][0]["items"],
+      serde_json::json!([
+        {"id": {"name": "coal"}, "items": {"in_inventory": [
+          {"inventory": 1, "stack": 0, "count": 50},
+          {"inventory": 1, "stack": 1, "count": 50},
+          {"inventory": 1, "stack": 2, "count": 20}
+        ]}},
+        {"id": {"name": "rocket-fuel"}, "items": {"in_inventory": [
+          {"inventory": 1, "stack": 3, "count": 20}
+        ]}}
+      ])
+    );
+
+    let reimported = BlueprintObject::from_json(&exported.to_string()).unwrap();
+    match reimported {
+      BlueprintObject::Blueprint(blueprint) => {
+        assert_eq!(blueprint.entities[0].items.get("coal"), Some(&120))
+      }
+      _ => panic!("expected a blueprint"),
+    }
+  }
+
+  #[test]
+  fn items_stay_counted_for_1_1() {
+    let mut object = BlueprintObject::from_json(
+      r#"{"blueprint": {"item": "blueprint", "label": "beacon", "version": 281479271677952, "entities": [{
+        "entity_number": 1, "name": "beacon", "position": {"x": 0.5, "y": 0.5},
+        "items": {"speed-module-3": 2}}]}}"#,
+    )
+    .unwrap();
+    let exported: serde_json::Value =
+      serde_json::from_str(&object.to_json_pretty().unwrap()).unwrap();
+    assert_eq!(
+      exported["blueprint"]["entities"][0]["items"],
+      serde_json::json!({"speed-module-3": 2})
+    );
+
+    object.convert_to(GameVersion::V2_0);
+    let exported: serde_json::Value =
+      serde_json::from_str(&object.to_json_pretty().unwrap()).unwrap();
+    assert_eq!(
+      exported["blueprint"]["entities"][0]["items"][0]["items"]["in_inventory"],
+      serde_json::json!([{"inventory": 1, "stack": 0}, {"inventory": 1, "stack": 1}])
+    );
+  }
+
+  #[test]
+  fn combinators_survive_2_0() {
+    let signal = SignalID::virtual_signal;
+    let entities = vec![
+      Entity {
+        name: "constant-combinator".to_string(),
+        control_behavior: Some(ControlBehavior {
+          filters: vec![
+            ConstantCombinatorFilter {
+              signal: signal("signal-A"),
+              count: 5,
+              index: 1,
+            },
+            ConstantCombinatorFilter {
+              signal: SignalID::item("iron-plate"),
+              count: -2,
+              index: 2,
+            },
+          ],
+          ..Default::default()
+        }),
+        ..Default::default()
+      },
+      Entity {
+        name: "decider-combinator".to_string(),
+        position: Position { x: 1.0, y: 0.5 },
+        control_behavior: Some(ControlBehavior {
+          decider_conditions: Some(DeciderConditions {
+            first_signal: Some(signal("signal-A")),
+            second_signal: None,
+            constant: Some(3),
+            comparator: Comparator::GreaterOrEqual,
+            output_signal: Some(signal("signal-B")),
+            copy_count_from_input: Some(false),
+          }),
+          ..Default::default()
+        }),
+        ..Default::default()
+      },
+    ];
+    let object = BlueprintObject::Blueprint(Blueprint::simple(
+      "combinators".to_string(),
+      entities,
+      GameVersion::V2_0,
+    ));
+
+    let exported: serde_json::Value =
+      serde_json::from_str(&object.to_json_pretty().unwrap()).unwrap();
+    let entities = &exported["blueprint"]["entities"];
+    assert_eq!(
+      entities[0]["control_behavior"],
+      serde_json::json!({"sections": {"sections": [{"index": 1, "filters": [
+        {"index": 1, "type": "virtual", "name": "signal-A", "quality": "normal", "comparator": "=",
+          "count": 5},
+        {"index": 2, "type": "item", "name": "iron-plate", "quality": "normal", "comparator": "=",
+          "count": -2}
+      ]}]}})
+    );
+    assert_eq!(
+      entities[1]["control_behavior"],
+      serde_json::json!({"decider_conditions": {
+        "conditions": [{"first_signal": {"type": "virtual", "name": "signal-A"}, "constant": 3,
+          "comparator": "\u{2265}"}],
+        "outputs": [{"signal": {"type": "virtual", "name": "signal-B"},
+          "copy_count_from_input": false}]
+      }})
+    );
+
+    let original = match &object {
+      BlueprintObject::Blueprint(blueprint) => blueprint,
+      _ => unreachable!(),
+    };
+    let reimported = match BlueprintObject::from_json(&exported.to_string()).unwrap() {
+      BlueprintObject::Blueprint(blueprint) => blueprint,
+      _ => panic!("expected a blueprint"),
+    };
+    for (first, second) in original.entities.iter().zip(&reimported.entities) {
+      assert_eq!(
+        Combinator::from_entity(first),
+        Combinator::from_entity(second)
+      );
+    }
+  }
+
+  #[test]
+  fn decoding_2_0_combinators() {
+    // as the game writes them: no type on item signals, and no comparator where it's `<`
+    let json = r#"{"blueprint": {
+      "item": "blueprint",
+      "label": "combinators",
+      "version": 562949954076673,
+      "entities": [
+        {"entity_number": 1, "name": "constant-combinator", "position": {"x": 0.5, "y": 0.5},
+          "control_behavior": {"sections": {"sections": [
+            {"index": 1, "filters": [{"index": 1, "name": "iron-plate", "quality": "normal",
+              "comparator": "=", "count": 7}]},
+            {"index": 2, "active": false, "filters": [{"index": 1, "name": "copper-plate",
+              "quality": "normal", "comparator": "=", "count": 1}]}
+          ]}}},
+        {"entity_number": 2, "name": "decider-combinator", "position": {"x": 1.5, "y": 1},
+          "control_behavior": {"decider_conditions": {
+            "conditions": [{"first_signal": {"name": "iron-plate"}, "constant": 10}],
+            "outputs": [{"signal": {"type": "virtual", "name": "signal-everything"}}]
+          }}},
+        {"entity_number": 3, "name": "decider-combinator", "position": {"x": 2.5, "y": 1},
+          "control_behavior": {"decider_conditions": {"conditions": [], "outputs": []}}}
+      ]
+    }}"#;
+    let blueprint = match BlueprintObject::from_json(json).unwrap() {
+      BlueprintObject::Blueprint(blueprint) => blueprint,
+      _ => panic!("expected a blueprint"),
+    };
+    let combinators: Vec<Option<Combinator>> = blueprint
+      .entities
+      .iter()
+      .map(Combinator::from_entity)
+      .collect();
+    assert_eq!(
+      combinators[0],
+      Some(Combinator::Constant(vec![(
+        SignalID::item("iron-plate"),
+        7
+      )]))
+    );
+    assert_eq!(
+      combinators[1],
+      Some(Combinator::Decider(DeciderConditions::new(
+        SignalID::item("iron-plate"),
+        Comparator::Less,
+        10,
+        SignalID::virtual_signal("signal-everything"),
+        true,
+      )))
+    );
+    assert!(combinators[2].is_some());
+  }
+
+  #[test]
+  fn deciders_with_several_outputs_survive_2_0() {
+    let decider = serde_json::json!({
+      "conditions": [
+        {"first_signal": {"name": "iron-plate"}, "constant": 10},
+        {"first_signal": {"name": "coal"}, "comparator": ">", "constant": 5, "compare_type": "or",
+          "first_signal_networks": {"red": false, "green": true}}
+      ],
+      "outputs": [{"signal": {"name": "iron-plate"}}, {"signal": {"name": "copper-plate"}}]
+    });
+    let json = serde_json::json!({"blueprint": {
+      "item": "blueprint",
+      "label": "decider",
+      "version": 562949954076673u64,
+      "entities": [{"entity_number": 1, "name": "decider-combinator",
+        "position": {"x": 0.5, "y": 1}, "control_behavior": {"decider_conditions": decider}}]
+    }});
+    let object = BlueprintObject::from_json(&json.to_string()).unwrap();
+    match &object {
+      BlueprintObject::Blueprint(blueprint) => {
+        let entity = &blueprint.entities[0];
+        assert_eq!(Combinator::from_entity(entity), None);
+        let behavior = entity.control_behavior.as_ref().unwrap();
+        assert_eq!(behavior.other.get("decider_conditions"), Some(&decider));
+      }
+      _ => panic!("expected a blueprint"),
+    }
+
+    let exported: serde_json::Value =
+      serde_json::from_str(&object.to_json_pretty().unwrap()).unwrap();
+    assert_eq!(
+      exported["blueprint"]["entities"][0]["control_behavior"],
+      serde_json::json!({"decider_conditions": decider})
+    );
+  }
+}
@@ -0,0 +1,294 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::fmt;
+
+use super::blueprint::*;
+use super::prototypes::*;
+use super::simplified::*;
+
+/// Objects that need to share a circuit network, like an inserter and the chest whose contents
+/// its condition reads.
+#[derive(Clone, Debug)]
+pub struct CircuitNetwork {
+  pub color: WireColor,
+  pub members: Vec<Object>,
+}
+
+#[derive(Clone, Debug)]
+pub enum WiringError {
+  /// a member of a network isn't in the blueprint
+  Missing(Object),
+  /// no chain of wires, even through poles, can join this entity to the rest of its network
+  Unreachable { name: String, position: [f64; 2] },
+}
+
+impl fmt::Display for WiringError {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      WiringError::Missing(object) => {
+        write!(formatter, "{:?} isn't in the blueprint to be wired", object)
+      }
+      WiringError::Unreachable { name, position } => write!(
+        formatter,
+        "{} at ({}, {}) is out of wire reach of the rest of its network",
+        name, position[0], position[1]
+      ),
+    }
+  }
+}
+
+impl std::error::Error for WiringError {}
+
+fn distance(first: &Entity, second: &Entity) -> f64 {
+  (first.position.x - second.position.x).hypot(first.position.y - second.position.y)
+}
+
+fn within_reach(first: &Entity, second: &Entity) -> bool {
+  distance(first, second) <= circuit_wire_reach(&first.name).min(circuit_wire_reach(&second.name))
+}
+
+/// The entity that `object` was rendered as.
+fn find_entity(entities: &[Entity], object: &Object) -> Option<usize> {
+  let rendered = object.render().into_iter().next()?;
+  entities.iter().position(|entity| {
+    entity.name == rendered.name
+      && (entity.position.x - rendered.position.x).abs() < 0.01
+      && (entity.position.y - rendered.position.y).abs() < 0.01
+  })
+}
+
+/// Wires up the members of each network with as few wires as it can, and then as little wire
+/// length, going through the blueprint's electric poles where members are too far apart to wire
+/// directly. A pole only ever carries one network of each color, since it would join any two
+/// into one. Returns how many wires were added.
+pub fn wire_networks(
+  blueprint: &mut Blueprint,
+  networks: &[CircuitNetwork],
+) -> Result<usize, WiringError> {
+  let mut used_poles = HashSet::new();
+  for (index, entity) in blueprint.entities.iter().enumerate() {
+    for &color in &[WireColor::Red, WireColor::Green] {
+      if entity
+        .connections
+        .values()
+        .any(|connection| !connection.wires(color).is_empty())
+      {
+        used_poles.insert((index, color));
+      }
+    }
+  }
+
+  let mut wires = Vec::new();
+  for network in networks {
+    let color = network.color;
+    let mut members = Vec::new();
+    for object in &network.members {
+      let index = find_entity(&blueprint.entities, object)
+        .ok_or_else(|| WiringError::Missing(object.clone()))?;
+      if !members.contains(&index) {
+        members.push(index);
+      }
+    }
+
+    // the members come first, followed by the poles the network may go through
+    let mut nodes = members.clone();
+    nodes.extend(
+      blueprint
+        .entities
+        .iter()
+        .enumerate()
+        .filter(|&(index, entity)| {
+          is_electric_pole(&entity.name)
+            && !used_poles.contains(&(index, color))
+            && !members.contains(&index)
+        })
+        .map(|(index, _)| index),
+    );
+    let entity = |node: usize| &blueprint.entities[nodes[node]];
+
+    // grow a tree from the first member, each time adding the path to whichever member is
+    // cheapest to reach from it
+    let mut in_tree = vec![false; nodes.len()];
+    if !nodes.is_empty() {
+      in_tree[0] = true;
+    }
+    while let Some(unreached) = (0..members.len()).find(|&member| !in_tree[member]) {
+      let mut best = vec![None; nodes.len()];
+      let mut previous = vec![None; nodes.len()];
+      let mut frontier = BinaryHeap::new();
+      for node in 0..nodes.len() {
+        if in_tree[node] {
+          best[node] = Some((0, 0));
+          frontier.push(Reverse((0, 0, node)));
+        }
+      }
+      let mut reached = None;
+      while let Some(Reverse((count, length, node))) = frontier.pop() {
+        if best[node] != Some((count, length)) {
+          continue;
+        }
+        if node < members.len() && !in_tree[node] {
+          reached = Some(node);
+          break;
+        }
+        for next in 0..nodes.len() {
+          if in_tree[next] || !within_reach(entity(node), entity(next)) {
+            continue;
+          }
+          let cost = (
+            count + 1,
+            length + (distance(entity(node), entity(next)) * 100.0).round() as u64,
+          );
+          if best[next].is_none_or(|existing| cost < existing) {
+            best[next] = Some(cost);
+            previous[next] = Some(node);
+            frontier.push(Reverse((cost.0, cost.1, next)));
+          }
+        }
+      }
+
+      let mut node = match reached {
+        Some(node) => node,
+        None => {
+          let entity = entity(unreached);
+          return Err(WiringError::Unreachable {
+            name: entity.name.clone(),
+            position: [entity.position.x, entity.position.y],
+          });
+        }
+      };
+      while !in_tree[node] {
+        in_tree[node] = true;
+        let from = previous[node].unwrap();
+        wires.push((color, nodes[from], nodes[node]));
+        node = from;
+      }
+    }
+
+    for (node, &index) in nodes.iter().enumerate().skip(members.len()) {
+      if in_tree[node] {
+        used_poles.insert((index, color));
+      }
+    }
+  }
+
+  for &(color, first, second) in &wires {
+    let first = blueprint.entities[first].entity_number;
+    let second = blueprint.entities[second].entity_number;
+    blueprint.connect(color, (first, 1), (second, 1));
+  }
+  Ok(wires.len())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::routing_problem::{load_problem, solve_problem};
+
+  fn chest(position: Coordinates) -> Object {
+    Object::Chest(Chest::new(position, ChestType::Steel))
+  }
+
+  /// Chests at each of `positions`, and a small pole halfway between the first two.
+  fn chests_and_pole(positions: &[Coordinates]) -> Blueprint {
+    let mut entities: Vec<Entity> = positions
+      .iter()
+      .flat_map(|&position| chest(position).render())
+      .collect();
+    entities.push(Entity {
+      name: "small-electric-pole".to_string(),
+      position: Position { x: 7.0, y: 0.0 },
+      ..Default::default()
+    });
+    Blueprint::simple("wiring".to_string(), entities, GameVersion::default())
+  }
+
+  /// The entity numbers that `entity` has wires of `color` to.
+  fn wired_to(entity: &Entity, color: WireColor) -> Vec<i32> {
+    entity
+      .connections
+      .values()
+      .flat_map(|connection| connection.wires(color).iter().map(|data| data.entity_id))
+      .collect()
+  }
+
+  #[test]
+  fn wires_go_through_poles_when_too_far_apart() {
+    let mut blueprint = chests_and_pole(&[[0, 0], [14, 0]]);
+    let network = CircuitNetwork {
+      color: WireColor::Red,
+      members: vec![chest([0, 0]), chest([14, 0])],
+    };
+    assert_eq!(wire_networks(&mut blueprint, &[network]).unwrap(), 2);
+    let pole = &blueprint.entities[2];
+    let mut ends = wired_to(pole, WireColor::Red);
+    ends.sort_unstable();
+    assert_eq!(ends, vec![1, 2]);
+    assert!(wired_to(pole, WireColor::Green).is_empty());
+  }
+
+  #[test]
+  fn poles_carry_one_network_of_each_color() {
+    let mut blueprint = chests_and_pole(&[[0, 0], [14, 0], [0, 2], [14, 2]]);
+    let network = |color: WireColor, y: i32| CircuitNetwork {
+      color,
+      members: vec![chest([0, y]), chest([14, y])],
+    };
+    let both_colors = [network(WireColor::Red, 0), network(WireColor::Green, 2)];
+    assert_eq!(
+      wire_networks(&mut blueprint.clone(), &both_colors).unwrap(),
+      4
+    );
+    match wire_networks(
+      &mut blueprint,
+      &[network(WireColor::Red, 0), network(WireColor::Red, 2)],
+    ) {
+      Err(WiringError::Unreachable { name, position }) => {
+        assert_eq!(name, "steel-chest");
+        assert_eq!(position[1], 2.0);
+      }
+      other => panic!(
+        "the second red network can't share the pole, but got {:?}",
+        other
+      ),
+    }
+  }
+
+  #[test]
+  fn limited_chest_problem_wires_its_inserter_to_the_chest() {
+    let problem = load_problem(concat!(
+      env!("CARGO_MANIFEST_DIR"),
+      "/problems/limited_chest.toml"
+    ))
+    .unwrap();
+    let blueprint = solve_problem(&problem).unwrap().blueprint;
+    let find = |name: &str, x: f64| {
+      blueprint
+        .entities
+        .iter()
+        .find(|entity| entity.name == name && entity.position.x == x && entity.position.y == 0.0)
+        .unwrap()
+    };
+    let inserter = find("inserter", 4.0);
+    let chest = find("steel-chest", 5.0);
+    assert_eq!(
+      wired_to(inserter, WireColor::Red),
+      vec![chest.entity_number]
+    );
+    assert_eq!(
+      wired_to(chest, WireColor::Red),
+      vec![inserter.entity_number]
+    );
+    let condition = inserter
+      .control_behavior
+      .as_ref()
+      .and_then(|behavior| behavior.circuit_condition.as_ref())
+      .unwrap();
+    assert_eq!(condition.constant, Some(100));
+    // and the belt still gets there
+    assert!(blueprint
+      .entities
+      .iter()
+      .any(|entity| entity.name.contains("transport-belt") && entity.position.x == 2.0));
+  }
+}